@@ -1,6 +1,9 @@
 use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use super::json::JsonValue;
 
 /// Structure to hold user authentication data
 #[derive(Clone, Debug)]
@@ -17,21 +20,109 @@ pub struct AuthToken {
     pub expires_at: u64, // Unix timestamp
 }
 
+/// Storage for login sessions. Implement this to keep sessions across restarts or to
+/// share them between server instances (e.g. in Redis or a file).
+pub trait SessionStore: Send + Sync {
+    fn insert(&self, token: &str, session: AuthToken);
+    fn get(&self, token: &str) -> Option<AuthToken>;
+    /// Returns whether a session was removed
+    fn remove(&self, token: &str) -> bool;
+    /// Keep only the sessions for which `keep` returns true
+    fn retain(&self, keep: &dyn Fn(&AuthToken) -> bool);
+}
+
+/// Default store keeping sessions in memory for the life of the process
+#[derive(Default)]
+pub struct InMemorySessionStore {
+    sessions: Mutex<HashMap<String, AuthToken>>,
+}
+
+impl InMemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for InMemorySessionStore {
+    fn insert(&self, token: &str, session: AuthToken) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.insert(token.to_string(), session);
+        }
+    }
+
+    fn get(&self, token: &str) -> Option<AuthToken> {
+        self.sessions.lock().ok()?.get(token).cloned()
+    }
+
+    fn remove(&self, token: &str) -> bool {
+        self.sessions.lock().is_ok_and(|mut sessions| sessions.remove(token).is_some())
+    }
+
+    fn retain(&self, keep: &dyn Fn(&AuthToken) -> bool) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.retain(|_, session| keep(session));
+        }
+    }
+}
+
+/// Fewest random hex characters a token may carry (64 bits)
+pub const MIN_TOKEN_ENTROPY_CHARS: usize = 16;
+
 /// Structure for managing authentication tokens
 pub struct TokenManager {
-    tokens: std::sync::Mutex<std::collections::HashMap<String, AuthToken>>,
+    store: Arc<dyn SessionStore>,
+    prefix: String, // non-secret marker such as "tok_", not counted toward entropy
+    entropy_chars: usize, // random hex characters after the prefix
+}
+
+impl Default for TokenManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TokenManager {
     pub fn new() -> Self {
         TokenManager {
-            tokens: std::sync::Mutex::new(std::collections::HashMap::new()),
+            store: Arc::new(InMemorySessionStore::new()),
+            prefix: String::new(),
+            entropy_chars: 32,
         }
     }
 
+    /// Issue tokens as `prefix` followed by at least `entropy_chars` random hex characters
+    /// (never fewer than MIN_TOKEN_ENTROPY_CHARS), e.g. "tok_3f9a..."
+    pub fn with_token_format(mut self, prefix: &str, entropy_chars: usize) -> Self {
+        self.prefix = prefix.to_string();
+        self.entropy_chars = entropy_chars.max(MIN_TOKEN_ENTROPY_CHARS);
+        self
+    }
+
+    pub fn token_prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn token_entropy_chars(&self) -> usize {
+        self.entropy_chars
+    }
+
+    /// Keep sessions in `store` instead of the default in-memory map
+    pub fn with_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn store(&self) -> Arc<dyn SessionStore> {
+        Arc::clone(&self.store)
+    }
+
     /// Generate a new token for a user
     pub fn generate_token(&self, username: &str) -> String {
-        let token = generate_token();
+        let mut bytes = vec![0u8; self.entropy_chars.div_ceil(2)];
+        fill_random(&mut bytes);
+        let mut random = hex_encode(&bytes);
+        random.truncate(self.entropy_chars);
+        let token = format!("{}{}", self.prefix, random);
         let expires_at = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -43,9 +134,7 @@ impl TokenManager {
             expires_at,
         };
         
-        if let Ok(mut tokens) = self.tokens.lock() {
-            tokens.insert(token.clone(), auth_token);
-        }
+        self.store.insert(&token, auth_token);
         token
     }
 
@@ -56,26 +145,32 @@ impl TokenManager {
             .unwrap()
             .as_secs();
 
-        if let Ok(mut tokens) = self.tokens.lock() {
-            if let Some(auth_token) = tokens.get(token) {
-                if auth_token.expires_at > current_time {
-                    return Some(auth_token.username.clone());
-                } else {
-                    // Token expired, remove it
-                    tokens.remove(token);
-                }
-            }
+        let auth_token = self.store.get(token)?;
+        if auth_token.expires_at > current_time {
+            Some(auth_token.username)
+        } else {
+            // Token expired, remove it
+            self.store.remove(token);
+            None
         }
-        None
     }
 
     /// Revoke a token (logout)
     pub fn revoke_token(&self, token: &str) -> bool {
-        if let Ok(mut tokens) = self.tokens.lock() {
-            tokens.remove(token).is_some()
-        } else {
-            false
-        }
+        self.store.remove(token)
+    }
+
+    /// Revoke every token issued to `username` (e.g. after a password change); returns how many
+    pub fn revoke_all_for_user(&self, username: &str) -> usize {
+        let revoked = std::cell::Cell::new(0);
+        self.store.retain(&|auth_token| {
+            let keep = auth_token.username != username;
+            if !keep {
+                revoked.set(revoked.get() + 1);
+            }
+            keep
+        });
+        revoked.get()
     }
 
     /// Clean up expired tokens
@@ -85,49 +180,254 @@ impl TokenManager {
             .unwrap()
             .as_secs();
 
-        if let Ok(mut tokens) = self.tokens.lock() {
-            tokens.retain(|_, auth_token| auth_token.expires_at > current_time);
+        self.store.retain(&|auth_token| auth_token.expires_at > current_time);
+    }
+}
+
+/// Settings for delivering the session token in a cookie for browser clients
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenCookie {
+    pub name: String,
+    pub secure: bool,
+    pub same_site: String, // "Strict", "Lax" or "None"
+}
+
+impl TokenCookie {
+    /// Set-Cookie value carrying a freshly issued token (lifetime matches the token's)
+    pub fn set_cookie(&self, token: &str) -> String {
+        self.build(token, 3600)
+    }
+
+    /// Set-Cookie value that removes the cookie from the browser
+    pub fn clear_cookie(&self) -> String {
+        self.build("", 0)
+    }
+
+    fn build(&self, value: &str, max_age: u64) -> String {
+        let mut cookie = format!("{}={}; Path=/; Max-Age={}; HttpOnly", self.name, value, max_age);
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        if !self.same_site.is_empty() {
+            cookie.push_str(&format!("; SameSite={}", self.same_site));
         }
+        cookie
     }
 }
 
-/// Generate a random token
-pub fn generate_token() -> String {
-    let time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos() as u64;
-    
-    let mut hasher = DefaultHasher::new();
-    time.hash(&mut hasher);
-    
-    // Add some additional entropy
-    for i in 0..16 {
-        (time.wrapping_mul(31).wrapping_add(i)).hash(&mut hasher);
+/// Password strength requirements enforced when users register
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            require_uppercase: false,
+            require_lowercase: false,
+            require_digit: false,
+            require_symbol: false,
+        }
     }
-    
-    let token_hash = hasher.finish();
-    format!("{:016x}{:016x}", token_hash, time)
+}
+
+impl PasswordPolicy {
+    /// Check a password against the policy, describing every unmet requirement
+    pub fn validate(&self, password: &str) -> Result<(), String> {
+        let mut problems = Vec::new();
+        if password.chars().count() < self.min_length {
+            problems.push(format!("be at least {} characters long", self.min_length));
+        }
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            problems.push("contain an uppercase letter".to_string());
+        }
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            problems.push("contain a lowercase letter".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            problems.push("contain a digit".to_string());
+        }
+        if self.require_symbol && !password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace()) {
+            problems.push("contain a symbol".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Password must {}", problems.join(", ")))
+        }
+    }
+}
+
+/// Failed login attempts recorded for one username. Times are Unix timestamps
+/// (seconds) so the state stays meaningful when persisted across restarts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoginAttempts {
+    pub count: u32,
+    pub window_start: u64,
+    pub locked_until: Option<u64>,
+}
+
+/// Storage for failed-login state. Implement this to persist lockouts (e.g. to a
+/// file or a shared database) or to share them between server instances.
+pub trait LoginAttemptStore: Send + Sync {
+    fn load(&self, key: &str) -> Option<LoginAttempts>;
+    fn save(&self, key: &str, attempts: &LoginAttempts);
+    fn clear(&self, key: &str);
+}
+
+/// Default store keeping failed-login state in memory for the life of the process
+#[derive(Default)]
+pub struct InMemoryLoginAttemptStore {
+    attempts: Mutex<HashMap<String, LoginAttempts>>,
+}
+
+impl InMemoryLoginAttemptStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl LoginAttemptStore for InMemoryLoginAttemptStore {
+    fn load(&self, key: &str) -> Option<LoginAttempts> {
+        self.attempts.lock().ok()?.get(key).cloned()
+    }
+
+    fn save(&self, key: &str, attempts: &LoginAttempts) {
+        if let Ok(mut map) = self.attempts.lock() {
+            map.insert(key.to_string(), attempts.clone());
+        }
+    }
+
+    fn clear(&self, key: &str) {
+        if let Ok(mut map) = self.attempts.lock() {
+            map.remove(key);
+        }
+    }
+}
+
+/// Brute-force protection for the login endpoint: after `max_failures` failed
+/// attempts within `window`, further attempts are refused until `lockout` elapses
+pub struct LoginRateLimiter {
+    store: Arc<dyn LoginAttemptStore>,
+    update_lock: Mutex<()>, // serializes load-modify-save sequences against the store
+    max_failures: u32, // 0 disables the limiter
+    window: Duration,
+    lockout: Duration,
+}
+
+impl LoginRateLimiter {
+    pub fn new(max_failures: u32, window: Duration, lockout: Duration) -> Self {
+        LoginRateLimiter {
+            store: Arc::new(InMemoryLoginAttemptStore::new()),
+            update_lock: Mutex::new(()),
+            max_failures,
+            window,
+            lockout,
+        }
+    }
+
+    /// Keep failed-login state in the given store instead of in memory
+    pub fn with_store(mut self, store: Arc<dyn LoginAttemptStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn store(&self) -> Arc<dyn LoginAttemptStore> {
+        Arc::clone(&self.store)
+    }
+
+    pub fn max_failures(&self) -> u32 {
+        self.max_failures
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn lockout(&self) -> Duration {
+        self.lockout
+    }
+
+    /// Return how long the caller must wait if `key` is currently locked out
+    pub fn check(&self, key: &str) -> Option<Duration> {
+        let now = unix_now();
+        let _guard = self.update_lock.lock().ok()?;
+        let locked_until = self.store.load(key)?.locked_until?;
+        if locked_until > now {
+            return Some(Duration::from_secs(locked_until - now));
+        }
+        // Lockout expired; start over with a clean slate
+        self.store.clear(key);
+        None
+    }
+
+    /// Record a failed attempt, locking the key out once the threshold is reached
+    pub fn record_failure(&self, key: &str) {
+        if self.max_failures == 0 {
+            return;
+        }
+        let now = unix_now();
+        let Ok(_guard) = self.update_lock.lock() else {
+            return;
+        };
+        let mut attempts = self.store.load(key).unwrap_or(LoginAttempts {
+            count: 0,
+            window_start: now,
+            locked_until: None,
+        });
+        if now.saturating_sub(attempts.window_start) > self.window.as_secs() {
+            attempts.count = 0;
+            attempts.window_start = now;
+        }
+        attempts.count += 1;
+        if attempts.count >= self.max_failures {
+            attempts.locked_until = Some(now + self.lockout.as_secs());
+        }
+        self.store.save(key, &attempts);
+    }
+
+    /// Forget earlier failures after a successful login
+    pub fn record_success(&self, key: &str) {
+        if let Ok(_guard) = self.update_lock.lock() {
+            self.store.clear(key);
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Fill `buf` from the operating system's CSPRNG, through the same ring provider the TLS
+/// listener uses
+fn fill_random(buf: &mut [u8]) {
+    rustls::crypto::ring::default_provider()
+        .secure_random
+        .fill(buf)
+        .expect("secure random number generator failed");
+}
+
+/// Generate a random token: 32 hex characters (128 bits) from a CSPRNG
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 16];
+    fill_random(&mut bytes);
+    hex_encode(&bytes)
 }
 
 /// Generate a random salt for password hashing
 pub fn generate_salt() -> [u8; 16] {
     let mut salt = [0u8; 16];
-    // Use current time and a simple counter for pseudo-randomness
-    let time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos() as u64;
-    
-    // Add a static counter to ensure uniqueness even for rapid calls
-    use std::sync::atomic::{AtomicU64, Ordering};
-    static COUNTER: AtomicU64 = AtomicU64::new(0);
-    let counter = COUNTER.fetch_add(1, Ordering::SeqCst);
-    
-    // Fill salt with time-based and counter-based pseudo-random values
-    for (i, byte) in salt.iter_mut().enumerate() {
-        *byte = ((time.wrapping_mul(31).wrapping_add(counter).wrapping_add(i as u64)) % 256) as u8;
-    }
+    fill_random(&mut salt);
     salt
 }
 
@@ -146,20 +446,29 @@ pub fn hash_password(password: &str, salt: &[u8]) -> String {
 
 /// Verify a password against a stored hash
 pub fn verify_password(password: &str, stored_hash: &str) -> bool {
-    if let Some((salt_hex, hash_hex)) = stored_hash.split_once(':') {
-        if let Ok(salt) = hex_decode(salt_hex) {
-            let mut hasher = DefaultHasher::new();
-            salt.hash(&mut hasher);
-            password.hash(&mut hasher);
-            let actual_hash = hasher.finish();
-            let actual_hash_hex = format!("{:016x}", actual_hash);
-            
-            return actual_hash_hex == hash_hex;
-        }
+    if let Some((salt_hex, hash_hex)) = stored_hash.split_once(':')
+        && let Ok(salt) = hex_decode(salt_hex)
+    {
+        let mut hasher = DefaultHasher::new();
+        salt.hash(&mut hasher);
+        password.hash(&mut hasher);
+        let actual_hash = hasher.finish();
+        let actual_hash_hex = format!("{:016x}", actual_hash);
+
+        return constant_time_eq(actual_hash_hex.as_bytes(), hash_hex.as_bytes());
     }
     false
 }
 
+/// Compare two secrets without returning early at the first differing byte, so the time
+/// taken does not reveal how much of a guess was right. Only the length can leak.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
 /// Helper function to encode bytes as hex string
 pub fn hex_encode(bytes: &[u8]) -> String {
     bytes.iter()
@@ -169,7 +478,7 @@ pub fn hex_encode(bytes: &[u8]) -> String {
 
 /// Helper function to decode hex string to bytes
 pub fn hex_decode(hex_str: &str) -> Result<Vec<u8>, &'static str> {
-    if hex_str.len() % 2 != 0 {
+    if !hex_str.len().is_multiple_of(2) {
         return Err("Invalid hex string length");
     }
     
@@ -182,43 +491,49 @@ pub fn hex_decode(hex_str: &str) -> Result<Vec<u8>, &'static str> {
     Ok(result)
 }
 
-/// Simple JSON parsing for login requests (no external dependencies)
-pub fn parse_login_request(json_body: &str) -> Option<(String, String)> {
-    // Very simple JSON parsing - looks for "username" and "password" fields
-    let mut username = None;
-    let mut password = None;
-    
-    // Remove whitespace and braces
-    let cleaned = json_body.trim().trim_start_matches('{').trim_end_matches('}');
-    
-    // Split by commas and parse each field
-    for field in cleaned.split(',') {
-        let field = field.trim();
-        if let Some(colon_pos) = field.find(':') {
-            let key = field[..colon_pos].trim().trim_matches('"');
-            let value = field[colon_pos + 1..].trim().trim_matches('"');
-            
-            match key {
-                "username" => username = Some(value.to_string()),
-                "password" => password = Some(value.to_string()),
-                _ => {}
-            }
-        }
-    }
-    
-    if let (Some(u), Some(p)) = (username, password) {
-        Some((u, p))
-    } else {
-        None
+/// Split an Authorization header value into (scheme, credentials) on the first space.
+/// Returns None for an empty header or a scheme without credentials (e.g. "Basic").
+pub fn parse_authorization(header: &str) -> Option<(&str, &str)> {
+    let (scheme, credentials) = header.trim().split_once(' ')?;
+    let credentials = credentials.trim();
+    if scheme.is_empty() || credentials.is_empty() {
+        return None;
     }
+    Some((scheme, credentials))
+}
+
+/// Extract the "username" and "password" string fields from a JSON login body
+pub fn parse_login_request(json_body: &str) -> Option<(String, String)> {
+    let body = JsonValue::parse(json_body).ok()?;
+    let username = body.get("username")?.as_str()?;
+    let password = body.get("password")?.as_str()?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Parse a password change request body: {"current_password": "...", "new_password": "..."}
+pub fn parse_change_password_request(json_body: &str) -> Option<(String, String)> {
+    let body = JsonValue::parse(json_body).ok()?;
+    let current = body.get("current_password")?.as_str()?;
+    let new = body.get("new_password")?.as_str()?;
+    Some((current.to_string(), new.to_string()))
+}
+
+/// JSON body for a successful login: {"success": true, "token": "..."}
+pub fn login_json(token: &str) -> JsonValue {
+    JsonValue::object([("success", true.into()), ("token", token.into())])
+}
+
+/// JSON body for a failed auth request: {"success": false, "error": "..."}
+pub fn error_json(message: &str) -> JsonValue {
+    JsonValue::object([("success", false.into()), ("error", message.into())])
 }
 
 /// Generate JSON response for successful login
 pub fn create_login_response(token: &str) -> String {
-    format!(r#"{{"success": true, "token": "{}"}}"#, token)
+    login_json(token).to_string()
 }
 
 /// Generate JSON response for errors
 pub fn create_error_response(message: &str) -> String {
-    format!(r#"{{"success": false, "error": "{}"}}"#, message)
+    error_json(message).to_string()
 }
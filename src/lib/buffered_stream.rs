@@ -1,33 +1,158 @@
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
 use std::io::{self, Read, Write};
+use std::fmt;
+use std::time::Duration;
+use super::RequestParseError;
 
-pub struct BufferedStream {
-    stream: TcpStream,
+// Responses up to this size skip the write buffer and go straight to the socket
+const DIRECT_WRITE_THRESHOLD: usize = 4096;
+
+// Returned (wrapped in an io::Error) when a request line or header line exceeds the limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineTooLong {
+    pub request_line: bool, // true for the request line, false for a header line
+}
+
+impl fmt::Display for LineTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.request_line {
+            write!(f, "Request line too long")
+        } else {
+            write!(f, "Header line too long")
+        }
+    }
+}
+
+impl std::error::Error for LineTooLong {}
+
+impl From<LineTooLong> for RequestParseError {
+    fn from(error: LineTooLong) -> Self {
+        if error.request_line {
+            RequestParseError::RequestLineTooLong
+        } else {
+            RequestParseError::HeaderTooLarge
+        }
+    }
+}
+
+impl LineTooLong {
+    // Recover the line-length error from an io::Error returned by BufferedStream
+    pub fn from_io_error(error: &io::Error) -> Option<LineTooLong> {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<LineTooLong>()).copied()
+    }
+}
+
+// A stream that can be half-closed and given a read timeout, as a lingering close needs.
+// Implemented for plain TCP here and for TLS sessions in the tls module.
+pub trait Connection: Read + Write {
+    fn shutdown_write(&mut self) -> io::Result<()>;
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn shutdown_write(&mut self) -> io::Result<()> {
+        self.shutdown(Shutdown::Write)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+pub struct BufferedStream<S = TcpStream> {
+    stream: S,
     read_buffer: Vec<u8>,
     write_buffer: Vec<u8>,
     read_pos: usize,
     read_end: usize,
+    max_line_length: usize, // 0 means unlimited
+    max_head_size: usize, // request line plus headers, 0 means unlimited
+    max_body_size: usize, // declared or decoded request body, 0 means unlimited
+    spool_threshold: usize, // Content-Length bodies larger than this are spooled, 0 means never
+    max_spooled_body_size: usize, // limit for spooled bodies instead of max_body_size, 0 means unlimited
 }
 
-impl BufferedStream {
-    pub fn new(stream: TcpStream, buffer_size: usize) -> Self {
+impl<S: Read + Write> BufferedStream<S> {
+    pub fn new(stream: S, buffer_size: usize) -> Self {
         BufferedStream {
             stream,
             read_buffer: vec![0; buffer_size],
             write_buffer: Vec::with_capacity(buffer_size),
             read_pos: 0,
             read_end: 0,
+            max_line_length: 0,
+            max_head_size: 0,
+            max_body_size: 0,
+            spool_threshold: 0,
+            max_spooled_body_size: 0,
         }
     }
 
+    // Reject lines longer than `max_line_length` bytes (0 disables the limit)
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    // Reject request heads (request line and headers) over `max_head_size` bytes with
+    // RequestParseError::HeaderSectionTooLarge (0 disables the limit)
+    pub fn with_max_head_size(mut self, max_head_size: usize) -> Self {
+        self.max_head_size = max_head_size;
+        self
+    }
+
+    // Reject request bodies over `max_body_size` bytes with RequestParseError::PayloadTooLarge
+    // (0 disables the limit). A Content-Length is checked with the head, before any of the
+    // body is read; a chunked body is checked as it is decoded.
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    // Bodies with a Content-Length over `threshold` bytes are spooled to disk by the caller
+    // (see spools_body), so they are held to `max_size` rather than the in-memory body limit
+    // (0 disables the limit). A threshold of 0 never spools.
+    pub fn with_spooling(mut self, threshold: usize, max_size: usize) -> Self {
+        self.spool_threshold = threshold;
+        self.max_spooled_body_size = max_size;
+        self
+    }
+
+    // Whether a body of `content_length` bytes goes to disk rather than into memory
+    pub fn spools_body(&self, content_length: usize) -> bool {
+        self.spool_threshold > 0 && content_length > self.spool_threshold
+    }
+
+    // Whether bytes the client already sent are waiting in the read buffer, i.e. the next
+    // request was pipelined behind the previous one
+    pub fn has_buffered_input(&self) -> bool {
+        self.read_pos < self.read_end
+    }
+
     pub fn read_line(&mut self) -> Result<String, io::Error> {
-        let mut line = String::new();
+        let line = self.read_raw_line(false, None)?;
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        Ok(String::from_utf8_lossy(line).into_owned())
+    }
+
+    // Read a single line including its terminating LF (if any), leaving the bytes untouched.
+    // Fails with LineTooLong once the line grows past the limit without a newline, or with
+    // HeaderSectionTooLarge once it grows past `head_budget` bytes.
+    fn read_raw_line(&mut self, request_line: bool, head_budget: Option<usize>) -> Result<Vec<u8>, io::Error> {
+        let mut line = Vec::new();
         
         loop {
-            // If we need more data in the buffer
+            // If we need more data in the buffer. A line may be split across any number
+            // of reads (even between CR and LF); the partial line carries over.
             if self.read_pos >= self.read_end {
                 self.read_pos = 0;
-                self.read_end = self.stream.read(&mut self.read_buffer)?;
+                self.read_end = 0;
+                self.read_end = match self.stream.read(&mut self.read_buffer) {
+                    Ok(n) => n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
                 
                 if self.read_end == 0 {
                     break; // EOF
@@ -38,11 +163,19 @@ impl BufferedStream {
             while self.read_pos < self.read_end {
                 let byte = self.read_buffer[self.read_pos];
                 self.read_pos += 1;
+                line.push(byte);
 
                 if byte == b'\n' {
                     return Ok(line);
-                } else if byte != b'\r' {
-                    line.push(byte as char);
+                }
+                // The limit is on the line's content; a CR that may be the start of its
+                // CRLF does not count yet
+                let content_len = line.len() - usize::from(byte == b'\r');
+                if self.max_line_length > 0 && content_len > self.max_line_length {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, LineTooLong { request_line }));
+                }
+                if head_budget.is_some_and(|budget| line.len() > budget) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, RequestParseError::HeaderSectionTooLarge));
                 }
             }
         }
@@ -54,67 +187,216 @@ impl BufferedStream {
         }
     }
 
-    pub fn read_request(&mut self) -> Result<String, io::Error> {
-        let mut request = String::new();
-        let mut content_length = 0;
+    // Read the request head and body as raw bytes; line endings are preserved so the
+    // parser can decide how strictly to treat them
+    // A chunked body is returned decoded, after the head.
+    pub fn read_request(&mut self) -> Result<Vec<u8>, io::Error> {
+        let (mut request, content_length, chunked) = self.read_head_framing()?;
+        if chunked {
+            request.extend_from_slice(&self.read_chunked_body()?);
+        } else {
+            request.extend_from_slice(&self.read_body(content_length)?);
+        }
+        Ok(request)
+    }
+
+    // Read only the request line and headers (through the blank line), returning them
+    // with the declared Content-Length. The body stays unread until read_body or discard_body.
+    // With Transfer-Encoding: chunked the Content-Length is ignored (RFC 7230 3.3.3) and 0 is
+    // returned; the body is then read with read_chunked_body.
+    pub fn read_head(&mut self) -> Result<(Vec<u8>, usize), io::Error> {
+        let (head, content_length, chunked) = self.read_head_framing()?;
+        Ok((head, if chunked { 0 } else { content_length }))
+    }
+
+    // The head, its Content-Length and whether the body is chunked
+    fn read_head_framing(&mut self) -> Result<(Vec<u8>, usize, bool), io::Error> {
+        let mut head = Vec::new();
+        let mut content_length = None;
+        // Whether the final transfer coding is chunked, once a Transfer-Encoding is seen
+        let mut chunked = None;
 
-        // Read headers first
         loop {
-            let line = self.read_line()?;
-            
-            if line.is_empty() {
+            // What is left of the head size limit for this line
+            let head_budget = (self.max_head_size > 0).then(|| self.max_head_size.saturating_sub(head.len()));
+            let line = match self.read_raw_line(head.is_empty(), head_budget) {
+                Ok(line) => line,
+                // A client that half-closes after its last header line never sends the blank
+                // line; EOF then ends the header section and the parser decides whether to
+                // accept it (tolerant mode does, strict mode answers 400)
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && !head.is_empty() => break,
+                Err(e) => return Err(e),
+            };
+            head.extend_from_slice(&line);
+
+            if line == b"\r\n" || line == b"\n" {
                 break;
             }
 
             // Check for Content-Length header
-            if line.to_lowercase().starts_with("content-length:") {
-                if let Some(length_str) = line.split(':').nth(1) {
-                    content_length = length_str.trim().parse().unwrap_or(0);
+            let line = String::from_utf8_lossy(&line);
+            let lower = line.to_lowercase();
+            if let Some(value) = lower.strip_prefix("content-length:") {
+                let length = parse_content_length(value)?;
+                if content_length.is_some_and(|previous| previous != length) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, RequestParseError::InvalidContentLength));
                 }
+                content_length = Some(length);
             }
+            // Chunked only when it is the final transfer coding; a later header line
+            // continues the list
+            if let Some(codings) = lower.strip_prefix("transfer-encoding:") {
+                chunked = Some(codings.split(',').next_back().is_some_and(|coding| coding.trim() == "chunked"));
+            }
+        }
 
-            request.push_str(&line);
-            request.push_str("\r\n");
+        // Without chunked last the body length cannot be determined (RFC 9112 6.3)
+        if chunked == Some(false) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, RequestParseError::InvalidTransferEncoding));
         }
+        let chunked = chunked.is_some();
 
-        request.push_str("\r\n");
-        
-        // Read body if Content-Length is specified
-        if content_length > 0 {
-            let mut body = vec![0; content_length];
-            let mut total_read = 0;
-            
-            while total_read < content_length {
-                // Use remaining buffer data first
-                let available_in_buffer = self.read_end - self.read_pos;
-                let to_copy = std::cmp::min(available_in_buffer, content_length - total_read);
-                
-                if to_copy > 0 {
-                    body[total_read..total_read + to_copy]
-                        .copy_from_slice(&self.read_buffer[self.read_pos..self.read_pos + to_copy]);
-                    self.read_pos += to_copy;
-                    total_read += to_copy;
-                }
-                
-                // If we need more data, read directly from stream
-                if total_read < content_length {
-                    let bytes_read = self.stream.read(&mut body[total_read..])?;
-                    if bytes_read == 0 {
-                        break; // EOF
-                    }
-                    total_read += bytes_read;
+        let content_length = content_length.unwrap_or(0);
+        let limit = if self.spools_body(content_length) { self.max_spooled_body_size } else { self.max_body_size };
+        if !chunked && limit > 0 && content_length > limit {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, RequestParseError::PayloadTooLarge));
+        }
+        Ok((head, content_length, chunked))
+    }
+
+    // Read and decode a chunked body (RFC 7230 4.1): a hex size line (extensions ignored),
+    // that many bytes and a CRLF per chunk, up to the zero-size chunk, then any trailer
+    // fields through the blank line. Trailer fields are discarded, but count against the head
+    // size limit. Malformed framing fails with InvalidData wrapping
+    // RequestParseError::InvalidChunkedBody, a body growing past the body size limit with
+    // PayloadTooLarge and a trailer section past the head size limit with HeaderSectionTooLarge.
+    pub fn read_chunked_body(&mut self) -> Result<Vec<u8>, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, RequestParseError::InvalidChunkedBody);
+        let mut body = Vec::new();
+
+        loop {
+            let size_line = self.read_line()?;
+            let size = size_line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size, 16).map_err(|_| invalid())?;
+            if size == 0 {
+                break;
+            }
+            if self.max_body_size > 0 && size > self.max_body_size - body.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, RequestParseError::PayloadTooLarge));
+            }
+            // Copied as it arrives, so a huge declared size costs nothing up front
+            if self.copy_body_to(size, &mut body)? < size {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "EOF in chunked body"));
+            }
+            if !self.read_line()?.is_empty() {
+                return Err(invalid());
+            }
+        }
+
+        let mut trailer_size = 0;
+        loop {
+            let trailer_budget = (self.max_head_size > 0).then(|| self.max_head_size.saturating_sub(trailer_size));
+            let line = self.read_raw_line(false, trailer_budget)?;
+            if line == b"\r\n" || line == b"\n" {
+                break;
+            }
+            trailer_size += line.len();
+        }
+        Ok(body)
+    }
+
+    // Read a body of `content_length` bytes; a short body is returned as-is at EOF
+    pub fn read_body(&mut self, content_length: usize) -> Result<Vec<u8>, io::Error> {
+        let mut body = vec![0; content_length];
+        let mut total_read = 0;
+
+        while total_read < content_length {
+            // Use remaining buffer data first
+            let available_in_buffer = self.read_end - self.read_pos;
+            let to_copy = std::cmp::min(available_in_buffer, content_length - total_read);
+
+            if to_copy > 0 {
+                body[total_read..total_read + to_copy]
+                    .copy_from_slice(&self.read_buffer[self.read_pos..self.read_pos + to_copy]);
+                self.read_pos += to_copy;
+                total_read += to_copy;
+            }
+
+            // If we need more data, read directly from stream
+            if total_read < content_length {
+                let bytes_read = match self.stream.read(&mut body[total_read..]) {
+                    Ok(n) => n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                if bytes_read == 0 {
+                    break; // EOF
                 }
+                total_read += bytes_read;
             }
-            
-            let body_str = String::from_utf8_lossy(&body[..total_read]);
-            request.push_str(&body_str);
         }
 
-        Ok(request)
+        body.truncate(total_read);
+        Ok(body)
+    }
+
+    // Copy a body of `content_length` bytes into `out` a buffer at a time instead of
+    // holding it in memory. Returns the number of bytes copied; short at EOF like read_body.
+    pub fn copy_body_to(&mut self, content_length: usize, out: &mut impl Write) -> Result<usize, io::Error> {
+        let mut remaining = content_length;
+
+        while remaining > 0 {
+            if self.read_pos >= self.read_end {
+                self.read_pos = 0;
+                self.read_end = 0;
+                self.read_end = match self.stream.read(&mut self.read_buffer) {
+                    Ok(0) => break, // EOF
+                    Ok(n) => n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+            }
+            let copied = std::cmp::min(self.read_end - self.read_pos, remaining);
+            out.write_all(&self.read_buffer[self.read_pos..self.read_pos + copied])?;
+            self.read_pos += copied;
+            remaining -= copied;
+        }
+
+        Ok(content_length - remaining)
+    }
+
+    // Skip a body of `content_length` bytes without keeping it, so the next request on a
+    // keep-alive connection starts at the right place
+    pub fn discard_body(&mut self, content_length: usize) -> Result<(), io::Error> {
+        let mut remaining = content_length;
+
+        while remaining > 0 {
+            if self.read_pos >= self.read_end {
+                self.read_pos = 0;
+                self.read_end = 0;
+                self.read_end = match self.stream.read(&mut self.read_buffer) {
+                    Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "EOF in request body")),
+                    Ok(n) => n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+            }
+            let skipped = std::cmp::min(self.read_end - self.read_pos, remaining);
+            self.read_pos += skipped;
+            remaining -= skipped;
+        }
+
+        Ok(())
     }
 
-    pub fn write_response(&mut self, response: &str) -> Result<(), io::Error> {
-        self.write_buffer.extend_from_slice(response.as_bytes());
+    pub fn write_response(&mut self, response: &[u8]) -> Result<(), io::Error> {
+        // Fast path: nothing is pending, so a small response can be written in one call
+        // without first copying it into the write buffer
+        if self.write_buffer.is_empty() && response.len() <= DIRECT_WRITE_THRESHOLD {
+            return self.stream.write_all(response);
+        }
+
+        self.write_buffer.extend_from_slice(response);
         
         // Flush if buffer is getting full (e.g., > 8KB)
         if self.write_buffer.len() > 8192 {
@@ -125,9 +407,46 @@ impl BufferedStream {
     }
 
     pub fn flush(&mut self) -> Result<(), io::Error> {
-        self.stream.write_all(&self.write_buffer)?;
+        if !self.write_buffer.is_empty() {
+            self.stream.write_all(&self.write_buffer)?;
+        }
         self.stream.flush()?;
         self.write_buffer.clear();
         Ok(())
     }
 }
+
+impl<S: Connection> BufferedStream<S> {
+    // Close after an error response: stop writing, then discard whatever the client is still
+    // sending so the kernel does not reset the connection before the response is read
+    pub fn close_lingering(&mut self, timeout: Duration) {
+        let _ = self.stream.shutdown_write();
+        let _ = self.stream.set_read_timeout(Some(timeout));
+        let mut discarded = 0;
+        while discarded < 1024 * 1024 {
+            match self.stream.read(&mut self.read_buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => discarded += n,
+            }
+        }
+    }
+}
+
+// Parse a Content-Length value: digits only (no sign or whitespace inside), where a list of
+// identical values ("5, 5", as left by some proxies) counts as one. Too large to represent
+// is reported as usize::MAX, which the body size limit then refuses.
+fn parse_content_length(value: &str) -> Result<usize, io::Error> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, RequestParseError::InvalidContentLength);
+    let mut length = None;
+    for item in value.split(',').map(str::trim) {
+        if item.is_empty() || !item.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let item = item.parse().unwrap_or(usize::MAX);
+        if length.is_some_and(|length| length != item) {
+            return Err(invalid());
+        }
+        length = Some(item);
+    }
+    length.ok_or_else(invalid)
+}
@@ -1,9 +1,11 @@
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use super::auth::{hash_password, generate_salt};
+use super::auth::{hash_password, generate_salt, PasswordPolicy, TokenCookie, MIN_TOKEN_ENTROPY_CHARS};
+use super::json::JsonValue;
+use super::logger::LogLevel;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ServerConfig {
     pub server: ServerSettings,
     pub threading: ThreadingSettings,
@@ -11,51 +13,159 @@ pub struct ServerConfig {
     pub static_files: StaticFilesSettings,
     pub authentication: AuthenticationSettings,
     pub logging: LoggingSettings,
+    pub tls: TlsSettings,
+    pub compression: CompressionSettings,
+    pub cors: CorsSettings,
+    pub recording: RecordingSettings,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ServerSettings {
     pub host: String,
     pub port: u16,
     pub read_timeout_seconds: u64,
     pub write_timeout_seconds: u64,
+    pub strict_parsing: bool, // reject bare LF line endings and malformed/non-UTF-8 headers
+    pub default_routes: bool, // register the built-in demo routes (/, /hello, /api/status, ...)
+    pub metrics_enabled: bool, // serve request/byte counters and rates at GET /metrics
+    pub root_message: String, // served at "/" when nothing else handles it (empty = 404)
+    pub root_redirect: String, // redirect target for "/" when nothing else handles it; wins over root_message
+    pub redirect_allowed_hosts: Vec<String>, // other hosts absolute redirects may point to (the request's own host is always allowed)
+    pub server_name: String, // name reported by /api/status and /api/stats
+    pub server_version: String, // version reported alongside it, empty = the crate version
+    pub request_id_header: String, // reuse or generate a request id in this header, echo and log it (empty = off)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ThreadingSettings {
     pub worker_threads: usize,
     pub max_concurrent_connections: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ConnectionSettings {
     pub max_idle_connections: usize,
     pub idle_timeout_seconds: u64,
     pub keep_alive_timeout_seconds: u64,
     pub buffer_size: usize,
+    pub chunked_threshold_bytes: usize, // 0 disables automatic chunking
+    pub send_408_on_idle_timeout: bool, // send 408 before closing a timed-out idle keep-alive connection
+    pub max_line_length: usize, // longest request or header line accepted, 0 = unlimited
+    pub max_header_bytes: usize, // largest request line plus headers accepted (431 above it), 0 = unlimited
+    pub max_request_body_bytes: usize, // largest request body read into memory (413 above it), 0 = unlimited
+    pub max_requests_per_connection: usize, // requests served on one keep-alive connection before closing it, 0 = unlimited
+    pub max_pipelined_requests: usize, // back-to-back requests already queued by the client served before closing, 0 = unlimited
+    pub default_keep_alive: bool, // keep HTTP/1.1 connections open when the client sends no Connection header
+    pub body_spool_threshold_bytes: usize, // bodies larger than this are written to a temp file instead of memory, 0 = never
+    pub body_spool_directory: String, // where spooled bodies are written, empty = the system temp directory
+    pub max_spooled_body_bytes: usize, // largest body accepted for spooling (413 above it); replaces max_request_body_bytes for those, 0 = unlimited
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct StaticFilesSettings {
     pub enabled: bool,
     pub directory: String,
     pub index_file: String,
     pub directory_listing: bool,
+    pub content_sniffing: bool, // detect Content-Type from file contents when the extension is unknown
+    pub log_file_reads: bool, // log the size and read time of every static file served
+    pub precompressed: bool, // serve pre-rendered file.br / file.gz to clients that accept them
+    pub large_file_warning_bytes: usize, // warn when a static file exceeds this size, 0 = never
+    pub max_path_depth: usize, // deepest static path served (in segments), 0 = unlimited
+    pub path_depth_status: u16, // 403 or 404, returned for paths beyond max_path_depth or through a symlink loop
+    pub host_directories: HashMap<String, String>, // Host name -> static directory used instead of `directory`
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AuthenticationSettings {
     pub enabled: bool,
     pub users: HashMap<String, String>, // username -> password
     pub protected_paths: Vec<String>,
+    pub max_failed_logins: u32, // failed logins per username before a lockout (0 disables)
+    pub failed_login_window_seconds: u64,
+    pub login_lockout_seconds: u64,
+    pub password_min_length: usize,
+    pub password_require_uppercase: bool,
+    pub password_require_lowercase: bool,
+    pub password_require_digit: bool,
+    pub password_require_symbol: bool,
+    pub token_cookie_enabled: bool, // also deliver the login token in an HttpOnly cookie
+    pub token_cookie_name: String,
+    pub token_cookie_secure: bool,
+    pub token_cookie_same_site: String,
+    pub csrf_protection: bool, // require X-CSRF-Token on unsafe requests authenticated by the token cookie
+    pub token_prefix: String, // non-secret marker put in front of login tokens, e.g. "tok_"
+    pub token_entropy_chars: usize, // random hex characters after the prefix, at least 16
+    pub options_without_auth: bool, // answer OPTIONS on protected paths with the allowed methods, no credentials needed
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LoggingSettings {
     pub enabled: bool,
     pub level: String, // "info", "warning", "error"
     pub log_requests: bool,
     pub log_responses: bool,
+    pub log_connections: bool, // info lines for each connection opened, request received and connection closed
+    pub file: String, // append log lines to this file instead of the console (empty = console)
+}
+
+// HTTPS is served when both files are set; otherwise the server speaks plain HTTP
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsSettings {
+    pub cert_file: String, // PEM certificate chain, leaf first
+    pub key_file: String, // PEM private key (PKCS#8, PKCS#1 or SEC1)
+}
+
+impl TlsSettings {
+    pub fn is_enabled(&self) -> bool {
+        !self.cert_file.is_empty() && !self.key_file.is_empty()
+    }
+}
+
+// gzip for responses to clients that send Accept-Encoding: gzip
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionSettings {
+    pub enabled: bool,
+    pub min_size_bytes: usize, // bodies of this size or smaller are sent as-is
+    pub content_types: Vec<String>, // compressible types; "text/*" matches a whole family
+    pub chunked_responses: bool, // also gzip responses sent chunked, segment by segment as they are framed
+}
+
+// Cross-origin access for browsers; no allowed origins means no CORS headers are sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsSettings {
+    pub allowed_origins: Vec<String>, // e.g. "https://app.example.com"; "*" allows any origin
+    pub allowed_methods: Vec<String>, // announced to preflight requests
+    pub allowed_headers: Vec<String>, // request headers a preflight may ask for
+}
+
+// Debug capture of requests and responses; headers such as Authorization are always redacted
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingSettings {
+    pub enabled: bool,
+    pub max_entries: usize, // most recent exchanges kept in memory for /admin/recordings, 0 = none
+    pub max_body_bytes: usize, // request and response bodies are cut to this many bytes
+    pub file: String, // also append each exchange to this file as a JSON line (empty = memory only)
+}
+
+impl CorsSettings {
+    pub fn is_enabled(&self) -> bool {
+        !self.allowed_origins.is_empty()
+    }
+}
+
+impl CompressionSettings {
+    // Whether a body of this Content-Type is worth compressing (parameters such as charset are ignored)
+    pub fn is_compressible(&self, content_type: &str) -> bool {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        self.content_types.iter().any(|allowed| {
+            let allowed = allowed.to_ascii_lowercase();
+            match allowed.strip_suffix("/*") {
+                Some(family) => mime.split('/').next() == Some(family),
+                None => mime == allowed,
+            }
+        })
+    }
 }
 
 impl Default for ServerConfig {
@@ -79,6 +189,15 @@ impl Default for ServerConfig {
                 port: 8080,
                 read_timeout_seconds: 30,
                 write_timeout_seconds: 30,
+                strict_parsing: false,
+                default_routes: true,
+                metrics_enabled: true,
+                root_message: String::new(),
+                root_redirect: String::new(),
+                redirect_allowed_hosts: Vec::new(),
+                server_name: "rust-http-server".to_string(),
+                server_version: String::new(),
+                request_id_header: String::new(),
             },
             threading: ThreadingSettings {
                 worker_threads: 4,
@@ -89,29 +208,112 @@ impl Default for ServerConfig {
                 idle_timeout_seconds: 30,
                 keep_alive_timeout_seconds: 60,
                 buffer_size: 8192, // 8KB
+                chunked_threshold_bytes: 65536, // 64KB
+                send_408_on_idle_timeout: true,
+                max_line_length: 8192,
+                max_header_bytes: 65536, // 64KB
+                max_request_body_bytes: 10 * 1024 * 1024, // 10MB
+                max_requests_per_connection: 0,
+                max_pipelined_requests: 0,
+                default_keep_alive: true,
+                body_spool_threshold_bytes: 0,
+                body_spool_directory: String::new(),
+                max_spooled_body_bytes: 1024 * 1024 * 1024, // 1GB
             },
             static_files: StaticFilesSettings {
                 enabled: true,
                 directory: "static".to_string(),
                 index_file: "index.html".to_string(),
                 directory_listing: true,
+                content_sniffing: false,
+                log_file_reads: false,
+                precompressed: false,
+                large_file_warning_bytes: 10 * 1024 * 1024, // 10MB
+                max_path_depth: 32,
+                path_depth_status: 404,
+                host_directories: HashMap::new(),
             },
             authentication: AuthenticationSettings {
                 enabled: true,
                 users: auth_users,
                 protected_paths: vec!["/admin".to_string()],
+                max_failed_logins: 5,
+                failed_login_window_seconds: 300,
+                login_lockout_seconds: 300,
+                password_min_length: 8,
+                password_require_uppercase: false,
+                password_require_lowercase: false,
+                password_require_digit: false,
+                password_require_symbol: false,
+                token_cookie_enabled: false,
+                token_cookie_name: "auth_token".to_string(),
+                token_cookie_secure: true,
+                token_cookie_same_site: "Strict".to_string(),
+                csrf_protection: true,
+                options_without_auth: true,
+                token_prefix: String::new(),
+                token_entropy_chars: 32,
             },
             logging: LoggingSettings {
                 enabled: true,
                 level: "info".to_string(),
                 log_requests: true,
                 log_responses: false,
+                log_connections: false,
+                file: String::new(),
+            },
+            tls: TlsSettings {
+                cert_file: String::new(),
+                key_file: String::new(),
+            },
+            compression: CompressionSettings {
+                enabled: false,
+                min_size_bytes: 1024,
+                content_types: vec![
+                    "text/*".to_string(),
+                    "application/json".to_string(),
+                    "application/javascript".to_string(),
+                    "application/xml".to_string(),
+                    "image/svg+xml".to_string(),
+                ],
+                chunked_responses: false,
+            },
+            cors: CorsSettings {
+                allowed_origins: Vec::new(),
+                allowed_methods: vec!["GET".to_string(), "POST".to_string(), "PUT".to_string(), "DELETE".to_string(), "OPTIONS".to_string()],
+                allowed_headers: vec!["Content-Type".to_string(), "Authorization".to_string()],
+            },
+            recording: RecordingSettings {
+                enabled: false,
+                max_entries: 100,
+                max_body_bytes: 4096,
+                file: String::new(),
             },
         }
     }
 }
 
 impl ServerConfig {
+    /// Password policy for registrations, built from the [authentication] settings
+    pub fn password_policy(&self) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: self.authentication.password_min_length,
+            require_uppercase: self.authentication.password_require_uppercase,
+            require_lowercase: self.authentication.password_require_lowercase,
+            require_digit: self.authentication.password_require_digit,
+            require_symbol: self.authentication.password_require_symbol,
+        }
+    }
+
+    /// Cookie settings for the login token, if token cookies are enabled
+    pub fn token_cookie(&self) -> Option<TokenCookie> {
+        self.authentication.token_cookie_enabled.then(|| TokenCookie {
+            name: self.authentication.token_cookie_name.clone(),
+            secure: self.authentication.token_cookie_secure,
+            same_site: self.authentication.token_cookie_same_site.clone(),
+        })
+    }
+
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
         let config_content = fs::read_to_string(path)
             .map_err(|e| ConfigError::FileRead(e.to_string()))?;
@@ -144,6 +346,10 @@ impl ServerConfig {
         // For now, we'll implement basic parsing for key-value pairs
         let lines: Vec<&str> = content.lines().collect();
         let mut current_section = "";
+        // Users and protected paths listed in the file replace the defaults instead of adding
+        // to them; `users = []` / `protected_paths = []` state that a list is empty
+        let mut users_from_file = false;
+        let mut protected_paths_from_file = false;
         
         for line in lines {
             let line = line.trim();
@@ -151,14 +357,30 @@ impl ServerConfig {
                 continue;
             }
             
-            if line.starts_with('[') && line.ends_with(']') {
-                current_section = &line[1..line.len()-1];
+            if line.starts_with('[') {
+                let header = strip_comment(line).trim();
+                if header.ends_with(']') {
+                    current_section = &header[1..header.len()-1];
+                }
                 continue;
             }
             
-            if let Some(equals_pos) = line.find('=') {
-                let key = line[..equals_pos].trim();
-                let value = line[equals_pos + 1..].trim().trim_matches('"');
+            if let Some((key, raw_value)) = split_key_value(line) {
+                let key = key.as_str();
+                let value = parse_value(raw_value)
+                    .ok_or_else(|| ConfigError::InvalidValue(key.to_string()))?;
+                let value = value.as_str();
+
+                if current_section == "authentication" {
+                    if (key == "users" || key.starts_with("user_")) && !users_from_file {
+                        config.authentication.users.clear();
+                        users_from_file = true;
+                    }
+                    if (key == "protected_paths" || key.starts_with("protected_path_")) && !protected_paths_from_file {
+                        config.authentication.protected_paths.clear();
+                        protected_paths_from_file = true;
+                    }
+                }
                 
                 match current_section {
                     "server" => Self::parse_server_setting(&mut config.server, key, value)?,
@@ -167,6 +389,10 @@ impl ServerConfig {
                     "static_files" => Self::parse_static_files_setting(&mut config.static_files, key, value)?,
                     "authentication" => Self::parse_auth_setting(&mut config.authentication, key, value)?,
                     "logging" => Self::parse_logging_setting(&mut config.logging, key, value)?,
+                    "tls" => Self::parse_tls_setting(&mut config.tls, key, value)?,
+                    "compression" => Self::parse_compression_setting(&mut config.compression, key, value)?,
+                    "cors" => Self::parse_cors_setting(&mut config.cors, key, value)?,
+                    "recording" => Self::parse_recording_setting(&mut config.recording, key, value)?,
                     _ => {} // Ignore unknown sections
                 }
             }
@@ -181,6 +407,15 @@ impl ServerConfig {
             "port" => settings.port = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
             "read_timeout_seconds" => settings.read_timeout_seconds = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
             "write_timeout_seconds" => settings.write_timeout_seconds = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "strict_parsing" => settings.strict_parsing = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "default_routes" => settings.default_routes = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "metrics_enabled" => settings.metrics_enabled = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "root_message" => settings.root_message = value.to_string(),
+            "root_redirect" => settings.root_redirect = value.to_string(),
+            "redirect_allowed_hosts" => settings.redirect_allowed_hosts = parse_list(value),
+            "server_name" => settings.server_name = value.to_string(),
+            "server_version" => settings.server_version = value.to_string(),
+            "request_id_header" => settings.request_id_header = value.to_string(),
             _ => return Err(ConfigError::UnknownKey(key.to_string())),
         }
         Ok(())
@@ -201,6 +436,17 @@ impl ServerConfig {
             "idle_timeout_seconds" => settings.idle_timeout_seconds = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
             "keep_alive_timeout_seconds" => settings.keep_alive_timeout_seconds = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
             "buffer_size" => settings.buffer_size = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "chunked_threshold_bytes" => settings.chunked_threshold_bytes = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "send_408_on_idle_timeout" => settings.send_408_on_idle_timeout = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "max_line_length" => settings.max_line_length = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "max_header_bytes" => settings.max_header_bytes = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "max_request_body_bytes" => settings.max_request_body_bytes = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "max_requests_per_connection" => settings.max_requests_per_connection = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "max_pipelined_requests" => settings.max_pipelined_requests = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "default_keep_alive" => settings.default_keep_alive = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "body_spool_threshold_bytes" => settings.body_spool_threshold_bytes = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "body_spool_directory" => settings.body_spool_directory = value.to_string(),
+            "max_spooled_body_bytes" => settings.max_spooled_body_bytes = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
             _ => return Err(ConfigError::UnknownKey(key.to_string())),
         }
         Ok(())
@@ -212,6 +458,21 @@ impl ServerConfig {
             "directory" => settings.directory = value.to_string(),
             "index_file" => settings.index_file = value.to_string(),
             "directory_listing" => settings.directory_listing = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "content_sniffing" => settings.content_sniffing = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "log_file_reads" => settings.log_file_reads = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "precompressed" => settings.precompressed = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "large_file_warning_bytes" => settings.large_file_warning_bytes = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "max_path_depth" => settings.max_path_depth = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "path_depth_status" => {
+                settings.path_depth_status = match value.parse() {
+                    Ok(status @ (403 | 404)) => status,
+                    _ => return Err(ConfigError::InvalidValue(key.to_string())),
+                }
+            }
+            _ if key.starts_with("host_") => {
+                let host = &key[5..]; // Remove "host_" prefix
+                settings.host_directories.insert(host.to_ascii_lowercase(), value.to_string());
+            },
             _ => return Err(ConfigError::UnknownKey(key.to_string())),
         }
         Ok(())
@@ -220,6 +481,29 @@ impl ServerConfig {
     fn parse_auth_setting(settings: &mut AuthenticationSettings, key: &str, value: &str) -> Result<(), ConfigError> {
         match key {
             "enabled" => settings.enabled = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "max_failed_logins" => settings.max_failed_logins = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "failed_login_window_seconds" => settings.failed_login_window_seconds = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "login_lockout_seconds" => settings.login_lockout_seconds = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "password_min_length" => settings.password_min_length = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "password_require_uppercase" => settings.password_require_uppercase = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "password_require_lowercase" => settings.password_require_lowercase = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "password_require_digit" => settings.password_require_digit = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "password_require_symbol" => settings.password_require_symbol = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "token_cookie_enabled" => settings.token_cookie_enabled = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "token_cookie_name" => settings.token_cookie_name = value.to_string(),
+            "token_cookie_secure" => settings.token_cookie_secure = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "token_cookie_same_site" => settings.token_cookie_same_site = value.to_string(),
+            "csrf_protection" => settings.csrf_protection = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "token_prefix" => settings.token_prefix = value.to_string(),
+            "options_without_auth" => settings.options_without_auth = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "token_entropy_chars" => {
+                settings.token_entropy_chars = value.parse()
+                    .ok()
+                    .filter(|chars| *chars >= MIN_TOKEN_ENTROPY_CHARS)
+                    .ok_or_else(|| ConfigError::InvalidValue(key.to_string()))?;
+            },
+            // Empty-list markers; parse_toml has already dropped the defaults
+            "users" | "protected_paths" if value == "[]" => {}
             _ if key.starts_with("user_") => {
                 let username = &key[5..]; // Remove "user_" prefix
                 settings.users.insert(username.to_string(), value.to_string());
@@ -235,9 +519,56 @@ impl ServerConfig {
     fn parse_logging_setting(settings: &mut LoggingSettings, key: &str, value: &str) -> Result<(), ConfigError> {
         match key {
             "enabled" => settings.enabled = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
-            "level" => settings.level = value.to_string(),
+            "level" => {
+                LogLevel::parse(value).ok_or_else(|| ConfigError::InvalidValue(key.to_string()))?;
+                settings.level = value.trim().to_ascii_lowercase();
+            }
             "log_requests" => settings.log_requests = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
             "log_responses" => settings.log_responses = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "log_connections" => settings.log_connections = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "file" => settings.file = value.to_string(),
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    fn parse_tls_setting(settings: &mut TlsSettings, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "cert_file" => settings.cert_file = value.to_string(),
+            "key_file" => settings.key_file = value.to_string(),
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    fn parse_compression_setting(settings: &mut CompressionSettings, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "enabled" => settings.enabled = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "min_size_bytes" => settings.min_size_bytes = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "chunked_responses" => settings.chunked_responses = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            // Comma-separated, e.g. "text/*, application/json"
+            "content_types" => settings.content_types = parse_list(value),
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    fn parse_cors_setting(settings: &mut CorsSettings, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "allowed_origins" => settings.allowed_origins = parse_list(value),
+            "allowed_methods" => settings.allowed_methods = parse_list(value),
+            "allowed_headers" => settings.allowed_headers = parse_list(value),
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    fn parse_recording_setting(settings: &mut RecordingSettings, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "enabled" => settings.enabled = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "max_entries" => settings.max_entries = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "max_body_bytes" => settings.max_body_bytes = value.parse().map_err(|_| ConfigError::InvalidValue(key.to_string()))?,
+            "file" => settings.file = value.to_string(),
             _ => return Err(ConfigError::UnknownKey(key.to_string())),
         }
         Ok(())
@@ -250,10 +581,19 @@ impl ServerConfig {
         toml.push_str("# HTTP Server Configuration\n\n");
         
         toml.push_str("[server]\n");
-        toml.push_str(&format!("host = \"{}\"\n", self.server.host));
+        toml.push_str(&format!("host = {}\n", quote_toml_string(&self.server.host)));
         toml.push_str(&format!("port = {}\n", self.server.port));
         toml.push_str(&format!("read_timeout_seconds = {}\n", self.server.read_timeout_seconds));
-        toml.push_str(&format!("write_timeout_seconds = {}\n\n", self.server.write_timeout_seconds));
+        toml.push_str(&format!("write_timeout_seconds = {}\n", self.server.write_timeout_seconds));
+        toml.push_str(&format!("strict_parsing = {}\n", self.server.strict_parsing));
+        toml.push_str(&format!("default_routes = {}\n", self.server.default_routes));
+        toml.push_str(&format!("metrics_enabled = {}\n", self.server.metrics_enabled));
+        toml.push_str(&format!("root_message = {}\n", quote_toml_string(&self.server.root_message)));
+        toml.push_str(&format!("root_redirect = {}\n", quote_toml_string(&self.server.root_redirect)));
+        toml.push_str(&format!("redirect_allowed_hosts = {}\n", quote_toml_string(&self.server.redirect_allowed_hosts.join(", "))));
+        toml.push_str(&format!("server_name = {}\n", quote_toml_string(&self.server.server_name)));
+        toml.push_str(&format!("server_version = {}\n", quote_toml_string(&self.server.server_version)));
+        toml.push_str(&format!("request_id_header = {}\n\n", quote_toml_string(&self.server.request_id_header)));
         
         toml.push_str("[threading]\n");
         toml.push_str(&format!("worker_threads = {}\n", self.threading.worker_threads));
@@ -263,38 +603,318 @@ impl ServerConfig {
         toml.push_str(&format!("max_idle_connections = {}\n", self.connection.max_idle_connections));
         toml.push_str(&format!("idle_timeout_seconds = {}\n", self.connection.idle_timeout_seconds));
         toml.push_str(&format!("keep_alive_timeout_seconds = {}\n", self.connection.keep_alive_timeout_seconds));
-        toml.push_str(&format!("buffer_size = {}\n\n", self.connection.buffer_size));
+        toml.push_str(&format!("buffer_size = {}\n", self.connection.buffer_size));
+        toml.push_str(&format!("chunked_threshold_bytes = {}\n", self.connection.chunked_threshold_bytes));
+        toml.push_str(&format!("send_408_on_idle_timeout = {}\n", self.connection.send_408_on_idle_timeout));
+        toml.push_str(&format!("max_line_length = {}\n", self.connection.max_line_length));
+        toml.push_str(&format!("max_header_bytes = {}\n", self.connection.max_header_bytes));
+        toml.push_str(&format!("max_request_body_bytes = {}\n", self.connection.max_request_body_bytes));
+        toml.push_str(&format!("max_requests_per_connection = {}\n", self.connection.max_requests_per_connection));
+        toml.push_str(&format!("max_pipelined_requests = {}\n", self.connection.max_pipelined_requests));
+        toml.push_str(&format!("default_keep_alive = {}\n", self.connection.default_keep_alive));
+        toml.push_str(&format!("body_spool_threshold_bytes = {}\n", self.connection.body_spool_threshold_bytes));
+        toml.push_str(&format!("body_spool_directory = {}\n", quote_toml_string(&self.connection.body_spool_directory)));
+        toml.push_str(&format!("max_spooled_body_bytes = {}\n\n", self.connection.max_spooled_body_bytes));
         
         toml.push_str("[static_files]\n");
         toml.push_str(&format!("enabled = {}\n", self.static_files.enabled));
-        toml.push_str(&format!("directory = \"{}\"\n", self.static_files.directory));
-        toml.push_str(&format!("index_file = \"{}\"\n", self.static_files.index_file));
-        toml.push_str(&format!("directory_listing = {}\n\n", self.static_files.directory_listing));
+        toml.push_str(&format!("directory = {}\n", quote_toml_string(&self.static_files.directory)));
+        toml.push_str(&format!("index_file = {}\n", quote_toml_string(&self.static_files.index_file)));
+        toml.push_str(&format!("directory_listing = {}\n", self.static_files.directory_listing));
+        toml.push_str(&format!("content_sniffing = {}\n", self.static_files.content_sniffing));
+        toml.push_str(&format!("log_file_reads = {}\n", self.static_files.log_file_reads));
+        toml.push_str(&format!("precompressed = {}\n", self.static_files.precompressed));
+        toml.push_str(&format!("large_file_warning_bytes = {}\n", self.static_files.large_file_warning_bytes));
+        toml.push_str(&format!("max_path_depth = {}\n", self.static_files.max_path_depth));
+        toml.push_str(&format!("path_depth_status = {}\n", self.static_files.path_depth_status));
+        for (host, directory) in &self.static_files.host_directories {
+            toml.push_str(&format!("{} = {}\n", quote_toml_string(&format!("host_{}", host)), quote_toml_string(directory)));
+        }
+        toml.push('\n');
         
         toml.push_str("[authentication]\n");
         toml.push_str(&format!("enabled = {}\n", self.authentication.enabled));
+        toml.push_str(&format!("max_failed_logins = {}\n", self.authentication.max_failed_logins));
+        toml.push_str(&format!("failed_login_window_seconds = {}\n", self.authentication.failed_login_window_seconds));
+        toml.push_str(&format!("login_lockout_seconds = {}\n", self.authentication.login_lockout_seconds));
+        toml.push_str(&format!("password_min_length = {}\n", self.authentication.password_min_length));
+        toml.push_str(&format!("password_require_uppercase = {}\n", self.authentication.password_require_uppercase));
+        toml.push_str(&format!("password_require_lowercase = {}\n", self.authentication.password_require_lowercase));
+        toml.push_str(&format!("password_require_digit = {}\n", self.authentication.password_require_digit));
+        toml.push_str(&format!("password_require_symbol = {}\n", self.authentication.password_require_symbol));
+        toml.push_str(&format!("token_cookie_enabled = {}\n", self.authentication.token_cookie_enabled));
+        toml.push_str(&format!("token_cookie_name = {}\n", quote_toml_string(&self.authentication.token_cookie_name)));
+        toml.push_str(&format!("token_cookie_secure = {}\n", self.authentication.token_cookie_secure));
+        toml.push_str(&format!("token_cookie_same_site = {}\n", quote_toml_string(&self.authentication.token_cookie_same_site)));
+        toml.push_str(&format!("csrf_protection = {}\n", self.authentication.csrf_protection));
+        toml.push_str(&format!("token_prefix = {}\n", quote_toml_string(&self.authentication.token_prefix)));
+        toml.push_str(&format!("token_entropy_chars = {}\n", self.authentication.token_entropy_chars));
+        toml.push_str(&format!("options_without_auth = {}\n", self.authentication.options_without_auth));
+        // Without a marker an empty list would read back as the built-in defaults
+        if self.authentication.users.is_empty() {
+            toml.push_str("users = []\n");
+        }
         for (username, password) in &self.authentication.users {
-            toml.push_str(&format!("user_{} = \"{}\"\n", username, password));
+            // Quoted, so names with spaces, '=' or '#' survive a reload
+            toml.push_str(&format!("{} = {}\n", quote_toml_string(&format!("user_{}", username)), quote_toml_string(password)));
+        }
+        if self.authentication.protected_paths.is_empty() {
+            toml.push_str("protected_paths = []\n");
         }
         for (i, path) in self.authentication.protected_paths.iter().enumerate() {
-            toml.push_str(&format!("protected_path_{} = \"{}\"\n", i + 1, path));
+            toml.push_str(&format!("protected_path_{} = {}\n", i + 1, quote_toml_string(path)));
         }
-        toml.push_str("\n");
+        toml.push('\n');
         
         toml.push_str("[logging]\n");
         toml.push_str(&format!("enabled = {}\n", self.logging.enabled));
-        toml.push_str(&format!("level = \"{}\"\n", self.logging.level));
+        toml.push_str(&format!("level = {}\n", quote_toml_string(&self.logging.level)));
         toml.push_str(&format!("log_requests = {}\n", self.logging.log_requests));
         toml.push_str(&format!("log_responses = {}\n", self.logging.log_responses));
+        toml.push_str(&format!("log_connections = {}\n", self.logging.log_connections));
+        toml.push_str(&format!("file = {}\n\n", quote_toml_string(&self.logging.file)));
+
+        toml.push_str("[tls]\n");
+        toml.push_str(&format!("cert_file = {}\n", quote_toml_string(&self.tls.cert_file)));
+        toml.push_str(&format!("key_file = {}\n\n", quote_toml_string(&self.tls.key_file)));
+
+        toml.push_str("[compression]\n");
+        toml.push_str(&format!("enabled = {}\n", self.compression.enabled));
+        toml.push_str(&format!("min_size_bytes = {}\n", self.compression.min_size_bytes));
+        toml.push_str(&format!("content_types = {}\n", quote_toml_string(&self.compression.content_types.join(", "))));
+        toml.push_str(&format!("chunked_responses = {}\n\n", self.compression.chunked_responses));
+
+        toml.push_str("[cors]\n");
+        toml.push_str(&format!("allowed_origins = {}\n", quote_toml_string(&self.cors.allowed_origins.join(", "))));
+        toml.push_str(&format!("allowed_methods = {}\n", quote_toml_string(&self.cors.allowed_methods.join(", "))));
+        toml.push_str(&format!("allowed_headers = {}\n\n", quote_toml_string(&self.cors.allowed_headers.join(", "))));
+
+        toml.push_str("[recording]\n");
+        toml.push_str(&format!("enabled = {}\n", self.recording.enabled));
+        toml.push_str(&format!("max_entries = {}\n", self.recording.max_entries));
+        toml.push_str(&format!("max_body_bytes = {}\n", self.recording.max_body_bytes));
+        toml.push_str(&format!("file = {}\n", quote_toml_string(&self.recording.file)));
         
         toml
     }
 
+    // Effective configuration as JSON with secrets (password hashes) replaced by "***"
+    pub fn to_redacted_json(&self) -> JsonValue {
+        let mut usernames: Vec<&String> = self.authentication.users.keys().collect();
+        usernames.sort();
+
+        JsonValue::object([
+            ("server", JsonValue::object([
+                ("host", self.server.host.as_str().into()),
+                ("port", self.server.port.into()),
+                ("read_timeout_seconds", self.server.read_timeout_seconds.into()),
+                ("write_timeout_seconds", self.server.write_timeout_seconds.into()),
+                ("strict_parsing", self.server.strict_parsing.into()),
+                ("default_routes", self.server.default_routes.into()),
+                ("metrics_enabled", self.server.metrics_enabled.into()),
+                ("root_message", self.server.root_message.as_str().into()),
+                ("root_redirect", self.server.root_redirect.as_str().into()),
+                ("redirect_allowed_hosts", JsonValue::Array(self.server.redirect_allowed_hosts.iter().map(|host| host.as_str().into()).collect())),
+                ("server_name", self.server.server_name.as_str().into()),
+                ("server_version", self.server.server_version.as_str().into()),
+                ("request_id_header", self.server.request_id_header.as_str().into()),
+            ])),
+            ("threading", JsonValue::object([
+                ("worker_threads", self.threading.worker_threads.into()),
+                ("max_concurrent_connections", self.threading.max_concurrent_connections.into()),
+            ])),
+            ("connection", JsonValue::object([
+                ("max_idle_connections", self.connection.max_idle_connections.into()),
+                ("idle_timeout_seconds", self.connection.idle_timeout_seconds.into()),
+                ("keep_alive_timeout_seconds", self.connection.keep_alive_timeout_seconds.into()),
+                ("buffer_size", self.connection.buffer_size.into()),
+                ("chunked_threshold_bytes", self.connection.chunked_threshold_bytes.into()),
+                ("send_408_on_idle_timeout", self.connection.send_408_on_idle_timeout.into()),
+                ("max_line_length", self.connection.max_line_length.into()),
+                ("max_header_bytes", self.connection.max_header_bytes.into()),
+                ("max_request_body_bytes", self.connection.max_request_body_bytes.into()),
+                ("max_requests_per_connection", self.connection.max_requests_per_connection.into()),
+                ("max_pipelined_requests", self.connection.max_pipelined_requests.into()),
+                ("default_keep_alive", self.connection.default_keep_alive.into()),
+                ("body_spool_threshold_bytes", self.connection.body_spool_threshold_bytes.into()),
+                ("body_spool_directory", self.connection.body_spool_directory.as_str().into()),
+                ("max_spooled_body_bytes", self.connection.max_spooled_body_bytes.into()),
+            ])),
+            ("static_files", JsonValue::object([
+                ("enabled", self.static_files.enabled.into()),
+                ("directory", self.static_files.directory.as_str().into()),
+                ("index_file", self.static_files.index_file.as_str().into()),
+                ("directory_listing", self.static_files.directory_listing.into()),
+                ("content_sniffing", self.static_files.content_sniffing.into()),
+                ("log_file_reads", self.static_files.log_file_reads.into()),
+                ("precompressed", self.static_files.precompressed.into()),
+                ("large_file_warning_bytes", self.static_files.large_file_warning_bytes.into()),
+                ("max_path_depth", self.static_files.max_path_depth.into()),
+                ("path_depth_status", u64::from(self.static_files.path_depth_status).into()),
+                ("host_directories", JsonValue::object(
+                    self.static_files.host_directories.iter().map(|(host, directory)| (host.as_str(), directory.as_str().into()))
+                )),
+            ])),
+            ("authentication", JsonValue::object([
+                ("enabled", self.authentication.enabled.into()),
+                ("max_failed_logins", u64::from(self.authentication.max_failed_logins).into()),
+                ("failed_login_window_seconds", self.authentication.failed_login_window_seconds.into()),
+                ("login_lockout_seconds", self.authentication.login_lockout_seconds.into()),
+                ("password_min_length", self.authentication.password_min_length.into()),
+                ("password_require_uppercase", self.authentication.password_require_uppercase.into()),
+                ("password_require_lowercase", self.authentication.password_require_lowercase.into()),
+                ("password_require_digit", self.authentication.password_require_digit.into()),
+                ("password_require_symbol", self.authentication.password_require_symbol.into()),
+                ("token_cookie_enabled", self.authentication.token_cookie_enabled.into()),
+                ("token_cookie_name", self.authentication.token_cookie_name.as_str().into()),
+                ("token_cookie_secure", self.authentication.token_cookie_secure.into()),
+                ("token_cookie_same_site", self.authentication.token_cookie_same_site.as_str().into()),
+                ("csrf_protection", self.authentication.csrf_protection.into()),
+                ("token_prefix", self.authentication.token_prefix.as_str().into()),
+                ("token_entropy_chars", self.authentication.token_entropy_chars.into()),
+                ("options_without_auth", self.authentication.options_without_auth.into()),
+                ("users", JsonValue::object(usernames.iter().map(|name| (name.as_str(), "***".into())))),
+                ("protected_paths", JsonValue::Array(
+                    self.authentication.protected_paths.iter().map(|path| path.as_str().into()).collect()
+                )),
+            ])),
+            ("logging", JsonValue::object([
+                ("enabled", self.logging.enabled.into()),
+                ("level", self.logging.level.as_str().into()),
+                ("log_requests", self.logging.log_requests.into()),
+                ("log_responses", self.logging.log_responses.into()),
+                ("log_connections", self.logging.log_connections.into()),
+                ("file", self.logging.file.as_str().into()),
+            ])),
+            ("tls", JsonValue::object([
+                ("cert_file", self.tls.cert_file.as_str().into()),
+                ("key_file", self.tls.key_file.as_str().into()),
+            ])),
+            ("compression", JsonValue::object([
+                ("enabled", self.compression.enabled.into()),
+                ("min_size_bytes", self.compression.min_size_bytes.into()),
+                ("content_types", JsonValue::Array(
+                    self.compression.content_types.iter().map(|content_type| content_type.as_str().into()).collect()
+                )),
+                ("chunked_responses", self.compression.chunked_responses.into()),
+            ])),
+            ("cors", JsonValue::object([
+                ("allowed_origins", JsonValue::Array(self.cors.allowed_origins.iter().map(|origin| origin.as_str().into()).collect())),
+                ("allowed_methods", JsonValue::Array(self.cors.allowed_methods.iter().map(|method| method.as_str().into()).collect())),
+                ("allowed_headers", JsonValue::Array(self.cors.allowed_headers.iter().map(|header| header.as_str().into()).collect())),
+            ])),
+            ("recording", JsonValue::object([
+                ("enabled", self.recording.enabled.into()),
+                ("max_entries", self.recording.max_entries.into()),
+                ("max_body_bytes", self.recording.max_body_bytes.into()),
+                ("file", self.recording.file.as_str().into()),
+            ])),
+        ])
+    }
+
     pub fn get_bind_address(&self) -> String {
         format!("{}:{}", self.server.host, self.server.port)
     }
 }
 
+// Strip a trailing `# comment`, ignoring '#' characters inside quoted strings
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Split a `key = value` line. Keys may be quoted ("user_jane doe" = ...) to hold spaces,
+// '=' or '#'; quoted keys use the same escapes as values. Returns the key and raw value.
+fn split_key_value(line: &str) -> Option<(String, &str)> {
+    if !line.starts_with('"') {
+        let equals_pos = line.find('=')?;
+        return Some((line[..equals_pos].trim().to_string(), &line[equals_pos + 1..]));
+    }
+    // Find the closing quote of the key, skipping escaped characters
+    let mut escaped = false;
+    let key_end = line.char_indices().skip(1).find_map(|(i, c)| match c {
+        _ if escaped => { escaped = false; None }
+        '\\' => { escaped = true; None }
+        '"' => Some(i),
+        _ => None,
+    })?;
+    let key = parse_value(&line[..=key_end])?;
+    let rest = line[key_end + 1..].trim_start().strip_prefix('=')?;
+    Some((key, rest))
+}
+
+// Parse the value part of a `key = value` line. Quoted values may contain '=', '#'
+// and escaped quotes; anything after the closing quote must be a comment.
+// Returns None for malformed values such as an unterminated string.
+fn parse_value(raw: &str) -> Option<String> {
+    let raw = strip_comment(raw).trim();
+    let Some(quoted) = raw.strip_prefix('"') else {
+        return Some(raw.to_string());
+    };
+
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                // Only whitespace may follow the closing quote (comments were stripped above)
+                return chars.as_str().trim().is_empty().then_some(value);
+            }
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                '"' => value.push('"'),
+                '\\' => value.push('\\'),
+                'u' => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    value.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                }
+                _ => return None,
+            },
+            c => value.push(c),
+        }
+    }
+    None // unterminated string
+}
+
+// Split a comma-separated list value ("a, b, c"), dropping empty entries
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Quote a string value for TOML output, escaping backslashes, quotes and control characters
+fn quote_toml_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
 #[derive(Debug)]
 pub enum ConfigError {
     FileRead(String),
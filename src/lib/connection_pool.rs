@@ -1,10 +1,128 @@
-// Simplified connection pool for future implementation
+use std::collections::VecDeque;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use super::ServerStats;
+
+// A keep-alive connection whose worker is waiting for the next request
+struct IdleConnection {
+    id: u64,
+    stream: TcpStream,
+    idle_since: Instant,
+}
+
+// Tracks idle keep-alive connections and caps how many may be held open at once.
+// When the cap is exceeded the least-recently-used idle connection is shut down,
+// which wakes its worker with EOF so it closes the connection and frees itself.
+// Connections idle for longer than the idle timeout are shut down the same way by `reap_idle`.
 pub struct ConnectionPool {
+    idle: Mutex<VecDeque<IdleConnection>>, // oldest first
+    max_idle_connections: usize,
+    idle_timeout: Duration, // zero disables reaping
+    next_id: AtomicU64,
+    closed: AtomicBool, // set by close_all_idle; later check-ins are closed immediately
+    stats: Option<Arc<ServerStats>>,
 }
 
 impl ConnectionPool {
-    pub fn new(_max_idle_connections: usize, _idle_timeout_secs: u64) -> Self {
+    pub fn new(max_idle_connections: usize, idle_timeout_secs: u64) -> Self {
         ConnectionPool {
+            idle: Mutex::new(VecDeque::new()),
+            max_idle_connections,
+            idle_timeout: Duration::from_secs(idle_timeout_secs),
+            next_id: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            stats: None,
+        }
+    }
+
+    // Publish the idle connection count to the given stats
+    pub fn with_stats(mut self, stats: Arc<ServerStats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    // Allocate an identifier used to check a connection in and out of the pool
+    pub fn next_connection_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    // Park a keep-alive connection as idle, evicting the oldest ones beyond the cap
+    pub fn checkin(&self, id: u64, stream: TcpStream) {
+        if self.closed.load(Ordering::SeqCst) {
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+        if let Ok(mut idle) = self.idle.lock() {
+            idle.push_back(IdleConnection { id, stream, idle_since: Instant::now() });
+
+            while idle.len() > self.max_idle_connections {
+                if let Some(evicted) = idle.pop_front() {
+                    let _ = evicted.stream.shutdown(Shutdown::Both);
+                }
+            }
+            self.publish(idle.len());
+        }
+    }
+
+    // Mark a connection as active again. Returns false if it was evicted while idle.
+    pub fn checkout(&self, id: u64) -> bool {
+        if let Ok(mut idle) = self.idle.lock()
+            && let Some(index) = idle.iter().position(|conn| conn.id == id)
+        {
+            idle.remove(index);
+            self.publish(idle.len());
+            return true;
+        }
+        false
+    }
+
+    // Shut down connections that have been idle longer than the idle timeout.
+    // Returns how many were closed.
+    pub fn reap_idle(&self) -> usize {
+        if self.idle_timeout.is_zero() {
+            return 0;
+        }
+        let mut reaped = 0;
+        if let Ok(mut idle) = self.idle.lock() {
+            // Oldest first, so stop at the first connection that is still within the timeout
+            while idle.front().is_some_and(|conn| conn.idle_since.elapsed() > self.idle_timeout) {
+                if let Some(expired) = idle.pop_front() {
+                    let _ = expired.stream.shutdown(Shutdown::Both);
+                    reaped += 1;
+                }
+            }
+            if reaped > 0 {
+                self.publish(idle.len());
+            }
+        }
+        reaped
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    // Shut down every idle connection, and any that go idle later, e.g. when the
+    // server stops accepting
+    pub fn close_all_idle(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Ok(mut idle) = self.idle.lock() {
+            for conn in idle.drain(..) {
+                let _ = conn.stream.shutdown(Shutdown::Both);
+            }
+            self.publish(0);
+        }
+    }
+
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().map(|idle| idle.len()).unwrap_or(0)
+    }
+
+    fn publish(&self, idle_count: usize) {
+        if let Some(stats) = &self.stats {
+            stats.set_idle_connections(idle_count);
         }
     }
 }
@@ -0,0 +1,64 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// HTTP dates (RFC 7231 section 7.1.1.1) without a calendar dependency. Only the preferred
+// IMF-fixdate form is produced or accepted; times before 1970 are clamped to the epoch.
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"]; // 1970-01-01 was a Thursday
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+// IMF-fixdate as used in HTTP headers, e.g. "Sun, 06 Nov 1994 08:49:37 GMT"
+pub fn http_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let days = secs / 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+    let seconds_of_day = secs % 86_400;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize], day, MONTHS[(month - 1) as usize], year,
+        seconds_of_day / 3600, seconds_of_day / 60 % 60, seconds_of_day % 60
+    )
+}
+
+// Parse an IMF-fixdate. The obsolete RFC 850 and asctime forms are not accepted.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let (_, rest) = value.trim().split_once(", ")?;
+    let parts: Vec<&str> = rest.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts.as_slice() else { return None };
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| name == month)? as u32 + 1;
+    let year: i64 = year.parse().ok()?;
+    let mut clock = time.split(':').map(|field| field.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (clock.next()??, clock.next()??, clock.next()??);
+    if clock.next().is_some() || !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    // Client-supplied years can be arbitrarily large, so every step is checked for overflow
+    let days = u64::try_from(days_from_civil(year, month, day)?).ok()?;
+    let secs = days.checked_mul(86_400)?.checked_add(hours * 3600 + minutes * 60 + seconds)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+// Days since 1970-01-01 -> (year, month, day) in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153; // March = 0
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = (if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 }) as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// Inverse of civil_from_days; None when the day count does not fit in an i64
+fn days_from_civil(year: i64, month: u32, day: u32) -> Option<i64> {
+    let year = if month <= 2 { year.checked_sub(1)? } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = i64::from(if month > 2 { month - 3 } else { month + 9 });
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146_097)?.checked_add(day_of_era)?.checked_sub(719_468)
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io;
 
 // Custom error types for better error handling
@@ -8,6 +9,18 @@ pub enum ServerError {
     TimeoutError,
     #[allow(dead_code)] // Used for connection errors
     ConnectionError(String),
+    TlsError(String), // certificate/key loading or TLS configuration
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerError::IoError(e) => write!(f, "I/O error: {}", e),
+            ServerError::TimeoutError => write!(f, "Timed out waiting for the client"),
+            ServerError::ConnectionError(message) => write!(f, "Connection error: {}", message),
+            ServerError::TlsError(message) => write!(f, "TLS error: {}", message),
+        }
+    }
 }
 
 impl From<io::Error> for ServerError {
@@ -15,3 +28,95 @@ impl From<io::Error> for ServerError {
         ServerError::IoError(error)
     }
 }
+
+// Why a request could not be parsed; each kind maps to the status code the client gets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestParseError {
+    EmptyRequest,
+    RequestLineNotUtf8,
+    InvalidRequestLine,
+    InvalidMethod(String), // not a valid method token
+    UnknownMethod(String), // a well-formed method this server does not implement (strict mode)
+    InvalidVersion(String), // not of the form HTTP/x.y
+    UnsupportedVersion(String), // well-formed but not HTTP/1.x
+    RequestLineTooLong,
+    HeaderTooLarge,
+    MalformedHeader { line: usize }, // header line without a colon; the request line is line 1
+    InvalidHeaderName(String),
+    InvalidHeaderValue(String), // header whose value is not valid UTF-8
+    BareLineFeed { line: usize },
+    UnterminatedHeaders,
+    InvalidChunkedBody, // bad chunk size line or missing CRLF after chunk data
+    InvalidContentLength, // Content-Length that is not a number, or Content-Length headers that disagree
+    InvalidTransferEncoding, // Transfer-Encoding whose final coding is not chunked
+    HeaderSectionTooLarge, // request line and headers together exceed the limit
+    PayloadTooLarge, // declared or decoded body exceeds the limit
+}
+
+impl RequestParseError {
+    // The response status for this error
+    pub fn status(&self) -> (u16, &'static str) {
+        match self {
+            RequestParseError::UnknownMethod(_) => (501, "Not Implemented"),
+            RequestParseError::UnsupportedVersion(_) => (505, "HTTP Version Not Supported"),
+            RequestParseError::RequestLineTooLong => (414, "URI Too Long"),
+            RequestParseError::HeaderTooLarge | RequestParseError::HeaderSectionTooLarge => (431, "Request Header Fields Too Large"),
+            RequestParseError::PayloadTooLarge => (413, "Payload Too Large"),
+            _ => (400, "Bad Request"),
+        }
+    }
+
+    // Stable machine-readable name of the error kind, sent to the client in X-Parse-Error.
+    // Unlike the Display text it never contains anything taken from the request.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RequestParseError::EmptyRequest => "empty_request",
+            RequestParseError::RequestLineNotUtf8 => "request_line_not_utf8",
+            RequestParseError::InvalidRequestLine => "invalid_request_line",
+            RequestParseError::InvalidMethod(_) => "invalid_method",
+            RequestParseError::UnknownMethod(_) => "unknown_method",
+            RequestParseError::InvalidVersion(_) => "invalid_version",
+            RequestParseError::UnsupportedVersion(_) => "unsupported_version",
+            RequestParseError::RequestLineTooLong => "request_line_too_long",
+            RequestParseError::HeaderTooLarge => "header_too_large",
+            RequestParseError::MalformedHeader { .. } => "malformed_header",
+            RequestParseError::InvalidHeaderName(_) => "invalid_header_name",
+            RequestParseError::InvalidHeaderValue(_) => "invalid_header_value",
+            RequestParseError::BareLineFeed { .. } => "bare_line_feed",
+            RequestParseError::UnterminatedHeaders => "unterminated_headers",
+            RequestParseError::InvalidChunkedBody => "invalid_chunked_body",
+            RequestParseError::InvalidContentLength => "invalid_content_length",
+            RequestParseError::InvalidTransferEncoding => "invalid_transfer_encoding",
+            RequestParseError::HeaderSectionTooLarge => "header_section_too_large",
+            RequestParseError::PayloadTooLarge => "payload_too_large",
+        }
+    }
+}
+
+impl fmt::Display for RequestParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestParseError::EmptyRequest => write!(f, "Empty request"),
+            RequestParseError::RequestLineNotUtf8 => write!(f, "Request line is not valid UTF-8"),
+            RequestParseError::InvalidRequestLine => write!(f, "Invalid request line"),
+            RequestParseError::InvalidMethod(method) => write!(f, "Invalid method {:?}", method),
+            RequestParseError::UnknownMethod(method) => write!(f, "Method {} is not implemented", method),
+            RequestParseError::InvalidVersion(version) => write!(f, "Invalid HTTP version {:?}", version),
+            RequestParseError::UnsupportedVersion(version) => write!(f, "HTTP version {} is not supported", version),
+            RequestParseError::RequestLineTooLong => write!(f, "Request line too long"),
+            RequestParseError::HeaderTooLarge => write!(f, "Header line too long"),
+            RequestParseError::MalformedHeader { line } => write!(f, "Malformed header line {}", line),
+            RequestParseError::InvalidHeaderName(name) => write!(f, "Invalid header name {:?}", name),
+            RequestParseError::InvalidHeaderValue(name) => write!(f, "Value of header {} is not valid UTF-8", name),
+            RequestParseError::BareLineFeed { line } => write!(f, "Bare LF line ending on line {}", line),
+            RequestParseError::UnterminatedHeaders => write!(f, "Unterminated header section"),
+            RequestParseError::InvalidChunkedBody => write!(f, "Invalid chunked request body"),
+            RequestParseError::InvalidContentLength => write!(f, "Invalid Content-Length"),
+            RequestParseError::InvalidTransferEncoding => write!(f, "Transfer-Encoding does not end with chunked"),
+            RequestParseError::HeaderSectionTooLarge => write!(f, "Header section too large"),
+            RequestParseError::PayloadTooLarge => write!(f, "Request body too large"),
+        }
+    }
+}
+
+impl std::error::Error for RequestParseError {}
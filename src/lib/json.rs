@@ -0,0 +1,309 @@
+use std::fmt;
+
+/// Minimal JSON value used to build responses without external dependencies
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>), // insertion order is preserved
+}
+
+impl JsonValue {
+    /// Build an object from key/value pairs, keeping their order
+    pub fn object<'a, I>(fields: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, JsonValue)>,
+    {
+        JsonValue::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    /// Parse a complete JSON document
+    pub fn parse(input: &str) -> Result<Self, &'static str> {
+        let mut parser = Parser { bytes: input.as_bytes(), pos: 0 };
+        let value = parser.parse_value(0)?;
+        parser.skip_whitespace();
+        if parser.pos != parser.bytes.len() {
+            return Err("Trailing characters after JSON value");
+        }
+        Ok(value)
+    }
+
+    /// Look up a field of an object (the first one, if the key is repeated)
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+// Nesting limit so hostile input can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, &'static str> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err("Invalid literal")
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<JsonValue, &'static str> {
+        if depth > MAX_DEPTH {
+            return Err("JSON nested too deeply");
+        }
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            None => Err("Unexpected end of JSON input"),
+            Some(b'n') => self.expect("null", JsonValue::Null),
+            Some(b't') => self.expect("true", JsonValue::Bool(true)),
+            Some(b'f') => self.expect("false", JsonValue::Bool(false)),
+            Some(b'"') => self.parse_string().map(JsonValue::String),
+            Some(b'[') => self.parse_array(depth),
+            Some(b'{') => self.parse_object(depth),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err("Unexpected character in JSON"),
+        }
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<JsonValue, &'static str> {
+        self.pos += 1; // '['
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                _ => return Err("Expected ',' or ']' in array"),
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<JsonValue, &'static str> {
+        self.pos += 1; // '{'
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.bytes.get(self.pos) != Some(&b'"') {
+                return Err("Expected string key in object");
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            if self.bytes.get(self.pos) != Some(&b':') {
+                return Err("Expected ':' after object key");
+            }
+            self.pos += 1;
+            fields.push((key, self.parse_value(depth + 1)?));
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(fields));
+                }
+                _ => return Err("Expected ',' or '}' in object"),
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, &'static str> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(|text| text.parse::<f64>().ok())
+            .map(JsonValue::Number)
+            .ok_or("Invalid number")
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, &'static str> {
+        let digits = self.bytes.get(self.pos..self.pos + 4).ok_or("Truncated unicode escape")?;
+        let digits = std::str::from_utf8(digits).map_err(|_| "Invalid unicode escape")?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| "Invalid unicode escape")?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn parse_string(&mut self) -> Result<String, &'static str> {
+        self.pos += 1; // opening quote
+        let mut result = String::new();
+        loop {
+            // Copy the run of plain characters up to the next quote or escape in one go
+            let run_start = self.pos;
+            while let Some(&b) = self.bytes.get(self.pos) {
+                if b == b'"' || b == b'\\' || b < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // Input came from a &str and the run stops at ASCII bytes, so this is valid UTF-8
+            result.push_str(std::str::from_utf8(&self.bytes[run_start..self.pos]).map_err(|_| "Invalid UTF-8 in string")?);
+
+            match self.bytes.get(self.pos) {
+                None => return Err("Unterminated string"),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escape = *self.bytes.get(self.pos).ok_or("Unterminated string")?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => result.push('"'),
+                        b'\\' => result.push('\\'),
+                        b'/' => result.push('/'),
+                        b'b' => result.push('\u{8}'),
+                        b'f' => result.push('\u{c}'),
+                        b'n' => result.push('\n'),
+                        b'r' => result.push('\r'),
+                        b't' => result.push('\t'),
+                        b'u' => {
+                            let mut code = self.parse_hex4()?;
+                            // Combine a UTF-16 surrogate pair into one code point
+                            if (0xD800..0xDC00).contains(&code) {
+                                if !self.bytes[self.pos..].starts_with(b"\\u") {
+                                    return Err("Unpaired surrogate in string");
+                                }
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return Err("Unpaired surrogate in string");
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            result.push(char::from_u32(code).ok_or("Invalid unicode escape")?);
+                        }
+                        _ => return Err("Invalid escape in string"),
+                    }
+                }
+                Some(_) => return Err("Control character in string"),
+            }
+        }
+    }
+}
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Bool(b) => write!(f, "{}", b),
+            JsonValue::Number(n) if !n.is_finite() => write!(f, "null"),
+            JsonValue::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            JsonValue::Number(n) => write!(f, "{}", n),
+            JsonValue::String(s) => write!(f, "\"{}\"", escape_json_string(s)),
+            JsonValue::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            JsonValue::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "\"{}\":{}", escape_json_string(key), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(value: &str) -> Self {
+        JsonValue::String(value.to_string())
+    }
+}
+
+impl From<String> for JsonValue {
+    fn from(value: String) -> Self {
+        JsonValue::String(value)
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(value: bool) -> Self {
+        JsonValue::Bool(value)
+    }
+}
+
+impl From<u64> for JsonValue {
+    fn from(value: u64) -> Self {
+        JsonValue::Number(value as f64)
+    }
+}
+
+impl From<usize> for JsonValue {
+    fn from(value: usize) -> Self {
+        JsonValue::Number(value as f64)
+    }
+}
+
+impl From<u16> for JsonValue {
+    fn from(value: u16) -> Self {
+        JsonValue::Number(value as f64)
+    }
+}
+
+/// Escape a string for inclusion between double quotes in JSON output
+pub fn escape_json_string(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,33 +1,162 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use super::config::LoggingSettings;
+
+// Longest response body prefix written by log_response
+const MAX_LOGGED_BODY_BYTES: usize = 1024;
+
+/// Severity of a log message; messages below the logger's minimum level are dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl LogLevel {
+    // The [logging] level names: "info", "warning" or "error"
+    pub fn parse(name: &str) -> Option<LogLevel> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "info" => Some(LogLevel::Info),
+            "warning" => Some(LogLevel::Warning),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+}
 
 // Logger for comprehensive logging
 pub struct Logger {
+    output: Option<Arc<Mutex<dyn Write + Send>>>, // None logs to stdout/stderr
+    enabled: bool, // false drops everything
+    min_level: LogLevel, // for log_info / log_warning / log_error
+    log_requests: bool, // access lines from log_request
+    log_responses: bool, // response bodies from log_response
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Logger {
     pub fn new() -> Self {
         Logger {
+            output: None,
+            enabled: true,
+            min_level: LogLevel::Info,
+            log_requests: true,
+            log_responses: false,
+        }
+    }
+
+    // Send all log lines to the given writer instead of stdout/stderr (e.g. a file or a test buffer)
+    pub fn with_output(output: Arc<Mutex<dyn Write + Send>>) -> Self {
+        Logger {
+            output: Some(output),
+            ..Self::new()
         }
     }
 
+    // Append log lines to the file at `path`, created if missing. If it cannot be opened the
+    // lines go to stderr instead, so a bad path never silences the server.
+    pub fn with_file(path: &str) -> Self {
+        let output: Arc<Mutex<dyn Write + Send>> = match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Arc::new(Mutex::new(file)),
+            Err(e) => {
+                eprintln!("WARNING: cannot open log file {}: {}; logging to stderr", path, e);
+                Arc::new(Mutex::new(io::stderr()))
+            }
+        };
+        Self::with_output(output)
+    }
+
+    // Apply the [logging] settings; an unrecognised level falls back to "info"
+    pub fn with_config(mut self, settings: &LoggingSettings) -> Self {
+        self.enabled = settings.enabled;
+        self.min_level = LogLevel::parse(&settings.level).unwrap_or(LogLevel::Info);
+        self.log_requests = settings.log_requests;
+        self.log_responses = settings.log_responses;
+        self
+    }
+
+    pub fn min_level(&self) -> LogLevel {
+        self.min_level
+    }
+
     pub fn log_info(&self, message: &str) {
-        let timestamp = self.get_timestamp();
-        println!("[{}] INFO: {}", timestamp, message);
+        self.log(LogLevel::Info, "INFO", message);
     }
 
     pub fn log_error(&self, message: &str) {
-        let timestamp = self.get_timestamp();
-        eprintln!("[{}] ERROR: {}", timestamp, message);
+        self.log(LogLevel::Error, "ERROR", message);
     }
 
     pub fn log_warning(&self, message: &str) {
-        let timestamp = self.get_timestamp();
-        println!("[{}] WARNING: {}", timestamp, message);
+        self.log(LogLevel::Warning, "WARNING", message);
     }
 
+    // Access log line; controlled by log_requests rather than the level
     pub fn log_request(&self, method: &str, path: &str, status: u16, client_addr: &str) {
+        if !self.enabled || !self.log_requests {
+            return;
+        }
         let timestamp = self.get_timestamp();
-        println!("[{}] {} {} - {} {}", timestamp, client_addr, method, path, status);
+        self.write_line(&format!("[{}] {} {} - {} {}", timestamp, client_addr, method, path, status), false);
+    }
+
+    // Access log line with the time taken to handle the request, in milliseconds, and the
+    // request id when the server assigns one
+    pub fn log_request_timed(&self, method: &str, path: &str, status: u16, client_addr: &str, duration: Duration, request_id: Option<&str>) {
+        if !self.enabled || !self.log_requests {
+            return;
+        }
+        let timestamp = self.get_timestamp();
+        let millis = duration.as_secs_f64() * 1000.0;
+        let mut line = format!("[{}] {} {} - {} {} {:.1}ms", timestamp, client_addr, method, path, status, millis);
+        if let Some(request_id) = request_id {
+            line.push_str(&format!(" request_id={}", request_id));
+        }
+        self.write_line(&line, false);
+    }
+
+    // The body sent for a request (up to MAX_LOGGED_BODY_BYTES), when log_responses is on
+    pub fn log_response(&self, method: &str, path: &str, status: u16, body: &[u8]) {
+        if !self.enabled || !self.log_responses {
+            return;
+        }
+        let shown = &body[..body.len().min(MAX_LOGGED_BODY_BYTES)];
+        let mut text = String::from_utf8_lossy(shown).into_owned();
+        if shown.len() < body.len() {
+            text.push_str(&format!("... ({} bytes)", body.len()));
+        }
+        let timestamp = self.get_timestamp();
+        self.write_line(&format!("[{}] RESPONSE {} {} {}: {}", timestamp, method, path, status, text), false);
+    }
+
+    fn log(&self, level: LogLevel, label: &str, message: &str) {
+        if !self.enabled || level < self.min_level {
+            return;
+        }
+        let timestamp = self.get_timestamp();
+        self.write_line(&format!("[{}] {}: {}", timestamp, label, message), level == LogLevel::Error);
+    }
+
+    fn write_line(&self, line: &str, is_error: bool) {
+        match &self.output {
+            Some(output) => {
+                // Holding the lock for the whole line keeps concurrent workers from interleaving
+                if let Ok(mut output) = output.lock() {
+                    let _ = writeln!(output, "{}", line);
+                    let _ = output.flush();
+                }
+            }
+            None if is_error => eprintln!("{}", line),
+            None => println!("{}", line),
+        }
     }
 
     fn get_timestamp(&self) -> String {
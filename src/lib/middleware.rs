@@ -0,0 +1,58 @@
+use super::{HttpRequest, HttpResponse};
+use super::auth::generate_token;
+
+/// Cross-cutting request/response processing, run by the router in registration order around
+/// route dispatch. Call `next` to continue down the chain (possibly with a modified request)
+/// or return a response without calling it to short-circuit.
+///
+/// Requests rejected by the server before their body is read (authentication, CSRF) and
+/// requests that fail to parse are answered without running middleware.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: &HttpRequest, next: &dyn Fn(&HttpRequest) -> HttpResponse) -> HttpResponse;
+}
+
+// Longest incoming id that is reused as-is; a W3C traceparent is 55 characters
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Example middleware: tags every response with a request id header (X-Request-Id unless
+/// configured otherwise), reusing the id the client sent in that header so ids can be
+/// correlated across services. Incoming ids that are empty, over-long or not plain visible
+/// ASCII are not trusted and replaced by a generated one.
+pub struct RequestIdMiddleware {
+    header: String,
+}
+
+impl Default for RequestIdMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestIdMiddleware {
+    pub fn new() -> Self {
+        Self::with_header("X-Request-Id")
+    }
+
+    // Read and echo the id in `header` instead, e.g. "traceparent"
+    pub fn with_header(header: &str) -> Self {
+        RequestIdMiddleware { header: header.to_string() }
+    }
+
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    fn is_trusted(id: &str) -> bool {
+        !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.bytes().all(|b| b.is_ascii_graphic())
+    }
+}
+
+impl Middleware for RequestIdMiddleware {
+    fn handle(&self, request: &HttpRequest, next: &dyn Fn(&HttpRequest) -> HttpResponse) -> HttpResponse {
+        let request_id = request.headers.get(&self.header.to_ascii_lowercase())
+            .filter(|id| Self::is_trusted(id))
+            .cloned()
+            .unwrap_or_else(generate_token);
+        next(request).with_header(&self.header, &request_id)
+    }
+}
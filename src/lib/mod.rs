@@ -10,21 +10,40 @@ pub mod buffered_stream;
 pub mod server;
 pub mod auth;
 pub mod config;
+pub mod stats;
+pub mod json;
+pub mod tls;
+pub mod middleware;
+pub mod date;
+pub mod recorder;
+pub mod multipart;
+pub mod redirect;
 
 // Re-export commonly used types
-pub use error::ServerError;
-pub use logger::Logger;
-pub use request::HttpRequest;
-pub use response::HttpResponse;
-pub use route::Route;
-pub use router::Router;
+pub use error::{ServerError, RequestParseError};
+pub use logger::{Logger, LogLevel};
+pub use request::{HttpRequest, ParseMode, SpooledBody, TransferSupport};
+pub use response::{HttpResponse, ChunkedWriter};
+pub use route::{Route, Handler};
+pub use router::{Router, ReadinessCheck, ResponseHook, percent_decode};
 pub use thread_pool::ThreadPool;
 pub use connection_pool::ConnectionPool;
-pub use buffered_stream::BufferedStream;
-pub use server::HttpServer;
+pub use buffered_stream::{BufferedStream, Connection, LineTooLong};
+pub use server::{HttpServer, DrainHandle};
 pub use auth::{
-    hash_password, verify_password, generate_salt, generate_token,
-    TokenManager, AuthUser, AuthToken, parse_login_request, 
-    create_login_response, create_error_response, hex_encode, hex_decode
+    hash_password, verify_password, constant_time_eq, generate_salt, generate_token,
+    TokenManager, AuthUser, AuthToken, parse_login_request, parse_change_password_request,
+    create_login_response, create_error_response, login_json, error_json, hex_encode, hex_decode,
+    parse_authorization, LoginRateLimiter, LoginAttempts, LoginAttemptStore,
+    InMemoryLoginAttemptStore, PasswordPolicy, TokenCookie, SessionStore, InMemorySessionStore,
+    MIN_TOKEN_ENTROPY_CHARS
 };
 pub use config::ServerConfig;
+pub use stats::{ServerStats, StatsSnapshot, StatsDelta, OpenConnection};
+pub use json::JsonValue;
+pub use tls::{TlsAcceptor, TlsStream};
+pub use middleware::{Middleware, RequestIdMiddleware};
+pub use date::{http_date, parse_http_date};
+pub use recorder::{Recorder, RecordedExchange, RecordedBody};
+pub use multipart::{MultipartPart, parse_multipart};
+pub use redirect::{RedirectPolicy, RedirectError, absolute_url};
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+/// One part of a multipart/form-data body (RFC 7578)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultipartPart {
+    pub headers: HashMap<String, String>, // names lowercased, like HttpRequest::headers
+    pub name: String, // form field name from Content-Disposition, empty if missing
+    pub filename: Option<String>, // set for file fields; empty when no file was chosen
+    pub content: Vec<u8>, // exactly as sent, without the CRLF that precedes the next boundary
+}
+
+impl MultipartPart {
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get("content-type").map(String::as_str)
+    }
+
+    pub fn content_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.content).ok()
+    }
+}
+
+// Split a multipart body into its parts. The preamble before the first boundary and the
+// epilogue after the closing `--boundary--` are ignored. A part cut off by the end of the
+// body (no boundary after it) is dropped, so a truncated upload never yields partial content.
+pub fn parse_multipart(body: &[u8], boundary: &str) -> Vec<MultipartPart> {
+    let mut parts = Vec::new();
+    if boundary.is_empty() {
+        return parts;
+    }
+    // Every boundary after the first is preceded by the CRLF that ends the previous part
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    let first = &delimiter[2..];
+
+    let mut pos = if body.starts_with(first) {
+        first.len()
+    } else {
+        match find(body, &delimiter, 0) {
+            Some(index) => index + delimiter.len(),
+            None => return parts,
+        }
+    };
+
+    loop {
+        // "--" after the boundary closes the body
+        if body[pos..].starts_with(b"--") {
+            break;
+        }
+        // Skip transport padding up to the CRLF that ends the boundary line
+        let Some(line_end) = find(body, b"\r\n", pos) else { break };
+        if !body[pos..line_end].iter().all(|&b| b == b' ' || b == b'\t') {
+            break; // the boundary string appeared inside some longer line
+        }
+        let start = line_end + 2;
+        let Some(end) = find(body, &delimiter, start) else { break };
+        parts.push(parse_part(&body[start..end]));
+        pos = end + delimiter.len();
+    }
+
+    parts
+}
+
+// Headers up to the first blank line, then the content
+fn parse_part(part: &[u8]) -> MultipartPart {
+    let (head, content) = if part.starts_with(b"\r\n") {
+        (&part[..0], &part[2..])
+    } else {
+        match find(part, b"\r\n\r\n", 0) {
+            Some(index) => (&part[..index], &part[index + 4..]),
+            None => (part, &part[part.len()..]),
+        }
+    };
+
+    let mut headers = HashMap::new();
+    for line in String::from_utf8_lossy(head).split("\r\n") {
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let disposition = headers.get("content-disposition").map(|value| disposition_params(value)).unwrap_or_default();
+    MultipartPart {
+        name: disposition.get("name").cloned().unwrap_or_default(),
+        filename: disposition.get("filename").cloned(),
+        headers,
+        content: content.to_vec(),
+    }
+}
+
+// Parameters of a Content-Disposition value (`form-data; name="a"; filename="b.txt"`) with
+// lowercased names. Quoted values may contain `;` and backslash-escaped characters.
+fn disposition_params(value: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut chars = value.chars().peekable();
+
+    // Skip the disposition type
+    while chars.next_if(|&c| c != ';').is_some() {}
+
+    while chars.next().is_some() {
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '=' && c != ';') {
+            name.push(c);
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            continue; // a parameter without a value
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut param_value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => param_value.extend(chars.next()),
+                    _ => param_value.push(c),
+                }
+            }
+            while chars.next_if(|&c| c != ';').is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ';') {
+                param_value.push(c);
+            }
+            param_value = param_value.trim_end().to_string();
+        }
+        params.insert(name.trim().to_lowercase(), param_value);
+    }
+
+    params
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from > haystack.len() {
+        return None;
+    }
+    haystack[from..].windows(needle.len()).position(|window| window == needle).map(|index| index + from)
+}
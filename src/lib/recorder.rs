@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use super::{HttpRequest, HttpResponse, JsonValue};
+use super::auth::hex_encode;
+use super::date::http_date;
+
+// Headers whose values are never stored, compared case-insensitively
+const REDACTED_HEADERS: [&str; 4] = ["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+// Endpoints whose request and response bodies carry credentials or tokens; both are stored as "***"
+const REDACTED_BODY_PATHS: [&str; 4] = ["/api/login", "/api/register", "/api/change-password", "/api/csrf-token"];
+
+// JSON fields whose values are replaced by "***" in any other recorded response body
+const REDACTED_JSON_FIELDS: [&str; 2] = ["token", "csrf_token"];
+
+/// A body as recorded: the first `max_body_bytes` bytes and the length before truncation
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedBody {
+    pub bytes: Vec<u8>,
+    pub total_len: u64,
+}
+
+impl RecordedBody {
+    fn capture(bytes: &[u8], total_len: u64, max_body_bytes: usize) -> Self {
+        RecordedBody { bytes: bytes[..bytes.len().min(max_body_bytes)].to_vec(), total_len }
+    }
+
+    // A sensitive body: only its length is kept
+    fn redacted(total_len: u64) -> Self {
+        RecordedBody { bytes: b"***".to_vec(), total_len }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        (self.bytes.len() as u64) < self.total_len
+    }
+
+    // Text bodies as "body", anything else as "body_hex", like /api/echo
+    fn to_json(&self, headers: JsonValue) -> Vec<(&'static str, JsonValue)> {
+        let body = match std::str::from_utf8(&self.bytes) {
+            Ok(text) => ("body", text.into()),
+            Err(_) => ("body_hex", hex_encode(&self.bytes).into()),
+        };
+        vec![
+            ("headers", headers),
+            body,
+            ("body_length", self.total_len.into()),
+            ("body_truncated", self.is_truncated().into()),
+        ]
+    }
+}
+
+/// One request and the response sent for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedExchange {
+    pub recorded_at: String, // IMF-fixdate
+    pub method: String,
+    pub path: String,
+    pub request_headers: Vec<(String, String)>, // sorted by name, sensitive values replaced by "***"
+    pub request_body: RecordedBody, // as received, before charset transcoding
+    pub status_code: u16,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: RecordedBody, // as sent, i.e. after compression
+}
+
+impl RecordedExchange {
+    pub fn request_header(&self, name: &str) -> Option<&str> {
+        find_header(&self.request_headers, name)
+    }
+
+    pub fn response_header(&self, name: &str) -> Option<&str> {
+        find_header(&self.response_headers, name)
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut request = vec![("method", self.method.as_str().into()), ("path", self.path.as_str().into())];
+        request.extend(self.request_body.to_json(headers_to_json(&self.request_headers)));
+        let mut response = vec![("status", self.status_code.into())];
+        response.extend(self.response_body.to_json(headers_to_json(&self.response_headers)));
+        JsonValue::object([
+            ("recorded_at", self.recorded_at.as_str().into()),
+            ("request", JsonValue::object(request)),
+            ("response", JsonValue::object(response)),
+        ])
+    }
+}
+
+/// Keeps the most recent request/response exchanges for debugging, optionally also
+/// appending each one to a file as a line of JSON so a session can be inspected or
+/// replayed later
+pub struct Recorder {
+    max_entries: usize,
+    max_body_bytes: usize,
+    entries: Mutex<VecDeque<RecordedExchange>>, // oldest first
+    file: Option<Mutex<File>>,
+}
+
+impl Recorder {
+    pub fn new(max_entries: usize, max_body_bytes: usize) -> Self {
+        Recorder {
+            max_entries,
+            max_body_bytes,
+            entries: Mutex::new(VecDeque::new()),
+            file: None,
+        }
+    }
+
+    // Also append every exchange to `path` (created if missing)
+    pub fn with_file(mut self, path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.file = Some(Mutex::new(file));
+        Ok(self)
+    }
+
+    pub fn record(&self, request: &HttpRequest, response: &HttpResponse) {
+        let path = request.path.split('?').next().unwrap_or_default();
+        let (request_body, response_body) = if REDACTED_BODY_PATHS.contains(&path) {
+            (RecordedBody::redacted(request.body_len()), RecordedBody::redacted(response.body.len() as u64))
+        } else {
+            let response_bytes = mask_token_fields(&response.body);
+            (
+                RecordedBody::capture(request.raw_body(), request.body_len(), self.max_body_bytes),
+                RecordedBody::capture(response_bytes.as_deref().unwrap_or(&response.body), response.body.len() as u64, self.max_body_bytes),
+            )
+        };
+        let exchange = RecordedExchange {
+            recorded_at: http_date(SystemTime::now()),
+            method: request.method.clone(),
+            path: request.path.clone(),
+            request_headers: redacted_headers(request.headers.iter()),
+            request_body,
+            status_code: response.status_code,
+            response_headers: redacted_headers(response.headers.iter()),
+            response_body,
+        };
+
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            // Recording is a debugging aid; a full disk must not fail the request
+            let _ = writeln!(file, "{}", exchange.to_json());
+        }
+
+        if self.max_entries == 0 {
+            return; // file only
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while entries.len() >= self.max_entries {
+            entries.pop_front();
+        }
+        entries.push_back(exchange);
+    }
+
+    // The buffered exchanges, oldest first
+    pub fn entries(&self) -> Vec<RecordedExchange> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clear();
+    }
+}
+
+fn redacted_headers<'a>(headers: impl Iterator<Item = (&'a String, &'a String)>) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = headers
+        .map(|(name, value)| {
+            let redact = REDACTED_HEADERS.iter().any(|sensitive| name.eq_ignore_ascii_case(sensitive));
+            (name.clone(), if redact { "***".to_string() } else { value.clone() })
+        })
+        .collect();
+    headers.sort();
+    headers
+}
+
+// A JSON body with its token fields masked, or None when it is not JSON or has none
+fn mask_token_fields(body: &[u8]) -> Option<Vec<u8>> {
+    fn mask(value: &mut JsonValue) -> bool {
+        let mut masked = false;
+        match value {
+            JsonValue::Object(fields) => {
+                for (key, value) in fields {
+                    if REDACTED_JSON_FIELDS.contains(&key.as_str()) {
+                        *value = "***".into();
+                        masked = true;
+                    } else {
+                        masked |= mask(value);
+                    }
+                }
+            }
+            JsonValue::Array(items) => {
+                for item in items {
+                    masked |= mask(item);
+                }
+            }
+            _ => {}
+        }
+        masked
+    }
+
+    let mut json = JsonValue::parse(std::str::from_utf8(body).ok()?).ok()?;
+    mask(&mut json).then(|| json.to_string().into_bytes())
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+}
+
+fn headers_to_json(headers: &[(String, String)]) -> JsonValue {
+    JsonValue::Object(headers.iter().map(|(name, value)| (name.clone(), value.as_str().into())).collect())
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+
+// Why a redirect target was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedirectError {
+    InvalidTarget(String), // malformed, or a scheme other than http/https
+    DisallowedHost(String), // an absolute target on a host that is neither the request's nor allowed
+    InvalidStatus(u16), // not one of 301, 302, 303, 307 or 308
+}
+
+impl fmt::Display for RedirectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RedirectError::InvalidTarget(target) => write!(f, "Invalid redirect target {:?}", target),
+            RedirectError::DisallowedHost(host) => write!(f, "Redirects to host {} are not allowed", host),
+            RedirectError::InvalidStatus(status_code) => write!(f, "{} is not a redirect status", status_code),
+        }
+    }
+}
+
+impl std::error::Error for RedirectError {}
+
+/// Which absolute URLs a redirect may point to. Relative targets ("/login", "page?x=1") stay
+/// on the current site and are always allowed. Absolute ones ("https://host/...", and the
+/// protocol-relative "//host/...") must name the host the request was sent to, or one of
+/// the allowed hosts, so user input in a redirect cannot send visitors to another site.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RedirectPolicy {
+    allowed_hosts: Vec<String>, // lowercased host names, without ports
+}
+
+impl RedirectPolicy {
+    // Same-host redirects only
+    pub fn new() -> Self {
+        RedirectPolicy::default()
+    }
+
+    // Also allow redirects to `hosts` (compared case-insensitively, ports ignored)
+    pub fn with_allowed_hosts(mut self, hosts: &[String]) -> Self {
+        self.allowed_hosts = hosts.iter().map(|host| host_name(host).to_ascii_lowercase()).collect();
+        self
+    }
+
+    // Also allow the host of `location` when it is absolute, e.g. a redirect target from
+    // trusted configuration. Relative and malformed targets change nothing.
+    pub fn allowing_target(mut self, location: &str) -> Self {
+        if let Ok(Some(host)) = target_host(location) {
+            self.allowed_hosts.push(host.to_ascii_lowercase());
+        }
+        self
+    }
+
+    // Check `location` for a request whose Host header is `request_host`
+    pub fn check(&self, location: &str, request_host: Option<&str>) -> Result<(), RedirectError> {
+        let Some(host) = target_host(location)? else { return Ok(()) };
+        let host = host.to_ascii_lowercase();
+        let same_host = request_host.is_some_and(|request_host| host_name(request_host).eq_ignore_ascii_case(&host));
+        if same_host || self.allowed_hosts.contains(&host) {
+            Ok(())
+        } else {
+            Err(RedirectError::DisallowedHost(host))
+        }
+    }
+}
+
+// Check that `location` is a redirect target browsers read the same way this module does:
+// relative, or an absolute http(s) URL with a host and no credentials. Hosts are not checked.
+pub(crate) fn check_target(location: &str) -> Result<(), RedirectError> {
+    target_host(location).map(|_| ())
+}
+
+// Build "scheme://host/path" for a redirect, e.g. to the HTTPS version of the current page.
+// `host` may include a port; `path` must be an origin-relative path starting with '/'.
+pub fn absolute_url(scheme: &str, host: &str, path: &str) -> Result<String, RedirectError> {
+    let url = format!("{}://{}{}", scheme.to_ascii_lowercase(), host, path);
+    let valid = path.starts_with('/') && !path.starts_with("//") && target_host(&url)?.is_some();
+    if !valid {
+        return Err(RedirectError::InvalidTarget(url));
+    }
+    Ok(url)
+}
+
+// The host an absolute target points to, or None for a relative one. Rejects targets that
+// browsers could read differently than this check: control characters, whitespace and
+// backslashes anywhere, credentials in the authority, and schemes other than http(s).
+fn target_host(location: &str) -> Result<Option<&str>, RedirectError> {
+    let invalid = || RedirectError::InvalidTarget(location.to_string());
+    if location.chars().any(|c| c.is_control() || c.is_whitespace() || c == '\\') {
+        return Err(invalid());
+    }
+
+    let authority_and_path = if let Some(rest) = location.strip_prefix("//") {
+        rest
+    } else {
+        // A scheme is everything before a ':' that comes before any '/', '?' or '#'
+        let scheme_end = location.find([':', '/', '?', '#']).filter(|&end| location[end..].starts_with(':'));
+        let Some(scheme_end) = scheme_end else { return Ok(None) };
+        let scheme = &location[..scheme_end];
+        if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+            return Err(invalid());
+        }
+        location[scheme_end + 1..].strip_prefix("//").ok_or_else(invalid)?
+    };
+
+    let authority = &authority_and_path[..authority_and_path.find(['/', '?', '#']).unwrap_or(authority_and_path.len())];
+    if authority.contains('@') {
+        return Err(invalid());
+    }
+    let host = host_name(authority);
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Some(host))
+}
+
+// The name part of a Host header value or URL authority: "example.com:8080" -> "example.com",
+// "[::1]:80" -> "[::1]"
+pub(crate) fn host_name(host: &str) -> &str {
+    let host = host.trim();
+    match host.rfind(':') {
+        Some(colon) if !host[colon..].contains(']') => &host[..colon],
+        _ => host,
+    }
+}
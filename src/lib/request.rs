@@ -1,62 +1,392 @@
 use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use super::{RequestParseError, Router};
 
-#[derive(Debug)]
+// Charset names (IANA, lowercased) decoded as ISO-8859-1
+const LATIN1_ALIASES: [&str; 7] = ["iso-8859-1", "iso_8859-1", "latin1", "l1", "iso-ir-100", "cp819", "ibm819"];
+
+// Distinguishes spooled bodies written by this process in the same directory
+static NEXT_SPOOL_ID: AtomicU64 = AtomicU64::new(0);
+
+// Methods accepted in strict mode; anything else is answered with 501
+const KNOWN_METHODS: [&str; 9] = ["GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH"];
+
+/// How strictly the request line and headers are parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Accept lone LF line endings and lossily decode non-UTF-8 header values
+    Tolerant,
+    /// Require CRLF line endings, well-formed header lines and UTF-8 header values
+    Strict,
+}
+
+// What the client can receive in the response framing, from its version and TE header
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TransferSupport {
+    pub chunked: bool, // every HTTP/1.1 client accepts chunked; HTTP/1.0 clients never do
+    pub trailers: bool, // "TE: trailers" - trailer fields after the last chunk will be read
+}
+
+#[derive(Debug, Clone)]
 pub struct HttpRequest {
     pub method: String,
     pub path: String,
     pub version: String,
     pub headers: HashMap<String, String>,
-    pub body: String,
+    pub body: Vec<u8>, // as received, or transcoded to UTF-8 when Content-Type declares a supported charset
+    pub original_body: Option<Vec<u8>>, // the bytes as received when `body` was transcoded; see raw_body()
+    pub params: HashMap<String, String>, // path parameters captured by the router, e.g. "id" for /users/:id, "*" for the tail of /files/*
+    pub spooled_body: Option<Arc<SpooledBody>>, // set instead of `body` when a large body was written to disk
+}
+
+// A request body written to a temporary file rather than held in memory. The file is
+// removed when the last handle to it is dropped, i.e. once the request has been handled.
+#[derive(Debug)]
+pub struct SpooledBody {
+    path: PathBuf,
+    len: u64,
+}
+
+impl SpooledBody {
+    // Create an empty, uniquely named spool file in `dir` (the system temp directory
+    // when empty) and return it with a handle open for writing
+    pub fn create(dir: &str) -> io::Result<(SpooledBody, File)> {
+        let dir = if dir.is_empty() { std::env::temp_dir() } else { PathBuf::from(dir) };
+        loop {
+            let id = NEXT_SPOOL_ID.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!("http_body_{}_{}.tmp", std::process::id(), id));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((SpooledBody { path, len: 0 }, file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue, // left over from an earlier run
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Bytes written to the file
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn set_len(&mut self, len: u64) {
+        self.len = len;
+    }
+
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+}
+
+impl Drop for SpooledBody {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
 }
 
 impl HttpRequest {
-    pub fn parse(request_data: &str) -> Result<Self, &'static str> {
-        let lines: Vec<&str> = request_data.lines().collect();
-        
-        if lines.is_empty() {
-            return Err("Empty request");
+    // Transfer codings the client accepts in the response (RFC 7230 4.3). "chunked" is
+    // implied for HTTP/1.1 and never listed in TE; only "trailers" changes what is sent.
+    // TE is hop-by-hop and should be nominated in Connection, but clients that omit
+    // that are still honoured.
+    pub fn transfer_support(&self) -> TransferSupport {
+        let chunked = parse_version(&self.version).is_some_and(|(major, minor)| major > 1 || (major == 1 && minor >= 1));
+        let trailers = chunked && self.headers.get("te").is_some_and(|te| {
+            te.split(',')
+                .map(|coding| coding.split(';').next().unwrap_or("").trim())
+                .any(|coding| coding.eq_ignore_ascii_case("trailers"))
+        });
+        TransferSupport { chunked, trailers }
+    }
+
+    // Attach a body read after the head was parsed, transcoding it to UTF-8 if needed
+    pub fn set_body(&mut self, body: Vec<u8>) {
+        self.body = body;
+        self.original_body = None;
+        self.transcode_body();
+    }
+
+    // The body bytes exactly as received, before any charset transcoding
+    pub fn raw_body(&self) -> &[u8] {
+        self.original_body.as_deref().unwrap_or(&self.body)
+    }
+
+    // Whether the body is sent with chunked framing, i.e. chunked is the final transfer coding
+    pub fn has_chunked_body(&self) -> bool {
+        self.headers.get("transfer-encoding").is_some_and(|codings| {
+            codings.split(',').next_back().is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+        })
+    }
+
+    // Whether the client waits for a 100 Continue before sending the body (RFC 7231 5.1.1).
+    // Expect: 100-continue from an HTTP/1.0 client is ignored.
+    pub fn expects_continue(&self) -> bool {
+        let http_11 = parse_version(&self.version).is_some_and(|(major, minor)| major > 1 || (major == 1 && minor >= 1));
+        http_11 && self.headers.get("expect").is_some_and(|expect| expect.trim().eq_ignore_ascii_case("100-continue"))
+    }
+
+    // The charset parameter of the Content-Type header, lowercased and unquoted
+    pub fn charset(&self) -> Option<String> {
+        self.content_type_param("charset").map(|charset| charset.to_lowercase())
+    }
+
+    // The boundary of a multipart/form-data body, for parse_multipart; case is preserved
+    pub fn multipart_boundary(&self) -> Option<String> {
+        if !self.has_media_type("multipart/form-data") {
+            return None;
+        }
+        self.content_type_param("boundary").map(str::to_string).filter(|boundary| !boundary.is_empty())
+    }
+
+    // Fields of an application/x-www-form-urlencoded body, percent-decoded with '+' as a
+    // space. Empty when the Content-Type is anything else or missing; the body is not guessed at.
+    pub fn form_params(&self) -> HashMap<String, String> {
+        if !self.has_media_type("application/x-www-form-urlencoded") {
+            return HashMap::new();
+        }
+        Router::parse_form_urlencoded(self.body_str().unwrap_or(""))
+    }
+
+    // Whether the Content-Type, ignoring its parameters, is `media_type`
+    fn has_media_type(&self, media_type: &str) -> bool {
+        self.headers.get("content-type")
+            .is_some_and(|content_type| content_type.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case(media_type))
+    }
+
+    // A parameter of the Content-Type header, trimmed and unquoted
+    fn content_type_param(&self, name: &str) -> Option<&str> {
+        let content_type = self.headers.get("content-type")?;
+        content_type.split(';').skip(1).find_map(|param| {
+            let (param_name, value) = param.split_once('=')?;
+            param_name.trim().eq_ignore_ascii_case(name).then(|| value.trim().trim_matches('"'))
+        })
+    }
+
+    // Latin-1 bodies are re-encoded as UTF-8 so body_str() reads them correctly. UTF-8,
+    // undeclared and unsupported charsets are left as they are.
+    fn transcode_body(&mut self) {
+        let Some(charset) = self.charset() else { return };
+        if !LATIN1_ALIASES.contains(&charset.as_str()) || self.body.is_ascii() {
+            return;
+        }
+        // Every Latin-1 byte is the code point of the same value
+        let text: String = self.body.iter().map(|&byte| char::from(byte)).collect();
+        self.original_body = Some(std::mem::replace(&mut self.body, text.into_bytes()));
+    }
+
+    // Attach a body that was spooled to disk; `body` stays empty. Spooled bodies are
+    // never transcoded.
+    pub fn set_spooled_body(&mut self, spooled: SpooledBody) {
+        self.body.clear();
+        self.original_body = None;
+        self.spooled_body = Some(Arc::new(spooled));
+    }
+
+    // Read the body wherever it is kept, from the spool file or from memory
+    pub fn body_reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        match &self.spooled_body {
+            Some(spooled) => Ok(Box::new(spooled.open()?)),
+            None => Ok(Box::new(self.body.as_slice())),
+        }
+    }
+
+    // Length of the body in bytes, whether spooled or in memory
+    pub fn body_len(&self) -> u64 {
+        match &self.spooled_body {
+            Some(spooled) => spooled.len(),
+            None => self.body.len() as u64,
+        }
+    }
+
+    // Cookies from the Cookie header as name -> value, parsed on each call. Empty segments
+    // are skipped, quotes around a value are removed, and the first of duplicate names wins
+    // (browsers send the cookie with the most specific path first).
+    pub fn cookies(&self) -> HashMap<String, String> {
+        let mut cookies = HashMap::new();
+        let Some(header) = self.headers.get("cookie") else {
+            return cookies;
+        };
+        for pair in header.split(';') {
+            let Some((name, value)) = pair.split_once('=') else {
+                continue;
+            };
+            let name = name.trim();
+            if name.is_empty() {
+                continue;
+            }
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            cookies.entry(name.to_string()).or_insert_with(|| value.to_string());
         }
+        cookies
+    }
+
+    // The body as text, or None when it is not valid UTF-8 (e.g. an image upload)
+    pub fn body_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.body).ok()
+    }
+
+    pub fn parse(request_data: &str) -> Result<Self, RequestParseError> {
+        Self::parse_bytes(request_data.as_bytes(), ParseMode::Tolerant)
+    }
+
+    /// Parse a request directly from the bytes read off the socket
+    pub fn parse_bytes(data: &[u8], mode: ParseMode) -> Result<Self, RequestParseError> {
+        let mut pos = 0;
+        let mut line_number = 0;
+
+        // Tolerant mode ignores empty lines preceding the request line (RFC 7230 3.5)
+        let request_line = loop {
+            line_number += 1;
+            match next_line(data, &mut pos, mode, line_number)? {
+                Some(line) if line.is_empty() && mode == ParseMode::Tolerant => continue,
+                Some(line) => break line,
+                None => return Err(RequestParseError::EmptyRequest),
+            }
+        };
+
+        let request_line = std::str::from_utf8(request_line)
+            .map_err(|_| RequestParseError::RequestLineNotUtf8)?;
 
         // Parse HTTP request line (method, path, version)
-        let request_line_parts: Vec<&str> = lines[0].split_whitespace().collect();
-        if request_line_parts.len() != 3 {
-            return Err("Invalid request line");
+        let request_line_parts: Vec<&str> = match mode {
+            ParseMode::Tolerant => request_line.split_whitespace().collect(),
+            ParseMode::Strict => request_line.split(' ').collect(),
+        };
+        if request_line_parts.len() != 3 || request_line_parts.iter().any(|part| part.is_empty()) {
+            return Err(RequestParseError::InvalidRequestLine);
         }
 
         let method = request_line_parts[0].to_string();
         let path = request_line_parts[1].to_string();
         let version = request_line_parts[2].to_string();
 
-        // Parse HTTP headers (split by lines)
+        if !method.bytes().all(is_token_char) {
+            return Err(RequestParseError::InvalidMethod(method));
+        }
+        if mode == ParseMode::Strict && !KNOWN_METHODS.contains(&method.as_str()) {
+            return Err(RequestParseError::UnknownMethod(method));
+        }
+        match parse_version(&version) {
+            Some((1, _)) => {}
+            Some(_) => return Err(RequestParseError::UnsupportedVersion(version)),
+            None if mode == ParseMode::Strict => return Err(RequestParseError::InvalidVersion(version)),
+            None => {}
+        }
+
+        // Parse HTTP headers up to the empty line that ends the header section
         let mut headers = HashMap::new();
-        let mut header_end_index = 1;
 
-        for (i, line) in lines.iter().enumerate().skip(1) {
+        loop {
+            line_number += 1;
+            let Some(line) = next_line(data, &mut pos, mode, line_number)? else {
+                // The input ended without the blank line, e.g. the client closed its side
+                if mode == ParseMode::Strict {
+                    return Err(RequestParseError::UnterminatedHeaders);
+                }
+                break;
+            };
             if line.is_empty() {
-                header_end_index = i;
                 break;
             }
 
-            if let Some(colon_pos) = line.find(':') {
-                let key = line[..colon_pos].trim().to_lowercase();
-                let value = line[colon_pos + 1..].trim().to_string();
-                headers.insert(key, value);
+            let Some(colon_pos) = line.iter().position(|&b| b == b':') else {
+                if mode == ParseMode::Strict {
+                    return Err(RequestParseError::MalformedHeader { line: line_number });
+                }
+                continue;
+            };
+
+            let name = &line[..colon_pos];
+            if mode == ParseMode::Strict && (name.is_empty() || name.iter().any(|b| b.is_ascii_whitespace())) {
+                return Err(RequestParseError::InvalidHeaderName(String::from_utf8_lossy(name).into_owned()));
             }
+
+            let value = &line[colon_pos + 1..];
+            let value = match mode {
+                ParseMode::Tolerant => String::from_utf8_lossy(value).into_owned(),
+                ParseMode::Strict => std::str::from_utf8(value)
+                    .map_err(|_| RequestParseError::InvalidHeaderValue(String::from_utf8_lossy(name).into_owned()))?
+                    .to_string(),
+            };
+
+            let key = String::from_utf8_lossy(name).trim().to_lowercase();
+            headers.insert(key, value.trim().to_string());
         }
 
-        // Extract request body if present
-        let body = if header_end_index + 1 < lines.len() {
-            lines[header_end_index + 1..].join("\n")
-        } else {
-            String::new()
-        };
+        // Everything after the header section is the body, kept byte-for-byte unless it
+        // needs transcoding (raw_body() still has the original)
+        let body = data[pos..].to_vec();
 
-        Ok(HttpRequest {
+        let mut request = HttpRequest {
             method,
             path,
             version,
             headers,
             body,
-        })
+            original_body: None,
+            params: HashMap::new(),
+            spooled_body: None,
+        };
+        request.transcode_body();
+        Ok(request)
+    }
+}
+
+// Return the next line starting at `pos` without its line terminator, advancing `pos`
+// past it. Returns None once the input is exhausted.
+fn next_line<'a>(
+    data: &'a [u8],
+    pos: &mut usize,
+    mode: ParseMode,
+    line_number: usize,
+) -> Result<Option<&'a [u8]>, RequestParseError> {
+    if *pos >= data.len() {
+        return Ok(None);
+    }
+
+    let rest = &data[*pos..];
+    match rest.iter().position(|&b| b == b'\n') {
+        Some(newline) => {
+            *pos += newline + 1;
+            match rest[..newline].strip_suffix(b"\r") {
+                Some(line) => Ok(Some(line)),
+                None if mode == ParseMode::Strict => Err(RequestParseError::BareLineFeed { line: line_number }),
+                None => Ok(Some(&rest[..newline])),
+            }
+        }
+        None => {
+            // Unterminated final line (the header section ended at EOF)
+            if mode == ParseMode::Strict {
+                return Err(RequestParseError::UnterminatedHeaders);
+            }
+            *pos = data.len();
+            Ok(Some(rest))
+        }
+    }
+}
+
+// tchar from RFC 7230 3.2.6, the characters allowed in a method token
+fn is_token_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+// Split "HTTP/<major>.<minor>" into its numbers
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.strip_prefix("HTTP/")?.split_once('.')?;
+    if major.is_empty() || minor.is_empty() || !major.bytes().chain(minor.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
     }
+    Some((major.parse().ok()?, minor.parse().ok()?))
 }
@@ -1,25 +1,46 @@
 use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::SystemTime;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use super::{HttpRequest, JsonValue, ServerError};
+use super::redirect::{check_target, RedirectError, RedirectPolicy};
+use super::auth::generate_token;
+use super::date::http_date;
 
 #[derive(Debug)]
 pub struct HttpResponse {
     pub status_code: u16,
     pub status_text: String,
     pub headers: HashMap<String, String>,
-    pub body: String,
+    pub body: Vec<u8>,
+    pub trailers: Vec<(String, String)>, // sent after the final chunk of a chunked response
+    pub gzip_chunks: bool, // when sent chunked, gzip the body on the fly and set Content-Encoding
 }
 
+// Uncompressed bytes fed to the gzip stream between flushes of a gzipped chunked response;
+// each flush sends what has been compressed so far as one or more chunks
+const GZIP_SEGMENT_SIZE: usize = 16 * 1024;
+
 impl HttpResponse {
     pub fn new(status_code: u16, status_text: &str) -> Self {
         HttpResponse {
             status_code,
             status_text: status_text.to_string(),
             headers: HashMap::new(),
-            body: String::new(),
+            body: Vec::new(),
+            trailers: Vec::new(),
+            gzip_chunks: false,
         }
     }
 
-    pub fn with_body(mut self, body: &str) -> Self {
-        self.body = body.to_string();
+    pub fn with_body(self, body: &str) -> Self {
+        self.with_body_bytes(body.as_bytes())
+    }
+
+    // Set a raw (possibly binary) body, e.g. the contents of an image file
+    pub fn with_body_bytes(mut self, body: &[u8]) -> Self {
+        self.body = body.to_vec();
         // Automatically set Content-Length header
         self.headers.insert("Content-Length".to_string(), body.len().to_string());
         self
@@ -34,16 +55,186 @@ impl HttpResponse {
         self.with_header("Content-Type", content_type)
     }
 
+    // Serialize `value` as the body; strings are escaped, so any text is safe to include
+    pub fn with_json(self, value: &JsonValue) -> Self {
+        self.with_content_type("application/json").with_body(&value.to_string())
+    }
+
     pub fn with_chunked_encoding(self) -> Self {
         self.with_header("Transfer-Encoding", "chunked")
     }
 
+    // Compress the body with gzip as it is sent chunked. Without chunked framing (e.g. for
+    // an HTTP/1.0 client) the body is sent uncompressed.
+    pub fn with_gzip_chunks(mut self) -> Self {
+        self.gzip_chunks = true;
+        self
+    }
+
+    // Add a trailer field (e.g. a checksum computed over the body). Responses with
+    // trailers are always sent chunked, with the field names announced in a Trailer header.
+    pub fn with_trailer(mut self, key: &str, value: &str) -> Self {
+        self.trailers.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    // A redirect to `location`, with a short HTML body for clients that do not follow it.
+    // Only the statuses that send the client to Location (301, 302, 303, 307, 308) are
+    // accepted; anything else is RedirectError::InvalidStatus. An absolute `location` must be
+    // a well-formed http(s) URL (RedirectError::InvalidTarget); its host is not checked, see
+    // checked_redirect for that.
+    pub fn redirect(status_code: u16, location: &str) -> Result<Self, RedirectError> {
+        let status_text = match status_code {
+            301 => "Moved Permanently",
+            302 => "Found",
+            303 => "See Other",
+            307 => "Temporary Redirect",
+            308 => "Permanent Redirect",
+            _ => return Err(RedirectError::InvalidStatus(status_code)),
+        };
+        check_target(location)?;
+        let location_html = escape_html(location);
+        Ok(HttpResponse::new(status_code, status_text)
+            .with_header("Location", location)
+            .with_content_type("text/html")
+            .with_body(&format!(
+                "<h1>{} - {}</h1><p>The document has moved <a href=\"{}\">here</a>.</p>",
+                status_code, status_text, location_html
+            )))
+    }
+
+    // Like `redirect`, but an absolute `location` must point to the host `request` was sent
+    // to or one allowed by `policy`. Use this whenever the target comes from user input.
+    pub fn checked_redirect(status_code: u16, location: &str, request: &HttpRequest, policy: &RedirectPolicy) -> Result<Self, RedirectError> {
+        policy.check(location, request.headers.get("host").map(String::as_str))?;
+        Self::redirect(status_code, location)
+    }
+
     pub fn with_connection(self, connection_type: &str) -> Self {
         self.with_header("Connection", connection_type)
     }
 
+    // The response to send before closing a connection that failed with `error`, or None
+    // when the connection should just be closed (the client is gone or never spoke HTTP)
+    pub fn from_error(error: &ServerError) -> Option<HttpResponse> {
+        let (status_code, status_text, message) = match error {
+            ServerError::TimeoutError => (408, "Request Timeout", "Request timed out"),
+            ServerError::IoError(e) => match e.kind() {
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => (408, "Request Timeout", "Request timed out"),
+                io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => (400, "Bad Request", "The request could not be read"),
+                io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof => return None,
+                _ => (500, "Internal Server Error", "The request could not be processed"),
+            },
+            ServerError::ConnectionError(_) | ServerError::TlsError(_) => return None,
+        };
+        Some(
+            HttpResponse::new(status_code, status_text)
+                .with_content_type("text/plain")
+                .with_connection("close")
+                .with_body(message)
+        )
+    }
+
+    // The answer to a HEAD request: the same headers, including the Content-Length of the
+    // body a GET would have received, but no body
+    pub fn without_body(mut self) -> Self {
+        // With no body there is nothing to chunk: chunked framing would still write the
+        // final "0" chunk after the headers, so drop it (and the trailers that need it)
+        self.headers.retain(|key, _| !key.eq_ignore_ascii_case("transfer-encoding"));
+        self.trailers.clear();
+        if self.gzip_chunks {
+            // GET would send it gzipped as it goes, with no length known up front
+            self.gzip_chunks = false;
+            self.headers.insert("Content-Encoding".to_string(), "gzip".to_string());
+        } else if self.status_code != 204 && self.status_code != 304 {
+            // 204 and 304 never have a body, so there is no length to report
+            self.headers.insert("Content-Length".to_string(), self.body.len().to_string());
+        }
+        self.body.clear();
+        self
+    }
+
+    // Add `header` to the Vary list, keeping any fields already there
+    pub fn add_vary(&mut self, header: &str) {
+        let vary = match self.headers.get("Vary") {
+            Some(existing) if existing.split(',').any(|field| field.trim().eq_ignore_ascii_case(header)) => return,
+            Some(existing) => format!("{}, {}", existing, header),
+            None => header.to_string(),
+        };
+        self.headers.insert("Vary".to_string(), vary);
+    }
+
+    // Opt in to byte ranges: the handler returns its full body and a GET with a Range
+    // header is answered with just the requested slice (see with_range)
+    pub fn ranged(self) -> Self {
+        self.with_header("Accept-Ranges", "bytes")
+    }
+
+    // Narrow a full 200 response that opted in via ranged() to the byte ranges in
+    // `range_header`: 206 with Content-Range for one range, 206 multipart/byteranges for
+    // several, 416 when none is satisfiable. Anything else (no opt-in, another status,
+    // a malformed header) is left whole.
+    pub fn with_range(mut self, range_header: Option<&str>) -> Self {
+        let opted_in = self.headers.get("Accept-Ranges").is_some_and(|value| value == "bytes");
+        let Some(range_header) = range_header else { return self };
+        if self.status_code != 200 || !opted_in {
+            return self;
+        }
+        let total = self.body.len();
+        let content_type = self.headers.get("Content-Type").cloned();
+        match parse_byte_ranges(range_header, total) {
+            Some(Ok(ranges)) if ranges.len() == 1 => {
+                let (start, end) = ranges[0];
+                self.body = self.body[start..=end].to_vec();
+                self.headers.insert("Content-Range".to_string(), format!("bytes {}-{}/{}", start, end, total));
+            }
+            Some(Ok(ranges)) => {
+                // Each part carries its own Content-Type and Content-Range
+                let boundary = generate_token();
+                let mut body = Vec::new();
+                for (start, end) in ranges {
+                    body.extend_from_slice(format!("\r\n--{}\r\n", boundary).as_bytes());
+                    if let Some(content_type) = &content_type {
+                        body.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+                    }
+                    body.extend_from_slice(format!("Content-Range: bytes {}-{}/{}\r\n\r\n", start, end, total).as_bytes());
+                    body.extend_from_slice(&self.body[start..=end]);
+                }
+                body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+                self.body = body;
+                self.headers.insert("Content-Type".to_string(), format!("multipart/byteranges; boundary={}", boundary));
+            }
+            Some(Err(())) => {
+                let mut response = HttpResponse::new(416, "Range Not Satisfiable")
+                    .with_header("Content-Range", &format!("bytes */{}", total))
+                    .with_body("");
+                if let Some(content_type) = content_type {
+                    response = response.with_content_type(&content_type);
+                }
+                return response;
+            }
+            None => return self,
+        }
+        self.status_code = 206;
+        self.status_text = "Partial Content".to_string();
+        self.headers.insert("Content-Length".to_string(), self.body.len().to_string());
+        self
+    }
+
+    // Fall back to Content-Length framing for a client that cannot receive chunked
+    // responses or trailers; the trailers are dropped
+    pub fn remove_chunked_framing(&mut self) {
+        self.headers.retain(|key, _| !key.eq_ignore_ascii_case("transfer-encoding") && !key.eq_ignore_ascii_case("trailer"));
+        self.headers.insert("Content-Length".to_string(), self.body.len().to_string());
+        self.trailers.clear();
+        self.gzip_chunks = false;
+    }
+
     // Format response with proper HTTP/1.1 format and \r\n line endings
-    pub fn format(&self) -> String {
+    pub fn format(&self) -> Vec<u8> {
         let mut response = String::new();
         
         // Status line generation (HTTP/1.1 200 OK)
@@ -53,18 +244,27 @@ impl HttpResponse {
         for (key, value) in &self.headers {
             response.push_str(&format!("{}: {}\r\n", key, value));
         }
+        self.push_date_header(&mut response);
         
         // Ensure proper \r\n line endings - empty line between headers and body
         response.push_str("\r\n");
         
         // Format response body
-        response.push_str(&self.body);
+        let mut response = response.into_bytes();
+        response.extend_from_slice(&self.body);
         
         response
     }
 
+    // Origin servers must send Date (RFC 7231 7.1.1.2); a handler-supplied one is kept
+    fn push_date_header(&self, head: &mut String) {
+        if !self.headers.keys().any(|key| key.eq_ignore_ascii_case("date")) {
+            head.push_str(&format!("Date: {}\r\n", http_date(SystemTime::now())));
+        }
+    }
+
     // Format response with chunked transfer encoding
-    pub fn format_chunked(&self) -> String {
+    pub fn format_chunked(&self) -> Vec<u8> {
         let mut response = String::new();
         
         // Status line generation (HTTP/1.1 200 OK)
@@ -72,28 +272,185 @@ impl HttpResponse {
         
         // Add required headers with proper formatting (excluding Content-Length for chunked)
         for (key, value) in &self.headers {
-            if key.to_lowercase() != "content-length" && key.to_lowercase() != "transfer-encoding" {
+            let key_lower = key.to_lowercase();
+            let replaced = self.gzip_chunks && key_lower == "content-encoding";
+            if key_lower != "content-length" && key_lower != "transfer-encoding" && key_lower != "trailer" && !replaced {
                 response.push_str(&format!("{}: {}\r\n", key, value));
             }
         }
+        if self.gzip_chunks {
+            response.push_str("Content-Encoding: gzip\r\n");
+        }
         
+        self.push_date_header(&mut response);
+
         // Add Transfer-Encoding: chunked header
         response.push_str("Transfer-Encoding: chunked\r\n");
+
+        // Announce trailer fields so the client knows to expect them
+        if !self.trailers.is_empty() {
+            let names: Vec<&str> = self.trailers.iter().map(|(key, _)| key.as_str()).collect();
+            response.push_str(&format!("Trailer: {}\r\n", names.join(", ")));
+        }
         
         // Ensure proper \r\n line endings - empty line between headers and body
         response.push_str("\r\n");
         
-        // Format body as chunks
-        if !self.body.is_empty() {
-            let body_bytes = self.body.as_bytes();
-            response.push_str(&format!("{:X}\r\n", body_bytes.len()));
-            response.push_str(&self.body);
-            response.push_str("\r\n");
+        // Format body as chunks: in one piece, or gzipped a segment at a time
+        let response = response.into_bytes();
+        let result = if self.gzip_chunks {
+            let mut writer = ChunkedWriter::gzip(response);
+            self.body.chunks(GZIP_SEGMENT_SIZE)
+                .try_for_each(|segment| writer.write_all(segment).and_then(|_| writer.flush()))
+                .and_then(|_| writer.finish(&self.trailers))
+        } else {
+            let mut writer = ChunkedWriter::new(response);
+            writer.write_all(&self.body).and_then(|_| writer.finish(&self.trailers))
+        };
+        result.expect("writing to a Vec cannot fail")
+    }
+}
+
+/// Writes a body with chunked transfer coding (RFC 7230 4.1) as it is produced, optionally
+/// gzipping it on the fly. Every write (for gzip: whatever the compressor emits, at the latest
+/// on flush) goes out as one chunk, so a client can start decoding before the body is complete.
+pub struct ChunkedWriter<W: Write> {
+    output: ChunkedOutput<W>,
+}
+
+enum ChunkedOutput<W: Write> {
+    Plain(ChunkFramer<W>),
+    Gzip(GzEncoder<ChunkFramer<W>>),
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(out: W) -> Self {
+        ChunkedWriter { output: ChunkedOutput::Plain(ChunkFramer(out)) }
+    }
+
+    // Gzip the body into the chunks; the response must carry Content-Encoding: gzip
+    pub fn gzip(out: W) -> Self {
+        ChunkedWriter { output: ChunkedOutput::Gzip(GzEncoder::new(ChunkFramer(out), Compression::default())) }
+    }
+
+    // The underlying writer, holding what has been sent so far
+    pub fn get_ref(&self) -> &W {
+        match &self.output {
+            ChunkedOutput::Plain(framer) => &framer.0,
+            ChunkedOutput::Gzip(encoder) => &encoder.get_ref().0,
         }
-        
-        // End chunk marker
-        response.push_str("0\r\n\r\n");
-        
-        response
+    }
+
+    // End the body: the rest of the gzip stream, the last-chunk marker, any trailer fields
+    // and the final empty line. Returns the underlying writer.
+    pub fn finish(self, trailers: &[(String, String)]) -> io::Result<W> {
+        let ChunkFramer(mut out) = match self.output {
+            ChunkedOutput::Plain(framer) => framer,
+            ChunkedOutput::Gzip(encoder) => encoder.finish()?,
+        };
+        out.write_all(b"0\r\n")?;
+        for (key, value) in trailers {
+            out.write_all(format!("{}: {}\r\n", key, value).as_bytes())?;
+        }
+        out.write_all(b"\r\n")?;
+        out.flush()?;
+        Ok(out)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.output {
+            ChunkedOutput::Plain(framer) => framer.write(buf),
+            ChunkedOutput::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    // With gzip, compresses everything written so far (a sync flush) and sends it
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            ChunkedOutput::Plain(framer) => framer.flush(),
+            ChunkedOutput::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Frames each non-empty write as one chunk. An empty chunk would end the body early.
+struct ChunkFramer<W: Write>(W);
+
+impl<W: Write> Write for ChunkFramer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.0.write_all(format!("{:X}\r\n", buf.len()).as_bytes())?;
+            self.0.write_all(buf)?;
+            self.0.write_all(b"\r\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Text from the request (paths, parse errors) is escaped before going into HTML
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Most ranges honoured in one request; more than this is served as the whole body
+const MAX_RANGES: usize = 16;
+
+// Resolve a "bytes=" range set against a body of `total` bytes to inclusive offsets, in
+// request order. Ranges starting past the end are dropped. None means the header should be
+// ignored; Err means no range overlaps the body.
+fn parse_byte_ranges(header: &str, total: usize) -> Option<Result<Vec<(usize, usize)>, ()>> {
+    let specs: Vec<&str> = header.trim().strip_prefix("bytes=")?.split(',').map(str::trim).collect();
+    if specs.len() > MAX_RANGES {
+        return None;
+    }
+    let mut ranges = Vec::new();
+    for spec in specs {
+        if let Some(range) = parse_byte_range(spec, total)? {
+            ranges.push(range);
+        }
+    }
+    Some(if ranges.is_empty() { Err(()) } else { Ok(ranges) })
+}
+
+// One range spec ("a-b", "a-" or "-n"): None when malformed, Some(None) when unsatisfiable
+fn parse_byte_range(spec: &str, total: usize) -> Option<Option<(usize, usize)>> {
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        // Suffix range: the final `last` bytes
+        let length: usize = last.parse().ok()?;
+        if length == 0 || total == 0 {
+            return Some(None);
+        }
+        return Some(Some((total - length.min(total), total - 1)));
+    }
+    let start: usize = first.parse().ok()?;
+    let end = if last.is_empty() { usize::MAX } else { last.parse().ok()? };
+    if end < start {
+        return None;
+    }
+    if start >= total {
+        return Some(None);
+    }
+    Some(Some((start, end.min(total - 1))))
+}
+
+// Lets handlers assemble a body incrementally with write!/writeln!,
+// keeping Content-Length in sync with what has been written
+impl io::Write for HttpResponse {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.body.extend_from_slice(buf);
+        self.headers.insert("Content-Length".to_string(), self.body.len().to_string());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
     }
 }
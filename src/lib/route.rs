@@ -1,8 +1,64 @@
+use std::fmt;
+use std::sync::Arc;
 use super::{HttpRequest, HttpResponse};
 
-#[derive(Debug, Clone)]
+/// A route handler. Closures may capture state (a connection pool, counters, caches); it is
+/// shared by every worker thread, so anything mutable needs its own synchronization.
+pub type Handler = Arc<dyn Fn(&HttpRequest) -> HttpResponse + Send + Sync>;
+
+#[derive(Clone)]
 pub struct Route {
     pub method: String,
     pub path: String,
-    pub handler: fn(&HttpRequest) -> HttpResponse,
+    pub handler: Handler,
+}
+
+impl fmt::Debug for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Route").field("method", &self.method).field("path", &self.path).finish_non_exhaustive()
+    }
+}
+
+impl Route {
+    // A pattern route has at least one ":name" segment, e.g. /users/:id, or is a wildcard route
+    pub fn is_pattern(&self) -> bool {
+        self.is_wildcard() || self.path.split('/').any(|segment| segment.starts_with(':'))
+    }
+
+    // A wildcard route ends in "/*" and matches everything below that prefix, e.g. /files/*
+    pub fn is_wildcard(&self) -> bool {
+        self.path.ends_with("/*")
+    }
+
+    // Match `path` against this route's pattern, returning the captured (still encoded)
+    // segments by name. Segment counts must agree and captures never match an empty segment.
+    // A trailing "*" instead captures the rest of the path (possibly empty) under "*", so
+    // /files/* matches /files/a/b with "*" = "a/b", and /files/ with "*" = "".
+    pub fn match_params(&self, path: &str) -> Option<Vec<(String, String)>> {
+        let mut pattern: Vec<&str> = self.path.split('/').collect();
+        let mut segments: Vec<&str> = path.split('/').collect();
+        let mut tail = None;
+        if self.is_wildcard() {
+            pattern.pop();
+            if segments.len() <= pattern.len() {
+                return None;
+            }
+            tail = Some(segments.split_off(pattern.len()).join("/"));
+        }
+        if pattern.len() != segments.len() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        for (expected, actual) in pattern.iter().zip(&segments) {
+            match expected.strip_prefix(':') {
+                Some(name) if !actual.is_empty() => params.push((name.to_string(), actual.to_string())),
+                Some(_) => return None,
+                None if expected == actual => {}
+                None => return None,
+            }
+        }
+        params.extend(tail.map(|tail| ("*".to_string(), tail)));
+        Some(params)
+    }
 }
@@ -1,19 +1,65 @@
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use super::response::escape_html;
+use super::date::{http_date, parse_http_date};
+use super::middleware::Middleware;
+use super::redirect::host_name;
 use super::{
-    HttpRequest, HttpResponse, Route, verify_password, 
-    hash_password, generate_salt, TokenManager, parse_login_request,
-    create_login_response, create_error_response
+    HttpRequest, Logger, HttpResponse, Route, verify_password, constant_time_eq,
+    hash_password, generate_salt, TokenManager, parse_login_request, parse_change_password_request, parse_authorization, LoginRateLimiter,
+    LoginAttemptStore, SessionStore, PasswordPolicy, TokenCookie, generate_token,
+    login_json, error_json, ServerStats, JsonValue, TlsAcceptor, Recorder, RedirectPolicy
 };
 
+/// A named readiness probe for a dependency; returns Err with a reason when it is unavailable
+pub type ReadinessCheck = Arc<dyn Fn() -> Result<(), String> + Send + Sync>;
+
+/// Runs on every routed response just before it is framed, e.g. to add headers or log
+pub type ResponseHook = Arc<dyn Fn(&HttpRequest, &mut HttpResponse) + Send + Sync>;
+
 pub struct Router {
     routes: Vec<Route>,
     static_dir: Option<String>,
+    host_static_dirs: HashMap<String, String>, // lowercase Host name -> static root for that site
+    content_sniffing: bool, // detect Content-Type from file signatures when the extension is unknown
+    log_file_reads: bool, // log size and read time of each static file
+    precompressed: bool, // serve file.br / file.gz sidecars to clients that accept them
+    large_file_warning_bytes: usize, // warn about static files above this size (0 = never)
+    max_path_depth: usize, // deepest static path served, in segments (0 = unlimited)
+    path_depth_status: u16, // 403 or 404 for paths that are too deep or pass through a symlink loop
+    logger: Arc<Logger>,
     auth_users: Arc<Mutex<HashMap<String, String>>>, // username -> password_hash
     protected_paths: Vec<String>,
     token_manager: Arc<TokenManager>,
+    login_limiter: Arc<LoginRateLimiter>, // shared across workers so lockouts apply server-wide
+    password_policy: PasswordPolicy, // enforced by /api/register
+    token_cookie: Option<TokenCookie>, // also issue/accept the token as a cookie when set
+    csrf_protection: bool, // require X-CSRF-Token on unsafe cookie-authenticated requests
+    csrf_tokens: Arc<Mutex<HashMap<String, String>>>, // session token -> CSRF token
+    options_without_auth: bool, // answer OPTIONS on protected paths with the allowed methods, unauthenticated
+    stats: Arc<ServerStats>,
+    metrics_enabled: bool, // serve GET /metrics
+    effective_config: Option<Arc<JsonValue>>, // redacted config served at /admin/config
+    performance_settings: JsonValue, // configured pool sizes and timeouts reported by /api/stats
+    tls_acceptor: Option<Arc<TlsAcceptor>>, // reloaded by POST /admin/tls/reload
+    recorder: Option<Arc<Recorder>>, // captures exchanges in debug recording mode; listed at /admin/recordings
+    root_message: Option<String>, // fallback body for "/" when no route or static file matches
+    root_redirect: Option<String>, // fallback redirect for "/"; takes precedence over root_message
+    redirect_policy: RedirectPolicy, // hosts absolute redirects may point to besides the request's own
+    server_name: String, // reported by /api/status and /api/stats
+    server_version: String,
+    status_endpoint: bool, // serve GET /api/status
+    readiness_checks: Vec<(String, ReadinessCheck)>, // probed by GET /readyz
+    draining: Arc<AtomicBool>, // set while the server drains; /readyz then reports 503
+    response_hooks: Vec<ResponseHook>, // run in registration order
+    middleware: Vec<Arc<dyn Middleware>>, // wraps route dispatch, first registered runs outermost
+    cors_origins: Vec<String>, // origins granted cross-origin access ("*" = any); empty disables CORS
+    cors_methods: Vec<String>, // announced to preflight requests
+    cors_headers: Vec<String>, // request headers preflights may ask for
 }
 
 impl Clone for Router {
@@ -21,36 +67,337 @@ impl Clone for Router {
         Router {
             routes: self.routes.clone(),
             static_dir: self.static_dir.clone(),
+            host_static_dirs: self.host_static_dirs.clone(),
+            content_sniffing: self.content_sniffing,
+            log_file_reads: self.log_file_reads,
+            precompressed: self.precompressed,
+            large_file_warning_bytes: self.large_file_warning_bytes,
+            max_path_depth: self.max_path_depth,
+            path_depth_status: self.path_depth_status,
+            logger: Arc::clone(&self.logger),
             auth_users: Arc::clone(&self.auth_users),
             protected_paths: self.protected_paths.clone(),
             token_manager: Arc::clone(&self.token_manager),
+            login_limiter: Arc::clone(&self.login_limiter),
+            password_policy: self.password_policy.clone(),
+            token_cookie: self.token_cookie.clone(),
+            csrf_protection: self.csrf_protection,
+            csrf_tokens: Arc::clone(&self.csrf_tokens),
+            options_without_auth: self.options_without_auth,
+            stats: Arc::clone(&self.stats),
+            metrics_enabled: self.metrics_enabled,
+            effective_config: self.effective_config.clone(),
+            performance_settings: self.performance_settings.clone(),
+            tls_acceptor: self.tls_acceptor.clone(),
+            recorder: self.recorder.clone(),
+            root_message: self.root_message.clone(),
+            root_redirect: self.root_redirect.clone(),
+            redirect_policy: self.redirect_policy.clone(),
+            server_name: self.server_name.clone(),
+            server_version: self.server_version.clone(),
+            status_endpoint: self.status_endpoint,
+            readiness_checks: self.readiness_checks.clone(),
+            draining: Arc::clone(&self.draining),
+            response_hooks: self.response_hooks.clone(),
+            middleware: self.middleware.clone(),
+            cors_origins: self.cors_origins.clone(),
+            cors_methods: self.cors_methods.clone(),
+            cors_headers: self.cors_headers.clone(),
         }
     }
 }
 
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Router {
     pub fn new() -> Self {
         Router {
             routes: Vec::new(),
             static_dir: None,
+            host_static_dirs: HashMap::new(),
+            content_sniffing: false,
+            log_file_reads: false,
+            precompressed: false,
+            large_file_warning_bytes: 0,
+            max_path_depth: 0,
+            path_depth_status: 404,
+            logger: Arc::new(Logger::new()),
             auth_users: Arc::new(Mutex::new(HashMap::new())),
             protected_paths: Vec::new(),
             token_manager: Arc::new(TokenManager::new()),
+            login_limiter: Arc::new(LoginRateLimiter::new(5, Duration::from_secs(300), Duration::from_secs(300))),
+            password_policy: PasswordPolicy::default(),
+            token_cookie: None,
+            csrf_protection: true,
+            csrf_tokens: Arc::new(Mutex::new(HashMap::new())),
+            options_without_auth: true,
+            stats: Arc::new(ServerStats::new()),
+            metrics_enabled: true,
+            effective_config: None,
+            performance_settings: JsonValue::Object(Vec::new()),
+            tls_acceptor: None,
+            recorder: None,
+            root_message: None,
+            root_redirect: None,
+            redirect_policy: RedirectPolicy::new(),
+            server_name: "rust-http-server".to_string(),
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            status_endpoint: false,
+            readiness_checks: Vec::new(),
+            draining: Arc::new(AtomicBool::new(false)),
+            response_hooks: Vec::new(),
+            middleware: Vec::new(),
+            cors_origins: Vec::new(),
+            cors_methods: Vec::new(),
+            cors_headers: Vec::new(),
         }
     }
 
     pub fn add_route(&mut self, method: &str, path: &str, handler: fn(&HttpRequest) -> HttpResponse) {
+        self.add_route_fn(method, path, handler);
+    }
+
+    // Like add_route, for closures that capture state
+    pub fn add_route_fn<F>(&mut self, method: &str, path: &str, handler: F)
+    where
+        F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
         self.routes.push(Route {
             method: method.to_string(),
             path: path.to_string(),
-            handler,
+            handler: Arc::new(handler),
         });
     }
 
+    // Serve `alias` with the handlers registered for `existing_path`, for every method it has
+    // (e.g. "/healthz" for "/api/health"). Pattern paths work too if the alias uses the same
+    // parameter names. Only routes registered so far are copied; returns how many.
+    pub fn add_alias(&mut self, existing_path: &str, alias: &str) -> usize {
+        let aliased: Vec<Route> = self.routes.iter()
+            .filter(|route| route.path == existing_path)
+            .map(|route| Route { path: alias.to_string(), ..route.clone() })
+            .collect();
+        let count = aliased.len();
+        self.routes.extend(aliased);
+        count
+    }
+
     pub fn set_static_dir(&mut self, dir: &str) {
         self.static_dir = Some(dir.to_string());
     }
 
+    // Serve static files for requests with `Host: host` (any port) from `dir` instead
+    pub fn set_host_static_dir(&mut self, host: &str, dir: &str) {
+        self.host_static_dirs.insert(host.to_ascii_lowercase(), dir.to_string());
+    }
+
+    // Static root for this request: the Host's own directory if one is configured
+    fn static_root_for(&self, request: &HttpRequest) -> Option<&String> {
+        request.headers.get("host")
+            .and_then(|host| self.host_static_dirs.get(&host_name(host).to_ascii_lowercase()))
+            .or(self.static_dir.as_ref())
+    }
+
+    // Lock a username out of /api/login after `max_failures` failures within `window` (0 disables)
+    pub fn set_login_rate_limit(&mut self, max_failures: u32, window: Duration, lockout: Duration) {
+        let store = self.login_limiter.store();
+        self.login_limiter = Arc::new(LoginRateLimiter::new(max_failures, window, lockout).with_store(store));
+    }
+
+    pub fn set_password_policy(&mut self, policy: PasswordPolicy) {
+        self.password_policy = policy;
+    }
+
+    // Issue the login token as a cookie too, and accept it in place of a Bearer header
+    pub fn set_token_cookie(&mut self, cookie: TokenCookie) {
+        self.token_cookie = Some(cookie);
+    }
+
+    pub fn set_csrf_protection(&mut self, enabled: bool) {
+        self.csrf_protection = enabled;
+    }
+
+    // When set, an unauthenticated OPTIONS on a protected path gets the allowed methods
+    // instead of a 401; the route handlers are not run, so no protected data is returned
+    pub fn set_options_without_auth(&mut self, enabled: bool) {
+        self.options_without_auth = enabled;
+    }
+
+    // Persist or share failed-login state through a custom backend
+    pub fn set_login_attempt_store(&mut self, store: Arc<dyn LoginAttemptStore>) {
+        let limiter = LoginRateLimiter::new(
+            self.login_limiter.max_failures(),
+            self.login_limiter.window(),
+            self.login_limiter.lockout(),
+        );
+        self.login_limiter = Arc::new(limiter.with_store(store));
+    }
+
+    // Keep login sessions in a custom backend, e.g. to survive restarts
+    pub fn set_session_store(&mut self, store: Arc<dyn SessionStore>) {
+        self.token_manager = Arc::new(TokenManager::new()
+            .with_store(store)
+            .with_token_format(self.token_manager.token_prefix(), self.token_manager.token_entropy_chars()));
+    }
+
+    // Issue login tokens as `prefix` + `entropy_chars` random hex characters
+    pub fn set_token_format(&mut self, prefix: &str, entropy_chars: usize) {
+        self.token_manager = Arc::new(TokenManager::new()
+            .with_store(self.token_manager.store())
+            .with_token_format(prefix, entropy_chars));
+    }
+
+    // Register a dependency probe consulted by GET /readyz
+    pub fn add_readiness_check<F>(&mut self, name: &str, check: F)
+    where
+        F: Fn() -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.readiness_checks.push((name.to_string(), Arc::new(check)));
+    }
+
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
+        self.middleware.push(Arc::new(middleware));
+    }
+
+    pub fn add_response_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&HttpRequest, &mut HttpResponse) + Send + Sync + 'static,
+    {
+        self.response_hooks.push(Arc::new(hook));
+    }
+
+    // Let every registered hook adjust the response to `request`, in registration order
+    pub fn apply_response_hooks(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        for hook in &self.response_hooks {
+            hook(request, response);
+        }
+    }
+
+    // Grant cross-origin access to `origins`; preflights are told `methods` and `headers` are allowed
+    pub fn set_cors(&mut self, origins: &[String], methods: &[String], headers: &[String]) {
+        self.cors_origins = origins.to_vec();
+        self.cors_methods = methods.iter().map(|method| method.to_ascii_uppercase()).collect();
+        self.cors_headers = headers.to_vec();
+    }
+
+    // Add Access-Control-Allow-* headers when the request's Origin is allowed. Preflights
+    // (OPTIONS with Access-Control-Request-Method) also learn the allowed methods and headers.
+    pub fn apply_cors(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        if self.cors_origins.is_empty() {
+            return;
+        }
+        // The answer depends on Origin even when this one is refused
+        response.add_vary("Origin");
+        let Some(origin) = request.headers.get("origin") else { return };
+        if !self.cors_origins.iter().any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin)) {
+            return;
+        }
+        response.headers.insert("Access-Control-Allow-Origin".to_string(), origin.clone());
+        if Self::is_preflight(request) {
+            response.headers.insert("Access-Control-Allow-Methods".to_string(), self.cors_methods.join(", "));
+            response.headers.insert("Access-Control-Allow-Headers".to_string(), self.cors_headers.join(", "));
+        }
+    }
+
+    fn is_preflight(request: &HttpRequest) -> bool {
+        request.method == "OPTIONS" && request.headers.contains_key("access-control-request-method")
+    }
+
+    // Share the server's drain flag so readiness fails as soon as draining starts
+    pub fn set_drain_flag(&mut self, draining: Arc<AtomicBool>) {
+        self.draining = draining;
+    }
+
+    // Landing response for "/" in minimal setups without a root route or static files
+    pub fn set_root_message(&mut self, message: &str) {
+        self.root_message = Some(message.to_string());
+    }
+
+    pub fn set_root_redirect(&mut self, location: &str) {
+        self.root_redirect = Some(location.to_string());
+    }
+
+    pub fn set_redirect_policy(&mut self, policy: RedirectPolicy) {
+        self.redirect_policy = policy;
+    }
+
+    // Name and version reported by the status endpoints (e.g. for white-labeling)
+    pub fn set_server_identity(&mut self, name: &str, version: &str) {
+        self.server_name = name.to_string();
+        self.server_version = version.to_string();
+    }
+
+    pub fn set_status_endpoint(&mut self, enabled: bool) {
+        self.status_endpoint = enabled;
+    }
+
+    pub fn set_content_sniffing(&mut self, enabled: bool) {
+        self.content_sniffing = enabled;
+    }
+
+    // Log every static file read, and warn about files larger than `warning_bytes` (0 = never)
+    pub fn set_file_read_logging(&mut self, log_reads: bool, warning_bytes: usize) {
+        self.log_file_reads = log_reads;
+        self.large_file_warning_bytes = warning_bytes;
+    }
+
+    // Serve pre-rendered `.br` / `.gz` sidecar files when the client accepts that encoding
+    pub fn set_precompressed(&mut self, enabled: bool) {
+        self.precompressed = enabled;
+    }
+
+    // Refuse static paths deeper than `max_depth` segments (0 = unlimited) or that
+    // pass through a symlink loop, answering with `status` (403 or 404)
+    pub fn set_static_path_limits(&mut self, max_depth: usize, status: u16) {
+        self.max_path_depth = max_depth;
+        self.path_depth_status = status;
+    }
+
+    pub fn set_logger(&mut self, logger: Arc<Logger>) {
+        self.logger = logger;
+    }
+
+    // Share live statistics with the server so /api/stats reports real numbers
+    pub fn set_stats(&mut self, stats: Arc<ServerStats>) {
+        self.stats = stats;
+    }
+
+    pub fn stats(&self) -> Arc<ServerStats> {
+        Arc::clone(&self.stats)
+    }
+
+    pub fn set_metrics_enabled(&mut self, enabled: bool) {
+        self.metrics_enabled = enabled;
+    }
+
+    // Expose the (already redacted) effective configuration at /admin/config
+    pub fn set_effective_config(&mut self, config: JsonValue) {
+        self.effective_config = Some(Arc::new(config));
+    }
+
+    // Sizes and timeouts the server runs with, reported under "performance" by /api/stats
+    pub fn set_performance_settings(&mut self, settings: JsonValue) {
+        self.performance_settings = settings;
+    }
+
+    // Allow certificate rotation through POST /admin/tls/reload
+    pub fn set_tls_acceptor(&mut self, acceptor: Arc<TlsAcceptor>) {
+        self.tls_acceptor = Some(acceptor);
+    }
+
+    // Enable debug recording; the server records every exchange and GET /admin/recordings lists them
+    pub fn set_recorder(&mut self, recorder: Arc<Recorder>) {
+        self.recorder = Some(recorder);
+    }
+
+    pub fn recorder(&self) -> Option<Arc<Recorder>> {
+        self.recorder.clone()
+    }
+
     // Add a user with pre-hashed password (used by configuration loading)
     pub fn add_auth_user(&self, username: &str, password: &str) {
         if let Ok(mut auth_users) = self.auth_users.lock() {
@@ -73,14 +420,118 @@ impl Router {
 
     // Authentication helper - supports Bearer Token only
     fn authenticate(&self, request: &HttpRequest) -> bool {
-        if let Some(auth_header) = request.headers.get("authorization") {
-            if auth_header.starts_with("Bearer ") {
-                // Token-based authentication
-                let token = &auth_header[7..]; // Skip "Bearer "
-                return self.token_manager.validate_token(token).is_some();
+        // Token-based authentication
+        match self.request_token(request) {
+            Some(token) => self.token_manager.validate_token(&token).is_some(),
+            None => false,
+        }
+    }
+
+    // The session token from the Authorization header, or from the token cookie if enabled
+    fn request_token(&self, request: &HttpRequest) -> Option<String> {
+        Self::bearer_token(request).map(str::to_string).or_else(|| self.cookie_token(request))
+    }
+
+    fn cookie_token(&self, request: &HttpRequest) -> Option<String> {
+        let cookie = self.token_cookie.as_ref()?;
+        request.cookies().remove(&cookie.name).filter(|token| !token.is_empty())
+    }
+
+    // Browsers attach cookies automatically, so unsafe requests authenticated only by the
+    // token cookie must also present the session's CSRF token in the X-CSRF-Token header
+    fn csrf_check_failed(&self, request: &HttpRequest) -> bool {
+        if !self.csrf_protection || !matches!(request.method.as_str(), "POST" | "PUT" | "DELETE" | "PATCH") {
+            return false;
+        }
+        if Self::bearer_token(request).is_some() {
+            return false;
+        }
+        let Some(session) = self.cookie_token(request) else {
+            return false;
+        };
+        if self.token_manager.validate_token(&session).is_none() {
+            return false; // not cookie-authenticated; ordinary auth checks apply
+        }
+
+        let expected = self.csrf_tokens.lock().ok().and_then(|tokens| tokens.get(&session).cloned());
+        match (expected, request.headers.get("x-csrf-token")) {
+            (Some(expected), Some(provided)) => !constant_time_eq(expected.as_bytes(), provided.as_bytes()),
+            _ => true,
+        }
+    }
+
+    // Hand out the CSRF token for the caller's session, creating it on first use
+    pub fn handle_csrf_token(&self, request: &HttpRequest) -> HttpResponse {
+        let Some(session) = self.request_token(request)
+            .filter(|token| self.token_manager.validate_token(token).is_some())
+        else {
+            return HttpResponse::new(401, "Unauthorized")
+                .with_json(&error_json("Valid session required"));
+        };
+
+        self.prune_csrf_tokens();
+        let csrf_token = match self.csrf_tokens.lock() {
+            Ok(mut tokens) => tokens.entry(session).or_insert_with(generate_token).clone(),
+            Err(_) => {
+                return HttpResponse::new(500, "Internal Server Error")
+                    .with_json(&error_json("Unable to issue CSRF token"));
+            }
+        };
+
+        let body = JsonValue::object([("csrf_token", csrf_token.into())]);
+        HttpResponse::new(200, "OK")
+            .with_json(&body)
+    }
+
+    // Drop expired sessions, then the CSRF tokens of every session that is no longer valid
+    // (expired, logged out or revoked), so the map never outgrows the live sessions
+    fn prune_csrf_tokens(&self) {
+        self.token_manager.cleanup_expired_tokens();
+        if let Ok(mut csrf_tokens) = self.csrf_tokens.lock() {
+            csrf_tokens.retain(|session, _| self.token_manager.validate_token(session).is_some());
+        }
+    }
+
+    // Bearer credentials from the Authorization header; the scheme is case-insensitive
+    fn bearer_token(request: &HttpRequest) -> Option<&str> {
+        let (scheme, credentials) = parse_authorization(request.headers.get("authorization")?)?;
+        scheme.eq_ignore_ascii_case("Bearer").then_some(credentials)
+    }
+
+    // Every method the server handles on at least one path
+    fn supported_methods(&self) -> Vec<&str> {
+        // Built-in endpoints: static files and stats (GET, and HEAD like every GET), auth endpoints (POST), OPTIONS *
+        let mut methods = vec!["GET", "HEAD", "POST", "OPTIONS"];
+        for route in &self.routes {
+            if !methods.contains(&route.method.as_str()) {
+                methods.push(&route.method);
             }
         }
-        false
+        methods.sort_unstable();
+        methods
+    }
+
+    // Methods of the routes registered for `path` (exact or pattern), for a 405's Allow header
+    fn allowed_methods(&self, path: &str) -> Vec<&str> {
+        let mut methods: Vec<&str> = self.routes.iter()
+            .filter(|route| route.path == path || (route.is_pattern() && route.match_params(path).is_some()))
+            .map(|route| route.method.as_str())
+            .collect();
+        // GET routes also answer HEAD
+        if methods.contains(&"GET") {
+            methods.push("HEAD");
+        }
+        methods.sort_unstable();
+        methods.dedup();
+        methods
+    }
+
+    // 204 listing the methods `path` answers, plus OPTIONS itself
+    fn options_response(&self, path: &str) -> HttpResponse {
+        let mut allowed = self.allowed_methods(path);
+        allowed.push("OPTIONS");
+        allowed.sort_unstable();
+        HttpResponse::new(204, "No Content").with_header("Allow", &allowed.join(", "))
     }
 
     fn is_protected_path(&self, path: &str) -> bool {
@@ -88,7 +539,86 @@ impl Router {
     }
 
     // Create route matching logic
+    // Checks that depend only on the request head. The server runs these before reading the
+    // body so a rejected request never has its body buffered.
+    pub fn reject_early(&self, request: &HttpRequest) -> Option<HttpResponse> {
+        // Asterisk-form target (RFC 7230 5.3.4) addresses the server as a whole and is only valid for OPTIONS
+        if request.path == "*" {
+            if request.method == "OPTIONS" {
+                return Some(HttpResponse::new(204, "No Content")
+                    .with_header("Allow", &self.supported_methods().join(", ")));
+            }
+            return Some(HttpResponse::new(400, "Bad Request")
+                .with_content_type("text/html")
+                .with_body("<h1>400 - Bad Request</h1><p>The asterisk-form target is only valid for OPTIONS.</p>"));
+        }
+
+        if self.csrf_check_failed(request) {
+            return Some(HttpResponse::new(403, "Forbidden")
+                .with_json(&error_json("Missing or invalid CSRF token")));
+        }
+
+        // Check if path requires authentication (browsers never send credentials on a preflight)
+        let path_without_query = request.path.split('?').next().unwrap_or(&request.path);
+        if self.is_protected_path(path_without_query) && !Self::is_preflight(request) && !self.authenticate(request) {
+            if request.method == "OPTIONS" && self.options_without_auth {
+                return Some(self.options_response(path_without_query));
+            }
+            return Some(HttpResponse::new(401, "Unauthorized")
+                .with_json(&JsonValue::object([
+                    ("error", "Unauthorized".into()),
+                    ("message", "Valid Bearer token required to access this resource.".into()),
+                ])));
+        }
+
+        None
+    }
+
+    // HEAD is answered with the response GET would get, body included; the server drops the
+    // body once compression has been negotiated, so the headers match GET's exactly
     pub fn route(&self, request: &HttpRequest) -> HttpResponse {
+        self.run_middleware(0, request)
+    }
+
+    // Run middleware `index` and everything after it, ending in route dispatch
+    fn run_middleware(&self, index: usize, request: &HttpRequest) -> HttpResponse {
+        match self.middleware.get(index) {
+            Some(middleware) => middleware.handle(request, &|request| self.run_middleware(index + 1, request)),
+            None => self.dispatch(request),
+        }
+    }
+
+    // Call the handler registered for `method` on `path`, if any
+    fn dispatch_route(&self, method: &str, path: &str, request: &HttpRequest) -> Option<HttpResponse> {
+        // Handle different URL paths - exact match
+        for route in &self.routes {
+            if route.method == method && route.path == path {
+                return Some((route.handler)(request).with_range(requested_range(request)));
+            }
+        }
+
+        // Then parameterized routes such as /users/:id, in registration order, and last
+        // wildcard routes such as /files/*, the longest prefix first
+        let mut wildcards: Vec<&Route> = self.routes.iter().filter(|route| route.method == method && route.is_wildcard()).collect();
+        wildcards.sort_by_key(|route| std::cmp::Reverse(route.path.len()));
+        let patterns = self.routes.iter().filter(|route| route.method == method && route.is_pattern() && !route.is_wildcard());
+        for route in patterns.chain(wildcards) {
+            if let Some(captures) = route.match_params(path) {
+                let mut request = request.clone();
+                request.params = captures.into_iter()
+                    .map(|(name, value)| (name, percent_decode_path(&value)))
+                    .collect();
+                return Some((route.handler)(&request).with_range(requested_range(&request)));
+            }
+        }
+        None
+    }
+
+    fn dispatch(&self, request: &HttpRequest) -> HttpResponse {
+        if let Some(response) = self.reject_early(request) {
+            return response;
+        }
+
         // Extract path without query parameters for routing
         let path_without_query = if let Some(query_start) = request.path.find('?') {
             &request.path[..query_start]
@@ -96,49 +626,103 @@ impl Router {
             &request.path
         };
 
-        // Check if path requires authentication
-        if self.is_protected_path(path_without_query) {
-            if !self.authenticate(request) {
-                return HttpResponse::new(401, "Unauthorized")
-                    .with_content_type("application/json")
-                    .with_body("{\"error\": \"Unauthorized\", \"message\": \"Valid Bearer token required to access this resource.\"}");
-            }
-        }
-
         // Handle authentication endpoints
         match path_without_query {
             "/api/register" => return self.handle_register(request),
             "/api/login" => return self.handle_login(request),
             "/api/logout" => return self.handle_logout(request),
+            "/api/change-password" => return self.handle_change_password(request),
+            "/api/csrf-token" if request.method == "GET" => return self.handle_csrf_token(request),
+            "/api/status" if request.method == "GET" && self.status_endpoint => return self.handle_status(request),
+            "/api/stats" if request.method == "GET" => return self.handle_stats(request),
+            "/readyz" if request.method == "GET" => return self.handle_readiness(request),
+            "/metrics" if request.method == "GET" && self.metrics_enabled => return self.handle_metrics(request),
+            "/admin/config" if request.method == "GET" && self.effective_config.is_some() => {
+                return self.handle_config_dump(request);
+            }
+            "/admin/recordings" if request.method == "GET" && self.recorder.is_some() => {
+                return self.handle_recordings(request);
+            }
+            "/admin/tls/reload" if request.method == "POST" && self.tls_acceptor.is_some() => {
+                return self.handle_tls_reload(request);
+            }
             _ => {}
         }
 
+        // File names are looked up decoded (e.g. "/my%20file.txt" -> "my file.txt")
+        let static_path = percent_decode_path(path_without_query);
+
         // Handle static file serving first for any path starting with static directory
-        if request.method == "GET" && self.static_dir.is_some() {
-            if let Some(static_dir) = &self.static_dir {
-                // Check if path starts with static directory or is accessing static content
-                if path_without_query.starts_with(&format!("/{}/", static_dir)) || path_without_query == format!("/{}", static_dir) {
-                    if let Some(response) = self.serve_static_file(path_without_query) {
-                        return response;
-                    }
+        let is_head = request.method == "HEAD";
+        if (request.method == "GET" || is_head)
+            && let Some(static_dir) = self.static_root_for(request)
+        {
+            // "/static" redirects to "/static/" so relative links in the listing resolve under it
+            if static_path == format!("/{}", static_dir) && Path::new(static_dir).is_dir() {
+                let query = request.path.find('?').map_or("", |query_start| &request.path[query_start..]);
+                if let Ok(response) = HttpResponse::redirect(302, &format!("/{}/{}", static_dir, query)) {
+                    return response;
                 }
             }
+            // Check if path starts with static directory or is accessing static content
+            if static_path.starts_with(&format!("/{}/", static_dir))
+                && let Some(response) = self.serve_static_file(&static_path, request)
+            {
+                return if is_head { response } else { response.with_range(requested_range(request)) };
+            }
         }
 
-        // Handle different URL paths - exact match
-        for route in &self.routes {
-            if route.method == request.method && route.path == path_without_query {
-                return (route.handler)(request);
-            }
+        if let Some(response) = self.dispatch_route(&request.method, path_without_query, request) {
+            return response;
+        }
+        // HEAD without its own route is answered by the GET handler
+        if is_head && let Some(response) = self.dispatch_route("GET", path_without_query, request) {
+            return response;
         }
 
         // Handle static file serving for root and other paths
-        if request.method == "GET" && self.static_dir.is_some() {
-            if let Some(response) = self.serve_static_file(path_without_query) {
-                return response;
+        if (request.method == "GET" || is_head)
+            && self.static_root_for(request).is_some()
+            && let Some(response) = self.serve_static_file(&static_path, request)
+        {
+            return if is_head { response } else { response.with_range(requested_range(request)) };
+        }
+
+        // Fall back to the configured landing response for the root path
+        if path_without_query == "/" && (request.method == "GET" || request.method == "HEAD") {
+            if let Some(location) = &self.root_redirect {
+                return match HttpResponse::checked_redirect(302, location, request, &self.redirect_policy) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        self.logger.log_warning(&format!("Root redirect refused: {}", e));
+                        HttpResponse::new(500, "Internal Server Error")
+                            .with_content_type("text/plain")
+                            .with_body("Redirect target not allowed")
+                    }
+                };
+            }
+            if let Some(message) = &self.root_message {
+                return HttpResponse::new(200, "OK")
+                    .with_content_type("text/plain")
+                    .with_body(message);
             }
         }
 
+        // The path exists, just not for this method. OPTIONS is answered for any such path.
+        let allowed = self.allowed_methods(path_without_query);
+        if !allowed.is_empty() && request.method == "OPTIONS" {
+            return self.options_response(path_without_query);
+        }
+        if !allowed.is_empty() {
+            return HttpResponse::new(405, "Method Not Allowed")
+                .with_header("Allow", &allowed.join(", "))
+                .with_content_type("text/html")
+                .with_body(&format!(
+                    "<h1>405 - Method Not Allowed</h1><p>{} is not supported for this resource.</p>",
+                    escape_html(&request.method)
+                ));
+        }
+
         // Implement 404 Not Found responses
         HttpResponse::new(404, "Not Found")
             .with_content_type("text/html")
@@ -146,8 +730,9 @@ impl Router {
     }
 
     // Handle static file serving with enhanced error handling and directory listing
-    fn serve_static_file(&self, path: &str) -> Option<HttpResponse> {
-        if let Some(static_dir) = &self.static_dir {
+    fn serve_static_file(&self, path: &str, request: &HttpRequest) -> Option<HttpResponse> {
+        let accept_encoding = request.headers.get("accept-encoding").map(String::as_str);
+        if let Some(static_dir) = self.static_root_for(request) {
             let file_path = if path == "/" {
                 format!("{}/index.html", static_dir)
             } else if path == format!("/{}", static_dir) || path == format!("/{}/", static_dir) {
@@ -169,27 +754,76 @@ impl Router {
                 );
             }
 
+            // Guard against runaway paths, e.g. a crawler following a link -> .. symlink forever
+            let relative = file_path.strip_prefix(static_dir.as_str()).unwrap_or(&file_path);
+            let depth = relative.split('/').filter(|segment| !segment.is_empty()).count();
+            if self.max_path_depth > 0 && depth > self.max_path_depth {
+                return Some(self.path_limit_response());
+            }
+            if let Some(link) = find_symlink_loop(Path::new(static_dir), relative) {
+                self.logger.log_warning(&format!("Symlink loop at {} refused", link.display()));
+                return Some(self.path_limit_response());
+            }
+
             let path_obj = Path::new(&file_path);
             
             if path_obj.exists() {
                 // If it's a directory, serve directory listing
                 if path_obj.is_dir() {
+                    let wants_json = request.headers.get("accept")
+                        .is_some_and(|accept| accept.to_lowercase().contains("application/json"));
+                    if wants_json {
+                        return Some(self.serve_directory_listing_json(&file_path));
+                    }
                     return self.serve_directory_listing(&file_path, path);
                 }
                 
-                // If it's a file, serve the file content
-                match fs::read_to_string(&file_path) {
+                // Prefer a pre-rendered compressed copy the client accepts
+                if self.precompressed
+                    && let Some(response) = self.serve_precompressed(&file_path, request, accept_encoding)
+                {
+                    return Some(response);
+                }
+
+                // Validators for conditional GET; a client whose copy is current gets a 304
+                // without the file being read at all
+                let validators = file_validators(&file_path);
+                if let Some((etag, modified)) = &validators
+                    && is_not_modified(request, etag, *modified)
+                {
+                    let mut response = not_modified_response(etag, *modified);
+                    if self.precompressed {
+                        response = response.with_header("Vary", "Accept-Encoding");
+                    }
+                    return Some(response);
+                }
+
+                // If it's a file, serve the file content. Content-Length comes from the bytes
+                // actually read, never from metadata, so it matches the body even when the
+                // file is being rewritten underneath us.
+                let started = Instant::now();
+                match fs::read(&file_path) {
                     Ok(content) => {
-                        let content_type = self.get_content_type(&file_path);
-                        return Some(
-                            HttpResponse::new(200, "OK")
-                                .with_content_type(&content_type)
-                                .with_body(&content)
-                        );
+                        self.log_file_read(&file_path, content.len(), started.elapsed());
+                        let content_type = self.get_content_type(&file_path, &content);
+                        let mut response = HttpResponse::new(200, "OK")
+                            .with_content_type(&content_type)
+                            .with_body_bytes(&content)
+                            .ranged();
+                        if let Some((etag, modified)) = &validators {
+                            response = response
+                                .with_header("ETag", etag)
+                                .with_header("Last-Modified", &http_date(*modified));
+                        }
+                        // The uncompressed copy is also a choice made from Accept-Encoding
+                        if self.precompressed {
+                            response = response.with_header("Vary", "Accept-Encoding");
+                        }
+                        return Some(response);
                     }
                     Err(e) => {
                         // Log the specific file error
-                        eprintln!("File read error for {}: {}", file_path, e);
+                        self.logger.log_error(&format!("File read error for {}: {}", file_path, e));
                         return Some(
                             HttpResponse::new(500, "Internal Server Error")
                                 .with_content_type("text/html")
@@ -202,6 +836,112 @@ impl Router {
         None
     }
 
+    fn path_limit_response(&self) -> HttpResponse {
+        if self.path_depth_status == 403 {
+            HttpResponse::new(403, "Forbidden")
+                .with_content_type("text/html")
+                .with_body("<h1>403 - Forbidden</h1><p>The requested path is not allowed.</p>")
+        } else {
+            // Same body as an ordinary miss, so the limit is not observable
+            HttpResponse::new(404, "Not Found")
+                .with_content_type("text/html")
+                .with_body("<h1>404 - Page Not Found</h1><p>The requested resource could not be found.</p>")
+        }
+    }
+
+    // Brotli is preferred over gzip when the client accepts both
+    // The sidecar carries its own validators, with the coding in the ETag so a cached gzip
+    // copy is never mistaken for the identity one
+    fn serve_precompressed(&self, file_path: &str, request: &HttpRequest, accept_encoding: Option<&str>) -> Option<HttpResponse> {
+        let accept_encoding = accept_encoding?;
+        for (encoding, extension) in [("br", "br"), ("gzip", "gz")] {
+            if !accepts_encoding(accept_encoding, encoding) {
+                continue;
+            }
+            let sidecar = format!("{}.{}", file_path, extension);
+            if !Path::new(&sidecar).is_file() {
+                continue;
+            }
+            let validators = file_validators(&sidecar)
+                .map(|(etag, modified)| (encoded_etag(&etag, encoding), modified));
+            if let Some((etag, modified)) = &validators
+                && is_not_modified(request, etag, *modified)
+            {
+                return Some(
+                    not_modified_response(etag, *modified)
+                        .with_header("Vary", "Accept-Encoding")
+                );
+            }
+            let started = Instant::now();
+            if let Ok(content) = fs::read(&sidecar) {
+                self.log_file_read(&sidecar, content.len(), started.elapsed());
+                // The type comes from the original name; sniffing would only see compressed bytes
+                let content_type = self.get_content_type(file_path, &[]);
+                let mut response = HttpResponse::new(200, "OK")
+                    .with_content_type(&content_type)
+                    .with_header("Content-Encoding", encoding)
+                    .with_header("Vary", "Accept-Encoding")
+                    .with_body_bytes(&content);
+                if let Some((etag, modified)) = &validators {
+                    response = response
+                        .with_header("ETag", etag)
+                        .with_header("Last-Modified", &http_date(*modified));
+                }
+                return Some(response);
+            }
+        }
+        None
+    }
+
+    fn log_file_read(&self, file_path: &str, size: usize, elapsed: Duration) {
+        if self.log_file_reads {
+            self.logger.log_info(&format!(
+                "Read static file {} ({} bytes in {:.3}ms)", file_path, size, elapsed.as_secs_f64() * 1000.0
+            ));
+        }
+        if self.large_file_warning_bytes > 0 && size > self.large_file_warning_bytes {
+            self.logger.log_warning(&format!(
+                "Large static response: {} is {} bytes (warning threshold {})", file_path, size, self.large_file_warning_bytes
+            ));
+        }
+    }
+
+    // Directory listing for programmatic clients: a JSON array of
+    // {name, is_dir, size, modified} with modified in Unix seconds (null if unavailable)
+    fn serve_directory_listing_json(&self, dir_path: &str) -> HttpResponse {
+        let entries = match fs::read_dir(dir_path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.logger.log_error(&format!("Directory read error for {}: {}", dir_path, e));
+                return HttpResponse::new(500, "Internal Server Error")
+                    .with_json(&error_json("Unable to read directory"));
+            }
+        };
+
+        let mut listing: Vec<(String, JsonValue)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_str()?.to_string();
+                let metadata = fs::metadata(entry.path()).ok()?;
+                let modified = metadata.modified().ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(JsonValue::Null, |since_epoch| since_epoch.as_secs().into());
+                let item = JsonValue::object([
+                    ("name", name.as_str().into()),
+                    ("is_dir", metadata.is_dir().into()),
+                    ("size", metadata.len().into()),
+                    ("modified", modified),
+                ]);
+                Some((name, item))
+            })
+            .collect();
+        listing.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let body = JsonValue::Array(listing.into_iter().map(|(_, item)| item).collect());
+        HttpResponse::new(200, "OK")
+            .with_json(&body)
+    }
+
     // Add directory listing functionality
     fn serve_directory_listing(&self, dir_path: &str, request_path: &str) -> Option<HttpResponse> {
         match fs::read_dir(dir_path) {
@@ -224,12 +964,8 @@ impl Router {
                 html.push_str(&format!("<h1>📁 Directory Listing: {}</h1>\n", request_path));
                 
                 // Add navigation back to parent directory if not at root
-                if request_path != "/" && request_path != "" {
-                    let parent_path = if request_path.ends_with('/') {
-                        &request_path[..request_path.len()-1]
-                    } else {
-                        request_path
-                    };
+                if request_path != "/" && !request_path.is_empty() {
+                    let parent_path = request_path.strip_suffix('/').unwrap_or(request_path);
                     
                     if let Some(last_slash) = parent_path.rfind('/') {
                         let parent = if last_slash == 0 { "/" } else { &parent_path[..last_slash] };
@@ -295,18 +1031,49 @@ impl Router {
         }
     }
 
-    // Handle different MIME types
-    fn get_content_type(&self, file_path: &str) -> String {
-        match file_path.split('.').last() {
-            Some("html") => "text/html".to_string(),
-            Some("css") => "text/css".to_string(),
-            Some("js") => "application/javascript".to_string(),
-            Some("json") => "application/json".to_string(),
-            Some("png") => "image/png".to_string(),
-            Some("jpg") | Some("jpeg") => "image/jpeg".to_string(),
-            Some("gif") => "image/gif".to_string(),
-            Some("txt") => "text/plain".to_string(),
-            _ => "text/plain".to_string(),
+    // Handle different MIME types, falling back to content sniffing for unknown extensions
+    fn get_content_type(&self, file_path: &str, content: &[u8]) -> String {
+        let by_extension = match file_path.split('.').next_back() {
+            Some("html") => Some("text/html"),
+            Some("css") => Some("text/css"),
+            Some("js") => Some("application/javascript"),
+            Some("json") => Some("application/json"),
+            Some("png") => Some("image/png"),
+            Some("jpg") | Some("jpeg") => Some("image/jpeg"),
+            Some("gif") => Some("image/gif"),
+            Some("txt") => Some("text/plain"),
+            _ => None,
+        };
+
+        by_extension
+            .or_else(|| if self.content_sniffing { sniff_content_type(content) } else { None })
+            .unwrap_or("text/plain")
+            .to_string()
+    }
+
+    // Parse an application/x-www-form-urlencoded body into decoded key/value pairs
+    pub fn parse_form_urlencoded(body: &str) -> HashMap<String, String> {
+        body.split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(key), percent_decode(value))
+            })
+            .collect()
+    }
+
+    // Username and password from a login/registration body, which may be JSON
+    // or form-encoded depending on the Content-Type
+    fn parse_credentials(request: &HttpRequest) -> Option<(String, String)> {
+        let is_form = request.headers.get("content-type")
+            .map(|content_type| content_type.to_lowercase().starts_with("application/x-www-form-urlencoded"))
+            .unwrap_or(false);
+
+        if is_form {
+            let mut form = Self::parse_form_urlencoded(request.body_str().unwrap_or(""));
+            Some((form.remove("username")?, form.remove("password")?))
+        } else {
+            parse_login_request(request.body_str().unwrap_or(""))
         }
     }
 
@@ -320,9 +1087,9 @@ impl Router {
                 if let Some(eq_pos) = pair.find('=') {
                     let key = &pair[..eq_pos];
                     let value = &pair[eq_pos + 1..];
-                    params.insert(key.to_string(), value.to_string());
+                    params.insert(percent_decode(key), percent_decode(value));
                 } else {
-                    params.insert(pair.to_string(), String::new());
+                    params.insert(percent_decode(pair), String::new());
                 }
             }
         }
@@ -330,23 +1097,195 @@ impl Router {
         params
     }
 
+    pub fn handle_status(&self, _request: &HttpRequest) -> HttpResponse {
+        let body = JsonValue::object([
+            ("status", "ok".into()),
+            ("server", self.server_name.as_str().into()),
+            ("version", self.server_version.as_str().into()),
+        ]);
+        HttpResponse::new(200, "OK")
+            .with_json(&body)
+    }
+
+    /// Handle performance statistics endpoint
+    pub fn handle_stats(&self, _request: &HttpRequest) -> HttpResponse {
+        let by_class = self.stats.responses_by_class();
+        let features = ["multi_threading", "connection_pooling", "buffered_io", "keep_alive", "chunked_encoding", "authentication"];
+        let body = JsonValue::object([
+            ("server", self.server_name.as_str().into()),
+            ("version", self.server_version.as_str().into()),
+            ("features", JsonValue::object(features.map(|feature| (feature, true.into())))),
+            ("performance", self.performance_settings.clone()),
+            ("uptime_seconds", self.stats.uptime().as_secs().into()),
+            ("connections", JsonValue::object([
+                ("active", self.stats.active_connections().into()),
+                ("idle", self.stats.idle_connections().into()),
+                ("accepted", self.stats.connections_total().into()),
+                ("rejected", self.stats.connections_rejected().into()),
+                ("requests_served", self.stats.requests_total().into()),
+                ("requests_per_connection", JsonValue::Number((self.stats.requests_per_connection() * 100.0).round() / 100.0)),
+            ])),
+            ("responses", JsonValue::object([
+                ("1xx", by_class[0].into()),
+                ("2xx", by_class[1].into()),
+                ("3xx", by_class[2].into()),
+                ("4xx", by_class[3].into()),
+                ("5xx", by_class[4].into()),
+            ])),
+        ]);
+
+        HttpResponse::new(200, "OK")
+            .with_json(&body)
+    }
+
+    // Cumulative counters plus rates since the previous scrape, in Prometheus text format.
+    // Counters wrap at 2^64; the interval rates stay correct across a wrap.
+    pub fn handle_metrics(&self, _request: &HttpRequest) -> HttpResponse {
+        let (totals, interval) = self.stats.next_interval();
+        let mut body = String::new();
+        for (name, help, value) in [
+            ("http_requests_total", "Requests served since start", totals.requests_total),
+            ("http_received_bytes_total", "Request bytes read since start", totals.bytes_received),
+            ("http_sent_bytes_total", "Response bytes written since start", totals.bytes_sent),
+            ("http_connections_total", "Connections accepted since start", self.stats.connections_total()),
+        ] {
+            body.push_str(&format!("# HELP {} {}\n# TYPE {} counter\n{} {}\n", name, help, name, name, value));
+        }
+        for (name, help, value) in [
+            ("http_requests_per_second", "Request rate since the previous scrape", interval.rate(interval.requests)),
+            ("http_received_bytes_per_second", "Receive rate since the previous scrape", interval.rate(interval.bytes_received)),
+            ("http_sent_bytes_per_second", "Send rate since the previous scrape", interval.rate(interval.bytes_sent)),
+            ("http_metrics_interval_seconds", "Length of the rate interval", interval.elapsed.as_secs_f64()),
+            ("http_requests_per_connection", "Average requests served per connection", self.stats.requests_per_connection()),
+        ] {
+            body.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n{} {:.3}\n", name, help, name, name, value));
+        }
+
+        HttpResponse::new(200, "OK")
+            .with_content_type("text/plain; version=0.0.4")
+            .with_body(&body)
+    }
+
+    // Run every registered readiness check; 503 lists the ones that failed
+    pub fn handle_readiness(&self, _request: &HttpRequest) -> HttpResponse {
+        // A draining server is deliberately unready so load balancers stop sending traffic
+        if self.draining.load(Ordering::SeqCst) {
+            let body = JsonValue::object([("status", "draining".into())]);
+            return HttpResponse::new(503, "Service Unavailable")
+                .with_json(&body);
+        }
+
+        let mut checks = Vec::new();
+        let mut failed = Vec::new();
+        for (name, check) in &self.readiness_checks {
+            match check() {
+                Ok(()) => checks.push(JsonValue::object([
+                    ("name", name.as_str().into()),
+                    ("ok", true.into()),
+                ])),
+                Err(reason) => {
+                    checks.push(JsonValue::object([
+                        ("name", name.as_str().into()),
+                        ("ok", false.into()),
+                        ("error", reason.into()),
+                    ]));
+                    failed.push(JsonValue::from(name.as_str()));
+                }
+            }
+        }
+
+        let ready = failed.is_empty();
+        let body = JsonValue::object([
+            ("status", if ready { "ready" } else { "unavailable" }.into()),
+            ("failed", JsonValue::Array(failed)),
+            ("checks", JsonValue::Array(checks)),
+        ]);
+        let response = if ready {
+            HttpResponse::new(200, "OK")
+        } else {
+            HttpResponse::new(503, "Service Unavailable")
+        };
+        response
+            .with_json(&body)
+    }
+
+    /// Handle the effective configuration endpoint (always requires a valid token)
+    pub fn handle_config_dump(&self, request: &HttpRequest) -> HttpResponse {
+        if !self.authenticate(request) {
+            return HttpResponse::new(401, "Unauthorized")
+                .with_json(&error_json("Valid Bearer token required"));
+        }
+
+        match &self.effective_config {
+            Some(config) => HttpResponse::new(200, "OK")
+                .with_json(config),
+            None => HttpResponse::new(404, "Not Found")
+                .with_json(&error_json("Configuration endpoint is not enabled")),
+        }
+    }
+
+    /// List the recorded exchanges, oldest first (always requires a valid token)
+    pub fn handle_recordings(&self, request: &HttpRequest) -> HttpResponse {
+        if !self.authenticate(request) {
+            return HttpResponse::new(401, "Unauthorized")
+                .with_json(&error_json("Valid Bearer token required"));
+        }
+        let Some(recorder) = &self.recorder else {
+            return HttpResponse::new(404, "Not Found")
+                .with_json(&error_json("Recording is not enabled"));
+        };
+
+        let entries = recorder.entries().iter().map(|exchange| exchange.to_json()).collect();
+        HttpResponse::new(200, "OK")
+            .with_json(&JsonValue::Array(entries))
+    }
+
+    /// Re-read the TLS certificate and key for new connections (always requires a valid token)
+    pub fn handle_tls_reload(&self, request: &HttpRequest) -> HttpResponse {
+        if !self.authenticate(request) {
+            return HttpResponse::new(401, "Unauthorized")
+                .with_json(&error_json("Valid Bearer token required"));
+        }
+        let Some(acceptor) = &self.tls_acceptor else {
+            return HttpResponse::new(404, "Not Found")
+                .with_json(&error_json("TLS is not enabled"));
+        };
+
+        match acceptor.reload() {
+            Ok(()) => {
+                self.logger.log_info("TLS certificates reloaded");
+                HttpResponse::new(200, "OK")
+                    .with_json(&JsonValue::object([("status", "reloaded".into())]))
+            }
+            Err(e) => {
+                self.logger.log_error(&format!("TLS certificate reload failed: {}", e));
+                HttpResponse::new(500, "Internal Server Error")
+                    .with_json(&error_json(&e.to_string()))
+            }
+        }
+    }
+
     /// Handle user registration endpoint
     pub fn handle_register(&self, request: &HttpRequest) -> HttpResponse {
         if request.method != "POST" {
             return HttpResponse::new(405, "Method Not Allowed")
-                .with_content_type("application/json")
-                .with_body(&create_error_response("Only POST method allowed"));
+                .with_json(&error_json("Only POST method allowed"));
         }
 
-        // Parse JSON body
-        if let Some((username, password)) = parse_login_request(&request.body) {
+        // Parse JSON or form-encoded body
+        if let Some((username, password)) = Self::parse_credentials(request) {
             // Check if user already exists
-            if let Ok(auth_users) = self.auth_users.lock() {
-                if auth_users.contains_key(&username) {
-                    return HttpResponse::new(409, "Conflict")
-                        .with_content_type("application/json")
-                        .with_body(&create_error_response("Username already exists"));
-                }
+            if let Ok(auth_users) = self.auth_users.lock()
+                && auth_users.contains_key(&username)
+            {
+                return HttpResponse::new(409, "Conflict")
+                    .with_json(&error_json("Username already exists"));
+            }
+
+            // Reject passwords that don't meet the configured policy
+            if let Err(message) = self.password_policy.validate(&password) {
+                return HttpResponse::new(400, "Bad Request")
+                    .with_json(&error_json(&message));
             }
 
             // Hash the password and store the user
@@ -360,12 +1299,10 @@ impl Router {
             let token = self.token_manager.generate_token(&username);
             
             HttpResponse::new(201, "Created")
-                .with_content_type("application/json")
-                .with_body(&create_login_response(&token))
+                .with_json(&login_json(&token))
         } else {
             HttpResponse::new(400, "Bad Request")
-                .with_content_type("application/json")
-                .with_body(&create_error_response("Invalid JSON format. Expected {\"username\": \"...\", \"password\": \"...\"}"))
+                .with_json(&error_json("Invalid JSON format. Expected {\"username\": \"...\", \"password\": \"...\"}"))
         }
     }
 
@@ -373,33 +1310,90 @@ impl Router {
     pub fn handle_login(&self, request: &HttpRequest) -> HttpResponse {
         if request.method != "POST" {
             return HttpResponse::new(405, "Method Not Allowed")
-                .with_content_type("application/json")
-                .with_body(&create_error_response("Only POST method allowed"));
+                .with_json(&error_json("Only POST method allowed"));
         }
 
-        // Parse JSON body
-        if let Some((username, password)) = parse_login_request(&request.body) {
+        // Parse JSON or form-encoded body
+        if let Some((username, password)) = Self::parse_credentials(request) {
+            // Refuse attempts for a username that is locked out after repeated failures
+            if let Some(retry_after) = self.login_limiter.check(&username) {
+                let retry_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                return HttpResponse::new(429, "Too Many Requests")
+                    .with_header("Retry-After", &retry_secs.to_string())
+                    .with_json(&error_json("Too many failed login attempts. Try again later."));
+            }
+
             // Verify credentials
-            if let Ok(auth_users) = self.auth_users.lock() {
-                if let Some(stored_hash) = auth_users.get(&username) {
-                    if verify_password(&password, stored_hash) {
-                        // Generate a token for the user
-                        let token = self.token_manager.generate_token(&username);
-                        
-                        return HttpResponse::new(200, "OK")
-                            .with_content_type("application/json")
-                            .with_body(&create_login_response(&token));
-                    }
-                }
+            if let Ok(auth_users) = self.auth_users.lock()
+                && let Some(stored_hash) = auth_users.get(&username)
+                && verify_password(&password, stored_hash)
+            {
+                self.login_limiter.record_success(&username);
+
+                // Generate a token for the user
+                let token = self.token_manager.generate_token(&username);
+
+                let response = HttpResponse::new(200, "OK")
+                    .with_json(&login_json(&token));
+                return match &self.token_cookie {
+                    Some(cookie) => response.with_header("Set-Cookie", &cookie.set_cookie(&token)),
+                    None => response,
+                };
             }
             
+            self.login_limiter.record_failure(&username);
             HttpResponse::new(401, "Unauthorized")
-                .with_content_type("application/json")
-                .with_body(&create_error_response("Invalid username or password"))
+                .with_json(&error_json("Invalid username or password"))
         } else {
             HttpResponse::new(400, "Bad Request")
-                .with_content_type("application/json")
-                .with_body(&create_error_response("Invalid JSON format. Expected {\"username\": \"...\", \"password\": \"...\"}"))
+                .with_json(&error_json("Invalid JSON format. Expected {\"username\": \"...\", \"password\": \"...\"}"))
+        }
+    }
+
+    /// Handle password change endpoint: requires a valid token and the current password,
+    /// revokes every existing token of the user and returns a fresh one
+    pub fn handle_change_password(&self, request: &HttpRequest) -> HttpResponse {
+        if request.method != "POST" {
+            return HttpResponse::new(405, "Method Not Allowed")
+                .with_json(&error_json("Only POST method allowed"));
+        }
+
+        let Some(username) = self.request_token(request).and_then(|token| self.token_manager.validate_token(&token)) else {
+            return HttpResponse::new(401, "Unauthorized")
+                .with_json(&error_json("Valid Bearer token required to change the password"));
+        };
+        let Some((current_password, new_password)) = parse_change_password_request(request.body_str().unwrap_or("")) else {
+            return HttpResponse::new(400, "Bad Request")
+                .with_json(&error_json("Invalid JSON format. Expected {\"current_password\": \"...\", \"new_password\": \"...\"}"));
+        };
+
+        // A stolen token alone must not be enough to take over the account
+        let current_ok = self.auth_users.lock().ok()
+            .and_then(|auth_users| auth_users.get(&username).map(|stored_hash| verify_password(&current_password, stored_hash)))
+            .unwrap_or(false);
+        if !current_ok {
+            return HttpResponse::new(403, "Forbidden")
+                .with_json(&error_json("Current password is incorrect"));
+        }
+        if let Err(message) = self.password_policy.validate(&new_password) {
+            return HttpResponse::new(400, "Bad Request")
+                .with_json(&error_json(&message));
+        }
+
+        let salt = generate_salt();
+        if let Ok(mut auth_users) = self.auth_users.lock() {
+            auth_users.insert(username.clone(), hash_password(&new_password, &salt));
+        }
+        let revoked = self.token_manager.revoke_all_for_user(&username);
+        self.prune_csrf_tokens();
+        self.logger.log_info(&format!("Password changed for {}; {} token(s) revoked", username, revoked));
+
+        let token = self.token_manager.generate_token(&username);
+        let response = HttpResponse::new(200, "OK")
+            .with_json(&login_json(&token));
+        match &self.token_cookie {
+            Some(cookie) => response.with_header("Set-Cookie", &cookie.set_cookie(&token)),
+            None => response,
         }
     }
 
@@ -407,25 +1401,186 @@ impl Router {
     pub fn handle_logout(&self, request: &HttpRequest) -> HttpResponse {
         if request.method != "POST" {
             return HttpResponse::new(405, "Method Not Allowed")
-                .with_content_type("application/json")
-                .with_body(&create_error_response("Only POST method allowed"));
+                .with_json(&error_json("Only POST method allowed"));
         }
 
         // Extract token from Authorization header
-        if let Some(auth_header) = request.headers.get("authorization") {
-            if auth_header.starts_with("Bearer ") {
-                let token = &auth_header[7..]; // Skip "Bearer "
-                
-                if self.token_manager.revoke_token(token) {
-                    return HttpResponse::new(200, "OK")
-                        .with_content_type("application/json")
-                        .with_body(r#"{"success": true, "message": "Logged out successfully"}"#);
-                }
+        if let Some(token) = self.request_token(request)
+            && self.token_manager.revoke_token(&token)
+        {
+            if let Ok(mut csrf_tokens) = self.csrf_tokens.lock() {
+                csrf_tokens.remove(&token);
             }
+            let response = HttpResponse::new(200, "OK")
+                .with_json(&JsonValue::object([("success", true.into()), ("message", "Logged out successfully".into())]));
+            return match &self.token_cookie {
+                Some(cookie) => response.with_header("Set-Cookie", &cookie.clear_cookie()),
+                None => response,
+            };
         }
         
         HttpResponse::new(400, "Bad Request")
-            .with_content_type("application/json")
-            .with_body(&create_error_response("Invalid or missing token"))
+            .with_json(&error_json("Invalid or missing token"))
+    }
+}
+
+// First symlink on `relative` (under `root`) that points back at one of its own ancestors,
+// e.g. `docs/loop -> ..`. Following such a link again and again never reaches anything new.
+fn find_symlink_loop(root: &Path, relative: &str) -> Option<PathBuf> {
+    let mut current = root.to_path_buf();
+    for segment in relative.split('/').filter(|segment| !segment.is_empty()) {
+        let parent = current.clone();
+        current.push(segment);
+        let Ok(metadata) = fs::symlink_metadata(&current) else {
+            return None; // missing entries are reported as a plain 404 later
+        };
+        if !metadata.file_type().is_symlink() {
+            continue;
+        }
+        match (fs::canonicalize(&current), fs::canonicalize(&parent)) {
+            (Ok(target), Ok(parent)) if target.is_dir() && parent.starts_with(&target) => return Some(current),
+            // The OS gives up on links that resolve through themselves (ELOOP)
+            (Err(e), _) if e.raw_os_error() == Some(ELOOP) => return Some(current),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+const ELOOP: i32 = 40;
+#[cfg(not(target_os = "linux"))]
+const ELOOP: i32 = 62;
+
+// Range only applies to GET; other methods always get the full response
+fn requested_range(request: &HttpRequest) -> Option<&str> {
+    if request.method != "GET" {
+        return None;
     }
+    request.headers.get("range").map(String::as_str)
+}
+
+// Weak validator from the file size and modification time; weak because a rewrite within
+// the timestamp resolution that keeps the size would not change it
+fn weak_etag(len: u64, modified: SystemTime) -> String {
+    let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("W/\"{:x}-{:x}.{:x}\"", len, since_epoch.as_secs(), since_epoch.subsec_nanos())
+}
+
+// ETag and modification time of a file on disk, if its metadata can be read
+fn file_validators(path: &str) -> Option<(String, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?;
+    Some((weak_etag(metadata.len(), modified), modified))
+}
+
+// The ETag of a content-coded copy: the same tag with the coding appended inside the quotes
+fn encoded_etag(etag: &str, encoding: &str) -> String {
+    format!("{}-{}\"", etag.trim_end_matches('"'), encoding)
+}
+
+fn not_modified_response(etag: &str, modified: SystemTime) -> HttpResponse {
+    HttpResponse::new(304, "Not Modified")
+        .with_header("ETag", etag)
+        .with_header("Last-Modified", &http_date(modified))
+}
+
+// Whether a GET or HEAD can be answered with 304 (RFC 7232 section 6). If-None-Match wins
+// when present and uses weak comparison; If-Modified-Since is compared at one-second
+// resolution, and a date that cannot be parsed is ignored.
+fn is_not_modified(request: &HttpRequest, etag: &str, modified: SystemTime) -> bool {
+    if request.method != "GET" && request.method != "HEAD" {
+        return false;
+    }
+    if let Some(if_none_match) = request.headers.get("if-none-match") {
+        let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+        return if_none_match.split(',').any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag));
+    }
+    let Some(since) = request.headers.get("if-modified-since").and_then(|date| parse_http_date(date)) else {
+        return false;
+    };
+    let modified_secs = modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let since_secs = since.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    modified_secs <= since_secs
+}
+
+// Whether an Accept-Encoding header value allows `coding`. An explicit entry wins over "*",
+// and q=0 means "not acceptable".
+pub(crate) fn accepts_encoding(accept_encoding: &str, coding: &str) -> bool {
+    let mut wildcard = None;
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or("").trim();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if name.eq_ignore_ascii_case(coding) {
+            return quality > 0.0;
+        }
+        if name == "*" {
+            wildcard = Some(quality > 0.0);
+        }
+    }
+    wildcard.unwrap_or(false)
+}
+
+/// Decode %XX escapes and '+' (space) as used in URLs and form bodies.
+/// Malformed escapes are kept literally; invalid UTF-8 is replaced.
+pub fn percent_decode(input: &str) -> String {
+    decode_percent_escapes(input, true)
+}
+
+// Path segments use %XX escapes only; '+' is a literal plus sign there
+fn percent_decode_path(path: &str) -> String {
+    decode_percent_escapes(path, false)
+}
+
+fn decode_percent_escapes(input: &str, plus_as_space: bool) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' if plus_as_space => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Detect a MIME type from the leading bytes of a file's content
+fn sniff_content_type(content: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"\x1f\x8b", "application/gzip"),
+    ];
+
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| content.starts_with(magic)) {
+        return Some(mime);
+    }
+
+    // HTML documents may start with whitespace before the doctype or root element
+    let start = content.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(content.len());
+    let head = content[start..].get(..14).unwrap_or(&content[start..]).to_ascii_lowercase();
+    if head.starts_with(b"<!doctype html") || head.starts_with(b"<html") {
+        return Some("text/html");
+    }
+
+    None
 }
@@ -1,21 +1,89 @@
-use std::net::{TcpListener, TcpStream};
+use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::io::prelude::*;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::io::ErrorKind;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use super::{
-    ServerError, Logger, HttpRequest, HttpResponse, Router, ThreadPool, 
-    ConnectionPool, BufferedStream, ServerConfig
+    ServerError, RequestParseError, Logger, hex_encode, JsonValue, HttpRequest, HttpResponse, TransferSupport, Router, ThreadPool, 
+    ConnectionPool, BufferedStream, Connection, LineTooLong, ServerConfig, ParseMode, ServerStats, LoginAttemptStore,
+    SessionStore, Middleware, SpooledBody, Recorder, RequestIdMiddleware, RedirectPolicy
 };
+use super::tls::TlsAcceptor;
+use super::router::accepts_encoding;
+use super::response::escape_html;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+// Bodies of rejected requests up to this size are skipped to keep the connection alive;
+// larger ones close the connection instead
+const MAX_DISCARDED_BODY_BYTES: usize = 1024 * 1024;
+
+// Upper bound on how often the idle-connection reaper wakes up
+const MAX_REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+// How often a saturated accept loop checks whether a connection slot has been freed
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+
+/// Flags shared between the accept loop and its drain handles
+struct Lifecycle {
+    draining: Arc<AtomicBool>, // readiness fails, traffic is still served
+    stopping: AtomicBool, // the accept loop exits at the next wake-up
+    shut_down: AtomicBool, // workers have been joined
+}
+
+/// Lets another thread take a running server out of rotation and then stop it, either
+/// after a grace period (`begin_drain`) or right away (`stop`)
+#[derive(Clone)]
+pub struct DrainHandle {
+    lifecycle: Arc<Lifecycle>,
+    wake_addr: SocketAddr,
+}
+
+impl DrainHandle {
+    /// Start draining: /readyz reports 503 at once, normal requests keep being served
+    /// for `grace`, after which the server stops accepting and `start()` returns
+    pub fn begin_drain(&self, grace: Duration) {
+        if self.lifecycle.draining.swap(true, Ordering::SeqCst) {
+            return; // already draining
+        }
+        let handle = self.clone();
+        thread::spawn(move || {
+            thread::sleep(grace);
+            handle.wake_to_stop();
+        });
+    }
+
+    /// Stop accepting connections now. `start()` then finishes the requests already
+    /// running, joins the worker threads and returns Ok(()).
+    pub fn stop(&self) {
+        self.lifecycle.draining.store(true, Ordering::SeqCst);
+        self.wake_to_stop();
+    }
+
+    fn wake_to_stop(&self) {
+        if self.lifecycle.stopping.swap(true, Ordering::SeqCst) {
+            return; // already stopping
+        }
+        // Unblock the accept loop so it notices the stop flag
+        let _ = TcpStream::connect_timeout(&self.wake_addr, Duration::from_secs(1));
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.lifecycle.draining.load(Ordering::SeqCst)
+    }
+}
 
 pub struct HttpServer {
     listener: TcpListener,
     router: Router,
-    logger: Logger,
+    logger: Arc<Logger>,
     thread_pool: ThreadPool,
-    #[allow(dead_code)] // TODO: implement connection pooling
-    connection_pool: ConnectionPool,
-    config: ServerConfig,
+    connection_pool: Arc<ConnectionPool>,
+    config: Arc<ServerConfig>,
+    lifecycle: Arc<Lifecycle>,
+    tls: Option<Arc<TlsAcceptor>>, // accepted connections are wrapped in TLS when set
 }
 
 impl HttpServer {
@@ -34,26 +102,69 @@ impl HttpServer {
 
     fn from_config_and_listener(config: ServerConfig, listener: TcpListener) -> Result<Self, ServerError> {
         let mut router = Router::new();
-        let logger = Logger::new();
+        let logger = if config.logging.file.is_empty() { Logger::new() } else { Logger::with_file(&config.logging.file) };
+        let logger = Arc::new(logger.with_config(&config.logging));
+        router.set_logger(Arc::clone(&logger));
+        let stats = Arc::new(ServerStats::new());
+        router.set_stats(Arc::clone(&stats));
+        let lifecycle = Arc::new(Lifecycle {
+            draining: Arc::new(AtomicBool::new(false)),
+            stopping: AtomicBool::new(false),
+            shut_down: AtomicBool::new(false),
+        });
+        router.set_drain_flag(Arc::clone(&lifecycle.draining));
         
         // Initialize thread pool with config values
         let thread_pool = ThreadPool::new(
             config.threading.worker_threads, 
             config.threading.max_concurrent_connections
         );
+        thread_pool.set_logger(Arc::clone(&logger));
         
         // Initialize connection pool with config values
-        let connection_pool = ConnectionPool::new(
+        let connection_pool = Arc::new(ConnectionPool::new(
             config.connection.max_idle_connections, 
             config.connection.idle_timeout_seconds
-        );
+        ).with_stats(stats));
         
+        // Serve the redacted effective configuration for debugging deployments
+        router.set_effective_config(config.to_redacted_json());
+        router.set_performance_settings(JsonValue::object([
+            ("thread_pool_size", config.threading.worker_threads.into()),
+            ("max_connections", config.threading.max_concurrent_connections.into()),
+            ("buffer_size", config.connection.buffer_size.into()),
+            ("read_timeout_seconds", config.server.read_timeout_seconds.into()),
+            ("idle_timeout_seconds", config.connection.idle_timeout_seconds.into()),
+        ]));
+        router.set_metrics_enabled(config.server.metrics_enabled);
+        let version = if config.server.server_version.is_empty() { env!("CARGO_PKG_VERSION") } else { &config.server.server_version };
+        router.set_server_identity(&config.server.server_name, version);
+
         // Configure static files
         if config.static_files.enabled {
             router.set_static_dir(&config.static_files.directory);
+            router.set_content_sniffing(config.static_files.content_sniffing);
+            router.set_precompressed(config.static_files.precompressed);
+            router.set_file_read_logging(config.static_files.log_file_reads, config.static_files.large_file_warning_bytes);
+            router.set_static_path_limits(config.static_files.max_path_depth, config.static_files.path_depth_status);
+            for (host, directory) in &config.static_files.host_directories {
+                router.set_host_static_dir(host, directory);
+            }
         }
         
         // Configure authentication
+        router.set_login_rate_limit(
+            config.authentication.max_failed_logins,
+            Duration::from_secs(config.authentication.failed_login_window_seconds),
+            Duration::from_secs(config.authentication.login_lockout_seconds),
+        );
+        router.set_password_policy(config.password_policy());
+        if let Some(cookie) = config.token_cookie() {
+            router.set_token_cookie(cookie);
+        }
+        router.set_csrf_protection(config.authentication.csrf_protection);
+        router.set_options_without_auth(config.authentication.options_without_auth);
+        router.set_token_format(&config.authentication.token_prefix, config.authentication.token_entropy_chars);
         if config.authentication.enabled {
             for (username, password) in &config.authentication.users {
                 router.add_auth_user(username, password);
@@ -63,17 +174,57 @@ impl HttpServer {
             }
         }
         
+        if config.recording.enabled {
+            let mut recorder = Recorder::new(config.recording.max_entries, config.recording.max_body_bytes);
+            if !config.recording.file.is_empty() {
+                recorder = recorder.with_file(&config.recording.file)?;
+            }
+            router.set_recorder(Arc::new(recorder));
+        }
+
+        // Registered before any other middleware so every dispatched response carries the id
+        if !config.server.request_id_header.is_empty() {
+            router.add_middleware(RequestIdMiddleware::with_header(&config.server.request_id_header));
+        }
+
+        if config.cors.is_enabled() {
+            router.set_cors(&config.cors.allowed_origins, &config.cors.allowed_methods, &config.cors.allowed_headers);
+        }
+
+        // Landing response for "/" when neither a route nor a static file handles it
+        if !config.server.root_redirect.is_empty() {
+            router.set_root_redirect(&config.server.root_redirect);
+        }
+        // The configured root redirect is trusted, so its own host is always allowed
+        let redirect_policy = RedirectPolicy::new()
+            .with_allowed_hosts(&config.server.redirect_allowed_hosts)
+            .allowing_target(&config.server.root_redirect);
+        router.set_redirect_policy(redirect_policy);
+        if !config.server.root_message.is_empty() {
+            router.set_root_message(&config.server.root_message);
+        }
+        
         // Add some default routes
-        router.add_route("GET", "/", Self::handle_home);
-        router.add_route("GET", "/hello", Self::handle_hello);
-        router.add_route("HEAD", "/hello", Self::handle_hello_head);
-        router.add_route("GET", "/api/status", Self::handle_status);
-        router.add_route("GET", "/api/stats", Self::handle_stats);
-        router.add_route("POST", "/api/echo", Self::handle_echo);
-        router.add_route("GET", "/admin", Self::handle_admin);
-        router.add_route("GET", "/chunked", Self::handle_chunked_demo);
+        if config.server.default_routes {
+            router.add_route("GET", "/", Self::handle_home);
+            router.add_route("GET", "/hello", Self::handle_hello);
+            router.set_status_endpoint(true);
+            router.add_route("POST", "/api/echo", Self::handle_echo);
+            router.add_route("POST", "/api/echo/raw", Self::handle_echo_raw);
+            router.add_route("GET", "/admin", Self::handle_admin);
+            router.add_route("GET", "/chunked", Self::handle_chunked_demo);
+        }
+        
+        // A [tls] section with both files switches the listener to HTTPS
+        let tls = if config.tls.is_enabled() {
+            let acceptor = Arc::new(TlsAcceptor::from_files(&config.tls.cert_file, &config.tls.key_file)?);
+            router.set_tls_acceptor(Arc::clone(&acceptor));
+            Some(acceptor)
+        } else {
+            None
+        };
         
-        Ok(HttpServer { listener, router, logger, thread_pool, connection_pool, config })
+        Ok(HttpServer { listener, router, logger, thread_pool, connection_pool, config: Arc::new(config), lifecycle, tls })
     }
 
     #[allow(dead_code)] // Public API method
@@ -81,6 +232,72 @@ impl HttpServer {
         self.router.add_route(method, path, handler);
     }
 
+    // Register a closure handler, e.g. one holding an Arc to shared application state
+    #[allow(dead_code)] // Public API method
+    pub fn add_route_fn<F>(&mut self, method: &str, path: &str, handler: F)
+    where
+        F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.router.add_route_fn(method, path, handler);
+    }
+
+    // Serve `alias` with the handlers already registered for `existing_path`
+    #[allow(dead_code)] // Public API method
+    pub fn add_alias(&mut self, existing_path: &str, alias: &str) -> usize {
+        self.router.add_alias(existing_path, alias)
+    }
+
+    #[allow(dead_code)] // Public API method
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = Arc::new(logger);
+        self.router.set_logger(Arc::clone(&self.logger));
+        self.thread_pool.set_logger(Arc::clone(&self.logger));
+    }
+
+    #[allow(dead_code)] // Public API method
+    pub fn set_login_attempt_store(&mut self, store: Arc<dyn LoginAttemptStore>) {
+        self.router.set_login_attempt_store(store);
+    }
+
+    #[allow(dead_code)] // Public API method
+    pub fn set_session_store(&mut self, store: Arc<dyn SessionStore>) {
+        self.router.set_session_store(store);
+    }
+
+    #[allow(dead_code)] // Public API method
+    pub fn add_readiness_check<F>(&mut self, name: &str, check: F)
+    where
+        F: Fn() -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.router.add_readiness_check(name, check);
+    }
+
+    // Wrap route dispatch in `middleware`; the first one added runs outermost
+    #[allow(dead_code)] // Public API method
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
+        self.router.add_middleware(middleware);
+    }
+
+    // Register a hook that sees each routed response before it is sent. Malformed
+    // requests that never parsed are answered without running hooks.
+    #[allow(dead_code)] // Public API method
+    pub fn add_response_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&HttpRequest, &mut HttpResponse) + Send + Sync + 'static,
+    {
+        self.router.add_response_hook(hook);
+    }
+
+    /// Re-read the [tls] certificate and key files; new connections use them, existing
+    /// ones keep the certificate they were accepted with
+    #[allow(dead_code)] // Public API method
+    pub fn reload_certificates(&self) -> Result<(), ServerError> {
+        match &self.tls {
+            Some(acceptor) => acceptor.reload(),
+            None => Err(ServerError::TlsError("TLS is not enabled".to_string())),
+        }
+    }
+
     #[allow(dead_code)] // Public API method
     pub fn set_static_dir(&mut self, dir: &str) {
         self.router.set_static_dir(dir);
@@ -106,22 +323,43 @@ impl HttpServer {
         &self.config
     }
 
+    /// Handle for draining or stopping the server from another thread while `start()` runs
+    pub fn drain_handle(&self) -> Result<DrainHandle, ServerError> {
+        let mut wake_addr = self.listener.local_addr()?;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(std::net::Ipv4Addr::LOCALHOST.into());
+        }
+        Ok(DrainHandle { lifecycle: Arc::clone(&self.lifecycle), wake_addr })
+    }
+
     pub fn start(&self) -> Result<(), ServerError> {
         let addr = self.listener.local_addr()?;
-        self.logger.log_info(&format!("HTTP Server starting on http://{}", addr));
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        self.logger.log_info(&format!("HTTP Server starting on {}://{}", scheme, addr));
         self.logger.log_info(&format!("Thread pool initialized with {} workers", self.config.threading.worker_threads));
         self.logger.log_info(&format!("Maximum concurrent connections: {}", self.thread_pool.get_max_connections()));
+        self.spawn_idle_reaper();
         
-        // Set read timeout for connections to handle timeout errors
-        for stream in self.listener.incoming() {
+        loop {
+            // Backpressure: while every connection slot is taken, leave new connections
+            // queued by the OS instead of accepting them only to answer 503
+            self.wait_for_free_slot();
+            let stream = self.listener.accept().map(|(stream, _)| stream);
+            if self.lifecycle.stopping.load(Ordering::SeqCst) {
+                self.logger.log_info("Drain complete; no longer accepting connections");
+                self.shutdown();
+                break;
+            }
             match stream {
                 Ok(stream) => {
+                    let open_connection = self.router.stats().open_connection();
+
                     // Get client address for logging
                     let client_addr = stream.peer_addr()
                         .map(|addr| addr.to_string())
                         .unwrap_or_else(|_| "unknown".to_string());
                     
-                    self.logger.log_info(&format!("New connection from {} (Active: {})", 
+                    Self::log_connection_event(&self.logger, &self.config, &format!("New connection from {} (Active: {})", 
                         client_addr, self.thread_pool.get_active_connections()));
                     
                     // Add timeout handling for connections using config values
@@ -131,32 +369,36 @@ impl HttpServer {
                     
                     // Use thread pool to handle connection concurrently
                     let router = Arc::new(self.router.clone());
-                    let logger = Arc::new(Logger::new());
+                    let logger = Arc::clone(&self.logger);
+                    let config = Arc::clone(&self.config);
+                    let connection_pool = Arc::clone(&self.connection_pool);
                     let client_addr_clone = client_addr.clone();
+                    let error_logger = Arc::clone(&self.logger);
+                    let tls = self.tls.clone();
                     
                     // Try to clone the stream for the rejection case
-                    let stream_clone = match stream.try_clone() {
-                        Ok(cloned) => Some(cloned),
-                        Err(_) => None,
-                    };
+                    let stream_clone = stream.try_clone().ok();
                     
                     match self.thread_pool.execute(move || {
-                        if let Err(e) = Self::handle_connection_threaded(stream, &client_addr_clone, router, logger) {
-                            eprintln!("Connection error for {}: {:?}", client_addr_clone, e);
+                        let _open_connection = open_connection;
+                        if let Err(e) = Self::handle_connection_threaded(stream, &client_addr_clone, router, logger, config, connection_pool, tls) {
+                            error_logger.log_error(&format!("Connection error for {}: {}", client_addr_clone, e));
                         }
                     }) {
                         Ok(()) => {
                             // Connection successfully queued for processing
                         }
                         Err(err) => {
+                            self.router.stats().record_rejected_connection();
                             self.logger.log_warning(&format!("Connection rejected from {}: {}", client_addr, err));
-                            // Send 503 Service Unavailable and close connection if we have a stream clone
-                            if let Some(mut reject_stream) = stream_clone {
+                            // Send 503 Service Unavailable and close connection if we have a stream clone.
+                            // A TLS client would not understand a plaintext answer, so it is just closed.
+                            if let Some(mut reject_stream) = stream_clone.filter(|_| self.tls.is_none()) {
                                 let response = HttpResponse::new(503, "Service Unavailable")
                                     .with_content_type("text/html")
                                     .with_connection("close")
                                     .with_body("<h1>503 - Service Unavailable</h1><p>Server is too busy to handle your request.</p>");
-                                let _ = reject_stream.write_all(response.format().as_bytes());
+                                let _ = reject_stream.write_all(&response.format());
                             }
                         }
                     }
@@ -183,133 +425,532 @@ impl HttpServer {
         Ok(())
     }
 
+    /// Finish in-flight requests and stop the worker threads; keep-alive connections are
+    /// closed after their current response. `start()` does this itself when it returns after
+    /// a drain or stop, and calling it again is a no-op.
+    pub fn shutdown(&self) {
+        if self.lifecycle.shut_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.connection_pool.close_all_idle();
+        self.thread_pool.shutdown();
+        self.logger.log_info("All workers finished; server shut down");
+    }
+
+    // Block while the thread pool is at its connection limit. Returns at once when a drain
+    // stops the server, so the accept loop can notice it.
+    fn wait_for_free_slot(&self) {
+        while self.thread_pool.get_active_connections() >= self.thread_pool.get_max_connections()
+            && !self.lifecycle.stopping.load(Ordering::SeqCst)
+        {
+            thread::sleep(ACCEPT_BACKOFF);
+        }
+    }
+
+    // Periodically close keep-alive connections idle past idle_timeout_seconds. The thread
+    // exits once the server stops or is dropped.
+    fn spawn_idle_reaper(&self) {
+        let timeout = self.connection_pool.idle_timeout();
+        if timeout.is_zero() {
+            return;
+        }
+        let interval = (timeout / 2).min(MAX_REAP_INTERVAL);
+        let pool = Arc::downgrade(&self.connection_pool);
+        let lifecycle = Arc::clone(&self.lifecycle);
+        let logger = Arc::clone(&self.logger);
+        thread::spawn(move || {
+            while !lifecycle.stopping.load(Ordering::SeqCst) {
+                thread::sleep(interval);
+                let Some(pool) = pool.upgrade() else { break };
+                let reaped = pool.reap_idle();
+                if reaped > 0 {
+                    logger.log_info(&format!("Closed {} idle keep-alive connection(s)", reaped));
+                }
+            }
+        });
+    }
+
     // New threaded connection handler for use with thread pool
     fn handle_connection_threaded(
         stream: TcpStream, 
         client_addr: &str, 
         router: Arc<Router>, 
-        logger: Arc<Logger>
+        logger: Arc<Logger>,
+        config: Arc<ServerConfig>,
+        connection_pool: Arc<ConnectionPool>,
+        tls: Option<Arc<TlsAcceptor>>,
+    ) -> Result<(), ServerError> {
+        // The pool keeps its own handle to the socket so it can shut down idle connections
+        let socket = stream.try_clone()?;
+        match tls {
+            Some(acceptor) => {
+                let tls_stream = match acceptor.accept(stream) {
+                    Ok(tls_stream) => tls_stream,
+                    Err(e) => {
+                        logger.log_warning(&format!("TLS handshake with {} failed: {}", client_addr, e));
+                        return Ok(());
+                    }
+                };
+                // Use buffered I/O for better performance
+                let buffered_stream = BufferedStream::new(tls_stream, config.connection.buffer_size)
+                    .with_max_line_length(config.connection.max_line_length)
+                    .with_max_head_size(config.connection.max_header_bytes)
+                    .with_max_body_size(config.connection.max_request_body_bytes)
+                    .with_spooling(config.connection.body_spool_threshold_bytes, config.connection.max_spooled_body_bytes);
+                Self::serve_connection(buffered_stream, &socket, client_addr, &router, &logger, &config, &connection_pool)
+            }
+            None => {
+                let buffered_stream = BufferedStream::new(stream, config.connection.buffer_size)
+                    .with_max_line_length(config.connection.max_line_length)
+                    .with_max_head_size(config.connection.max_header_bytes)
+                    .with_max_body_size(config.connection.max_request_body_bytes)
+                    .with_spooling(config.connection.body_spool_threshold_bytes, config.connection.max_spooled_body_bytes);
+                Self::serve_connection(buffered_stream, &socket, client_addr, &router, &logger, &config, &connection_pool)
+            }
+        }
+    }
+
+    // Request/response loop for one connection, plain or TLS
+    fn serve_connection<S: Connection>(
+        mut buffered_stream: BufferedStream<S>,
+        socket: &TcpStream,
+        client_addr: &str,
+        router: &Router,
+        logger: &Logger,
+        config: &ServerConfig,
+        connection_pool: &ConnectionPool,
     ) -> Result<(), ServerError> {
-        // Use buffered I/O for better performance
-        let mut buffered_stream = BufferedStream::new(stream.try_clone().unwrap(), 8192);
+        let connection_id = connection_pool.next_connection_id();
+        let mut requests_served = 0;
+        // Requests in the current run of pipelined requests, counting the one that started it
+        let mut pipelined_run = 0;
         
         // Support multiple requests per connection (HTTP keep-alive)
         loop {
-            // Read incoming HTTP request using buffered I/O
-            let request_data = match buffered_stream.read_request() {
-                Ok(data) => {
-                    if data.trim().is_empty() {
-                        logger.log_info(&format!("Client {} closed connection", client_addr));
+            pipelined_run = if buffered_stream.has_buffered_input() { pipelined_run + 1 } else { 1 };
+
+            // Read the request head; the body is only read once the request is accepted
+            let read_result = buffered_stream.read_head();
+            // The connection is busy again (or closing); it no longer counts as idle
+            connection_pool.checkout(connection_id);
+
+            let (request_data, content_length) = match read_result {
+                Ok((data, content_length)) => {
+                    if data.iter().all(|b| b.is_ascii_whitespace()) {
+                        Self::log_connection_event(logger, config, &format!("Client {} closed connection", client_addr));
                         return Ok(());
                     }
-                    logger.log_info(&format!("Received request from {}", client_addr));
-                    data
-                }
-                Err(e) => {
-                    match e.kind() {
-                        ErrorKind::TimedOut => {
-                            logger.log_warning(&format!("Read timeout for client {}", client_addr));
-                            let response = HttpResponse::new(408, "Request Timeout")
-                                .with_content_type("text/plain")
-                                .with_body("Request timed out");
-                            let _ = buffered_stream.write_response(&response.format());
-                            let _ = buffered_stream.flush();
-                            return Err(ServerError::TimeoutError);
-                        }
-                        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => {
-                            logger.log_warning(&format!("Connection reset by client {}", client_addr));
-                            return Ok(());
-                        }
-                        ErrorKind::UnexpectedEof => {
-                            logger.log_info(&format!("Client {} closed connection", client_addr));
-                            return Ok(());
-                        }
-                        _ => {
-                            logger.log_error(&format!("Read error from {}: {}", client_addr, e));
-                            return Err(ServerError::IoError(e));
-                        }
-                    }
+                    Self::log_connection_event(logger, config, &format!("Received request from {}", client_addr));
+                    (data, content_length)
                 }
+                Err(e) => return Self::handle_read_error(e, &mut buffered_stream, client_addr, logger, config, requests_served),
             };
-            
+            // Handling time for the access log, from a complete head to a ready response
+            let started = Instant::now();
+            // Body bytes of a rejected request, skipped after the response is sent
+            let mut unread_body = 0;
+            // A rejected chunked body cannot be skipped by length; the connection closes instead
+            let mut unread_chunked_body = false;
+
             // Handle malformed HTTP requests gracefully
-            let (response, should_keep_alive) = match HttpRequest::parse(&request_data) {
-                Ok(request) => {
-                    // Check if client wants to keep connection alive
-                    let connection_header = request.headers.get("connection")
-                        .map(|s| s.to_lowercase())
-                        .unwrap_or_else(|| {
-                            // Default behavior based on HTTP version
-                            if request.version == "HTTP/1.1" {
-                                "keep-alive".to_string()
+            let parse_mode = if config.server.strict_parsing { ParseMode::Strict } else { ParseMode::Tolerant };
+            // Body bytes actually taken off the wire for this request; a chunked body counts
+            // at its decoded size, and a rejected request's unread body counts as nothing
+            let mut body_bytes_read = 0;
+            let (mut response, should_keep_alive, transfer) = match HttpRequest::parse_bytes(&request_data, parse_mode) {
+                Ok(mut request) => {
+                    let mut keep_alive = Self::wants_keep_alive(&request, config)
+                        && Self::has_requests_left(config, requests_served + 1)
+                        && Self::within_pipeline_limit(config, pipelined_run);
+
+                    // Head-only checks (auth, CSRF) run before the body is read, so a rejected
+                    // request never has its body buffered
+                    let has_body = content_length > 0 || request.has_chunked_body();
+                    let mut response = match router.reject_early(&request) {
+                        Some(rejection) => {
+                            unread_body = content_length;
+                            unread_chunked_body = request.has_chunked_body();
+                            // A client waiting for 100 Continue may or may not send its body
+                            // after the final response, so the body cannot be skipped reliably
+                            if content_length > MAX_DISCARDED_BODY_BYTES || unread_chunked_body || (has_body && request.expects_continue()) {
+                                keep_alive = false; // cheaper to drop the connection than drain it
+                            }
+                            rejection
+                        }
+                        None => {
+                            // The request is accepted; let a client that asked for it send the body
+                            if has_body && request.expects_continue()
+                                && let Err(e) = Self::send_continue(&mut buffered_stream)
+                            {
+                                return Self::handle_read_error(e, &mut buffered_stream, client_addr, logger, config, requests_served);
+                            }
+                            let body_result = if request.has_chunked_body() {
+                                // The decoded body replaces the chunk framing, as if it came with a Content-Length
+                                buffered_stream.read_chunked_body().map(|body| request.set_body(body))
+                            } else if buffered_stream.spools_body(content_length) {
+                                Self::spool_body(&mut buffered_stream, content_length, config).map(|spooled| request.set_spooled_body(spooled))
                             } else {
-                                "close".to_string()
+                                buffered_stream.read_body(content_length).map(|body| request.set_body(body))
+                            };
+                            if let Err(e) = body_result {
+                                return Self::handle_read_error(e, &mut buffered_stream, client_addr, logger, config, requests_served);
                             }
-                        });
-                    
-                    let keep_alive = connection_header.contains("keep-alive");
-                    
-                    // Use router for request handling
-                    let mut response = router.route(&request);
-                    
-                    // Add connection header to response
-                    if keep_alive {
-                        response = response.with_connection("keep-alive");
-                    } else {
-                        response = response.with_connection("close");
+                            body_bytes_read = request.body_len();
+                            router.route(&request)
+                        }
+                    };
+
+                    router.apply_cors(&request, &mut response);
+                    router.apply_response_hooks(&request, &mut response);
+                    let keep_alive = Self::apply_connection_policy(&mut response, keep_alive, config, requests_served);
+                    Self::compress_response(&request, &mut response, config);
+                    if request.method == "HEAD" {
+                        response = response.without_body();
+                    }
+                    logger.log_response(&request.method, &request.path, response.status_code, &response.body);
+                    if let Some(recorder) = router.recorder() {
+                        recorder.record(&request, &response);
                     }
                     
-                    // Check if client accepts chunked encoding
-                    let supports_chunked = request.headers.get("te")
-                        .map(|encoding| encoding.contains("chunked"))
-                        .unwrap_or(true); // Default to supporting chunked for HTTP/1.1
-                    
-                    logger.log_request(&request.method, &request.path, response.status_code, client_addr);
-                    (response, keep_alive && supports_chunked)
+                    let request_id = Self::response_header(&response, &config.server.request_id_header);
+                    logger.log_request_timed(&request.method, &request.path, response.status_code, client_addr, started.elapsed(), request_id);
+                    (response, keep_alive, request.transfer_support())
                 }
                 Err(parse_error) => {
                     // Log errors appropriately
                     logger.log_warning(&format!("Malformed request from {}: {}", client_addr, parse_error));
-                    logger.log_request("INVALID", "N/A", 400, client_addr);
-                    
-                    let response = HttpResponse::new(400, "Bad Request")
-                        .with_content_type("text/html")
-                        .with_connection("close")
-                        .with_body("<h1>400 - Bad Request</h1><p>The request could not be parsed.</p>");
-                    (response, false)
+                    let response = Self::parse_error_response(&parse_error);
+                    logger.log_request("INVALID", "N/A", response.status_code, client_addr);
+                    (response, false, TransferSupport::default())
                 }
             };
 
             // Send response with buffered I/O
-            let formatted_response = if response.headers.contains_key("Transfer-Encoding") {
-                // Use chunked encoding if Transfer-Encoding header is present
-                response.format_chunked()
-            } else {
-                response.format()
-            };
+            let formatted_response = Self::format_for_client(&mut response, transfer, config);
 
             match buffered_stream.write_response(&formatted_response) {
                 Ok(_) => {
                     if let Err(e) = buffered_stream.flush() {
+                        if Self::is_client_disconnect(e.kind()) {
+                            Self::log_connection_event(logger, config, &format!("Client {} closed connection ({})", client_addr, e));
+                            return Ok(());
+                        }
                         logger.log_warning(&format!("Failed to flush response to {}: {}", client_addr, e));
                     }
                 }
+                Err(e) if Self::is_client_disconnect(e.kind()) => {
+                    Self::log_connection_event(logger, config, &format!("Client {} closed connection ({})", client_addr, e));
+                    return Ok(());
+                }
                 Err(e) => {
                     logger.log_error(&format!("Failed to send response to {}: {}", client_addr, e));
                     return Err(ServerError::IoError(e));
                 }
             }
 
-            // Check if we should close the connection
-            if !should_keep_alive || response.headers.get("Connection").map(|c| c.to_lowercase().contains("close")).unwrap_or(false) {
-                logger.log_info(&format!("Closing connection to {}", client_addr));
+            requests_served += 1;
+            router.stats().record_request(request_data.len() as u64 + body_bytes_read, formatted_response.len() as u64);
+            router.stats().record_status(response.status_code);
+
+            // Skip the body of a request rejected before it was read. When closing instead,
+            // linger briefly so the client still receives the response.
+            if unread_body > 0 {
+                if !should_keep_alive {
+                    Self::log_connection_event(logger, config, &format!("Closing connection to {} without reading request body", client_addr));
+                    buffered_stream.close_lingering(Duration::from_millis(500));
+                    return Ok(());
+                }
+                if let Err(e) = buffered_stream.discard_body(unread_body) {
+                    Self::log_connection_event(logger, config, &format!("Closing connection to {} while skipping request body ({})", client_addr, e));
+                    return Ok(());
+                }
+            }
+
+            // Check if we should close the connection. Requests the client pipelined after this
+            // one go unanswered; linger so they do not reset the connection before it reads ours.
+            if !should_keep_alive {
+                Self::log_connection_event(logger, config, &format!("Closing connection to {}", client_addr));
+                if buffered_stream.has_buffered_input() || unread_chunked_body {
+                    buffered_stream.close_lingering(Duration::from_millis(500));
+                }
                 break;
             }
+
+            // Hold the connection as idle until the next request arrives. If the pool
+            // is over capacity the oldest idle connection is shut down to make room.
+            if let Ok(idle_stream) = socket.try_clone() {
+                connection_pool.checkin(connection_id, idle_stream);
+            }
         }
 
         Ok(())
     }
 
+    // Parse, route and format one complete request without any socket, e.g. to embed the
+    // router in-process. The result is exactly what would be written to the connection.
+    #[allow(dead_code)] // Public API method
+    pub fn handle_raw(&self, raw_request: &[u8]) -> Vec<u8> {
+        let parse_mode = if self.config.server.strict_parsing { ParseMode::Strict } else { ParseMode::Tolerant };
+        let (mut response, transfer) = match HttpRequest::parse_bytes(raw_request, parse_mode) {
+            Ok(request) => {
+                let mut response = self.router.route(&request);
+                self.router.apply_cors(&request, &mut response);
+                self.router.apply_response_hooks(&request, &mut response);
+                let keep_alive = Self::wants_keep_alive(&request, &self.config) && Self::has_requests_left(&self.config, 1);
+                Self::apply_connection_policy(&mut response, keep_alive, &self.config, 0);
+                Self::compress_response(&request, &mut response, &self.config);
+                if request.method == "HEAD" {
+                    response = response.without_body();
+                }
+                if let Some(recorder) = self.router.recorder() {
+                    recorder.record(&request, &response);
+                }
+                (response, request.transfer_support())
+            }
+            Err(parse_error) => (Self::parse_error_response(&parse_error), TransferSupport::default()),
+        };
+        Self::format_for_client(&mut response, transfer, &self.config)
+    }
+
+    // Value of the response header `name` in any letter case; None for an empty name
+    fn response_header<'a>(response: &'a HttpResponse, name: &str) -> Option<&'a str> {
+        if name.is_empty() {
+            return None;
+        }
+        response.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Interim response telling a client that sent Expect: 100-continue to go ahead with the body
+    fn send_continue<S: Connection>(buffered_stream: &mut BufferedStream<S>) -> Result<(), std::io::Error> {
+        buffered_stream.write_response(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        buffered_stream.flush()
+    }
+
+    // Stream a large request body to a temp file in [connection] body_spool_directory. The
+    // file is deleted if copying fails, and otherwise once the request is dropped.
+    fn spool_body<S: Connection>(
+        buffered_stream: &mut BufferedStream<S>,
+        content_length: usize,
+        config: &ServerConfig,
+    ) -> Result<SpooledBody, std::io::Error> {
+        let (mut spooled, file) = SpooledBody::create(&config.connection.body_spool_directory)?;
+        let mut writer = std::io::BufWriter::new(file);
+        let copied = buffered_stream.copy_body_to(content_length, &mut writer)?;
+        writer.flush()?;
+        spooled.set_len(copied as u64);
+        Ok(spooled)
+    }
+
+    // Settle the Connection header once the handler and response hooks are done. The server's
+    // keep-alive decision stands unless the response already asks to close, which is always
+    // honoured; a response can never hold open a connection the server is closing. Whatever
+    // Connection and Keep-Alive headers the response carried (in any letter case) are
+    // replaced, so exactly one of each is sent. Returns whether the connection stays open.
+    fn apply_connection_policy(response: &mut HttpResponse, keep_alive: bool, config: &ServerConfig, requests_served: usize) -> bool {
+        let asks_to_close = response.headers.iter()
+            .any(|(key, value)| key.eq_ignore_ascii_case("connection") && value.to_ascii_lowercase().contains("close"));
+        response.headers.retain(|key, _| !key.eq_ignore_ascii_case("connection") && !key.eq_ignore_ascii_case("keep-alive"));
+
+        let keep_alive = keep_alive && !asks_to_close;
+        if keep_alive {
+            response.headers.insert("Connection".to_string(), "keep-alive".to_string());
+            response.headers.insert("Keep-Alive".to_string(), Self::keep_alive_policy(config, requests_served));
+        } else {
+            response.headers.insert("Connection".to_string(), "close".to_string());
+        }
+        keep_alive
+    }
+
+    // Whether a connection that has served `requests_served` requests may serve another
+    fn has_requests_left(config: &ServerConfig, requests_served: usize) -> bool {
+        let max_requests = config.connection.max_requests_per_connection;
+        max_requests == 0 || requests_served < max_requests
+    }
+
+    // A client that queues requests back to back keeps its worker busy; after
+    // max_pipelined_requests of them the connection is closed so others get a turn
+    fn within_pipeline_limit(config: &ServerConfig, pipelined_run: usize) -> bool {
+        let max_pipelined = config.connection.max_pipelined_requests;
+        max_pipelined == 0 || pipelined_run < max_pipelined
+    }
+
+    // Keep-Alive header value advertising how long an idle connection is kept and how many
+    // more requests it will serve, counting the current one
+    fn keep_alive_policy(config: &ServerConfig, requests_served: usize) -> String {
+        let timeout = format!("timeout={}", config.connection.idle_timeout_seconds);
+        match config.connection.max_requests_per_connection {
+            0 => timeout,
+            max_requests => format!("{}, max={}", timeout, max_requests - requests_served),
+        }
+    }
+
+    // HTTP/1.1 defaults to keep-alive (unless [connection] default_keep_alive is off), HTTP/1.0
+    // to close, unless the Connection header says otherwise
+    fn wants_keep_alive(request: &HttpRequest, config: &ServerConfig) -> bool {
+        match request.headers.get("connection") {
+            Some(connection) => connection.to_lowercase().contains("keep-alive"),
+            None => request.version == "HTTP/1.1" && config.connection.default_keep_alive,
+        }
+    }
+
+    // gzip the body when compression is enabled, the client accepts gzip and the body is a
+    // large enough compressible type. Partial and already-encoded responses are left alone, and
+    // chunked ones unless [compression] chunked_responses is on.
+    fn compress_response(request: &HttpRequest, response: &mut HttpResponse, config: &ServerConfig) {
+        let settings = &config.compression;
+        if !settings.enabled || response.body.len() <= settings.min_size_bytes || response.status_code == 206 {
+            return;
+        }
+        let has_header = |name: &str| response.headers.keys().any(|key| key.eq_ignore_ascii_case(name));
+        let chunked = has_header("transfer-encoding") || !response.trailers.is_empty();
+        if has_header("content-encoding") || (chunked && !settings.chunked_responses) {
+            return;
+        }
+        let compressible = response.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("content-type"))
+            .is_some_and(|(_, content_type)| settings.is_compressible(content_type));
+        if !compressible {
+            return;
+        }
+        // The encoding now depends on Accept-Encoding, whichever way it goes, so caches must
+        // keep the variants apart
+        response.add_vary("Accept-Encoding");
+        if !request.headers.get("accept-encoding").is_some_and(|value| accepts_encoding(value, "gzip")) {
+            return;
+        }
+
+        // A chunked response keeps its framing and is compressed as its chunks are written
+        if chunked {
+            response.gzip_chunks = true;
+            return;
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let Ok(compressed) = encoder.write_all(&response.body).and_then(|_| encoder.finish()) else {
+            return; // send it uncompressed rather than fail the request
+        };
+        response.body = compressed;
+        response.headers.insert("Content-Length".to_string(), response.body.len().to_string());
+        response.headers.insert("Content-Encoding".to_string(), "gzip".to_string());
+    }
+
+    // The specific failure is given both as a stable code (X-Parse-Error and the body) and
+    // as a readable message, so clients can tell e.g. a bad request line from a bad header
+    fn parse_error_response(parse_error: &RequestParseError) -> HttpResponse {
+        let (status_code, status_text) = parse_error.status();
+        HttpResponse::new(status_code, status_text)
+            .with_header("X-Parse-Error", parse_error.code())
+            .with_content_type("text/html")
+            .with_connection("close")
+            .with_body(&format!(
+                "<h1>{} - {}</h1><p>The request could not be parsed: {}</p><p>Error code: <code>{}</code></p>",
+                status_code, status_text, escape_html(&parse_error.to_string()), parse_error.code()
+            ))
+    }
+
+    // Serialize a response with the framing this client can read. Bodies above the configured
+    // threshold are sent chunked; smaller ones keep their Content-Length framing.
+    fn format_for_client(response: &mut HttpResponse, transfer: TransferSupport, config: &ServerConfig) -> Vec<u8> {
+        // Trailers are only sent to clients that asked for them with "TE: trailers"
+        if !transfer.trailers {
+            response.trailers.clear();
+        }
+
+        let threshold = config.connection.chunked_threshold_bytes;
+        let exceeds_threshold = threshold > 0 && response.body.len() > threshold;
+        let wants_chunked = response.headers.keys().any(|key| key.eq_ignore_ascii_case("transfer-encoding"))
+            || exceeds_threshold
+            || !response.trailers.is_empty();
+        if wants_chunked && transfer.chunked {
+            // Use chunked encoding if Transfer-Encoding header is present (trailers also need chunking)
+            response.format_chunked()
+        } else if wants_chunked {
+            // HTTP/1.0 clients cannot read chunked framing; fall back to Content-Length
+            response.remove_chunked_framing();
+            response.format()
+        } else {
+            response.format()
+        }
+    }
+
+    // Answer (where appropriate) and log a failed read of a request head or body
+    fn handle_read_error<S: Connection>(
+        e: std::io::Error,
+        buffered_stream: &mut BufferedStream<S>,
+        client_addr: &str,
+        logger: &Logger,
+        config: &ServerConfig,
+        requests_served: usize,
+    ) -> Result<(), ServerError> {
+        // An over-long request line or header line gets 414/431, an oversized head 431, an
+        // oversized body 413, a malformed chunked body 400, and the connection closes
+        let parse_error = LineTooLong::from_io_error(&e).map(RequestParseError::from)
+            .or_else(|| e.get_ref().and_then(|inner| inner.downcast_ref::<RequestParseError>()).cloned());
+        if let Some(error) = parse_error {
+            let response = Self::parse_error_response(&error);
+            logger.log_warning(&format!("{} from {}", error, client_addr));
+            logger.log_request("INVALID", "N/A", response.status_code, client_addr);
+            let _ = buffered_stream.write_response(&response.format());
+            let _ = buffered_stream.flush();
+            buffered_stream.close_lingering(Duration::from_millis(500));
+            return Ok(());
+        }
+        match e.kind() {
+            // Read timeouts surface as WouldBlock on Unix and TimedOut on Windows
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => {
+                // A keep-alive connection that already served requests timing out
+                // is an ordinary idle close; optionally tell the client with a 408
+                let idle = requests_served > 0;
+                if !idle || config.connection.send_408_on_idle_timeout {
+                    Self::send_error_response(buffered_stream, &ServerError::TimeoutError);
+                }
+                if idle {
+                    Self::log_connection_event(logger, config, &format!("Idle keep-alive connection to {} timed out", client_addr));
+                    return Ok(());
+                }
+                logger.log_warning(&format!("Read timeout for client {}", client_addr));
+                Err(ServerError::TimeoutError)
+            }
+            // A client going away (typically while idle on keep-alive) is a normal close
+            kind if Self::is_client_disconnect(kind) => {
+                Self::log_connection_event(logger, config, &format!("Client {} closed connection ({})", client_addr, e));
+                Ok(())
+            }
+            _ => {
+                logger.log_error(&format!("Read error from {}: {}", client_addr, e));
+                let error = ServerError::IoError(e);
+                Self::send_error_response(buffered_stream, &error);
+                Err(error)
+            }
+        }
+    }
+
+    // Connection lifecycle lines are only logged with [logging] log_connections enabled;
+    // access log lines are written regardless
+    fn log_connection_event(logger: &Logger, config: &ServerConfig, message: &str) {
+        if config.logging.log_connections {
+            logger.log_info(message);
+        }
+    }
+
+    // Best-effort final response for a connection that is closing because of `error`
+    fn send_error_response<S: Read + Write>(buffered_stream: &mut BufferedStream<S>, error: &ServerError) {
+        if let Some(response) = HttpResponse::from_error(error) {
+            let _ = buffered_stream.write_response(&response.format());
+            let _ = buffered_stream.flush();
+        }
+    }
+
+    // Errors that mean the peer closed or reset the connection rather than a server fault
+    fn is_client_disconnect(kind: ErrorKind) -> bool {
+        matches!(
+            kind,
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe | ErrorKind::UnexpectedEof
+        )
+    }
+
     // Route handlers
     fn handle_home(request: &HttpRequest) -> HttpResponse {
         let query_params = Router::parse_query_params(&request.path);
@@ -324,7 +965,7 @@ impl HttpServer {
         if !query_params.is_empty() {
             body.push_str("<h3>Query Parameters:</h3><ul>");
             for (key, value) in query_params {
-                body.push_str(&format!("<li>{}: {}</li>", key, value));
+                body.push_str(&format!("<li>{}: {}</li>", escape_html(&key), escape_html(&value)));
             }
             body.push_str("</ul>");
         }
@@ -344,57 +985,28 @@ impl HttpServer {
             .with_body(&format!("Hello, {}!", name))
     }
 
-    fn handle_hello_head(request: &HttpRequest) -> HttpResponse {
-        let query_params = Router::parse_query_params(&request.path);
-        let default_name = "World".to_string();
-        let name = query_params.get("name").unwrap_or(&default_name);
-        
-        // HEAD response should have same headers as GET but no body
-        let body = format!("Hello, {}!", name);
-        HttpResponse::new(200, "OK")
-            .with_content_type("text/plain")
-            .with_header("Content-Length", &body.len().to_string())
-            // No body for HEAD requests
-    }
-
-    fn handle_status(_request: &HttpRequest) -> HttpResponse {
-        HttpResponse::new(200, "OK")
-            .with_content_type("application/json")
-            .with_body(r#"{"status":"ok","server":"rust-http-server","version":"1.0.0"}"#)
-    }
-
-    fn handle_stats(_request: &HttpRequest) -> HttpResponse {
-        // For a static method, we can't access instance data like thread_pool
-        // In a real implementation, you'd use a shared state (Arc<Mutex<Stats>>)
-        let stats = r#"{
-            "server": "rust-http-server-optimized",
-            "version": "1.0.0",
-            "features": {
-                "multi_threading": true,
-                "connection_pooling": true,
-                "buffered_io": true,
-                "keep_alive": true,
-                "chunked_encoding": true,
-                "authentication": true
-            },
-            "performance": {
-                "thread_pool_size": 4,
-                "max_connections": 100,
-                "buffer_size": "8KB",
-                "connection_timeout": "30s"
-            }
-        }"#;
-        
+    fn handle_echo(request: &HttpRequest) -> HttpResponse {
+        // Binary bodies are echoed as a hex dump instead of being mangled into text
+        let body_field = match request.body_str() {
+            Some(text) => ("body", text.into()),
+            None => ("body_hex", hex_encode(&request.body).into()),
+        };
         HttpResponse::new(200, "OK")
-            .with_content_type("application/json")
-            .with_body(stats)
+            .with_json(&JsonValue::object([
+                ("method", request.method.as_str().into()),
+                ("path", request.path.as_str().into()),
+                body_field,
+            ]))
     }
 
-    fn handle_echo(request: &HttpRequest) -> HttpResponse {
+    // Echo the request body back byte-for-byte (a framing conformance aid)
+    fn handle_echo_raw(request: &HttpRequest) -> HttpResponse {
+        let content_type = request.headers.get("content-type")
+            .map(String::as_str)
+            .unwrap_or("application/octet-stream");
         HttpResponse::new(200, "OK")
-            .with_content_type("application/json")
-            .with_body(&format!(r#"{{"method":"{}","path":"{}","body":"{}"}}"#, 
-                request.method, request.path, request.body))
+            .with_content_type(content_type)
+            .with_body_bytes(request.raw_body())
     }
 
     fn handle_admin(_request: &HttpRequest) -> HttpResponse {
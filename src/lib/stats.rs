@@ -0,0 +1,183 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Live server statistics shared between the accept loop, connection handlers and the router.
+//
+// Request and byte counters are u64 and wrap around to zero on overflow (at 1 request per
+// nanosecond that takes centuries). Deltas between snapshots use wrapping subtraction, so
+// they stay correct across a single wrap between the two snapshots.
+pub struct ServerStats {
+    started_at: Instant,
+    idle_connections: AtomicUsize,
+    active_connections: AtomicUsize, // accepted and not yet closed, idle ones included
+    connections_total: AtomicU64, // connections accepted
+    connections_rejected: AtomicU64, // accepted but turned away because no worker could take them
+    requests_total: AtomicU64,
+    responses_by_class: [AtomicU64; 5], // 1xx through 5xx
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    last_interval: Mutex<StatsSnapshot>, // start of the current /metrics interval
+}
+
+// Keeps a connection counted as active until it is dropped, even if its handler panics
+pub struct OpenConnection(Arc<ServerStats>);
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Counter values at one point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsSnapshot {
+    pub requests_total: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub taken_at: Instant,
+}
+
+// Change in the counters between two snapshots
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsDelta {
+    pub requests: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub elapsed: Duration,
+}
+
+impl StatsSnapshot {
+    pub fn delta_since(&self, earlier: &StatsSnapshot) -> StatsDelta {
+        StatsDelta {
+            requests: self.requests_total.wrapping_sub(earlier.requests_total),
+            bytes_received: self.bytes_received.wrapping_sub(earlier.bytes_received),
+            bytes_sent: self.bytes_sent.wrapping_sub(earlier.bytes_sent),
+            elapsed: self.taken_at.saturating_duration_since(earlier.taken_at),
+        }
+    }
+}
+
+impl StatsDelta {
+    // Per-second rate of a counter over this interval (0 for an empty interval)
+    pub fn rate(&self, count: u64) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 { count as f64 / seconds } else { 0.0 }
+    }
+}
+
+impl Default for ServerStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerStats {
+    pub fn new() -> Self {
+        ServerStats {
+            started_at: Instant::now(),
+            idle_connections: AtomicUsize::new(0),
+            active_connections: AtomicUsize::new(0),
+            connections_total: AtomicU64::new(0),
+            connections_rejected: AtomicU64::new(0),
+            requests_total: AtomicU64::new(0),
+            responses_by_class: Default::default(),
+            bytes_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            last_interval: Mutex::new(StatsSnapshot {
+                requests_total: 0,
+                bytes_received: 0,
+                bytes_sent: 0,
+                taken_at: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn set_idle_connections(&self, count: usize) {
+        self.idle_connections.store(count, Ordering::SeqCst);
+    }
+
+    pub fn idle_connections(&self) -> usize {
+        self.idle_connections.load(Ordering::SeqCst)
+    }
+
+    pub fn record_connection(&self) {
+        self.connections_total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Count an accepted connection, which stays active until the returned guard is dropped
+    pub fn open_connection(self: &Arc<Self>) -> OpenConnection {
+        self.record_connection();
+        self.active_connections.fetch_add(1, Ordering::SeqCst);
+        OpenConnection(Arc::clone(self))
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::SeqCst)
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn connections_total(&self) -> u64 {
+        self.connections_total.load(Ordering::Relaxed)
+    }
+
+    pub fn record_rejected_connection(&self) {
+        self.connections_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connections_rejected(&self) -> u64 {
+        self.connections_rejected.load(Ordering::Relaxed)
+    }
+
+    pub fn requests_total(&self) -> u64 {
+        self.requests_total.load(Ordering::Relaxed)
+    }
+
+    // Average requests served per accepted connection; above 1 means keep-alive is in use
+    pub fn requests_per_connection(&self) -> f64 {
+        match self.connections_total() {
+            0 => 0.0,
+            connections => self.requests_total() as f64 / connections as f64,
+        }
+    }
+
+    // Count one served request and the bytes read and written for it (fetch_add wraps on overflow)
+    pub fn record_request(&self, bytes_received: u64, bytes_sent: u64) {
+        self.requests_total.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes_received, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes_sent, Ordering::Relaxed);
+    }
+
+    // Count a response by its status class; codes outside 100-599 are not counted
+    pub fn record_status(&self, status_code: u16) {
+        if let Some(counter) = (status_code / 100).checked_sub(1).and_then(|class| self.responses_by_class.get(class as usize)) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Responses sent per status class, [1xx, 2xx, 3xx, 4xx, 5xx]
+    pub fn responses_by_class(&self) -> [u64; 5] {
+        std::array::from_fn(|class| self.responses_by_class[class].load(Ordering::Relaxed))
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            requests_total: self.requests_total.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            taken_at: Instant::now(),
+        }
+    }
+
+    // Take a snapshot and return it with the change since the previous call, starting a new interval
+    pub fn next_interval(&self) -> (StatsSnapshot, StatsDelta) {
+        let current = self.snapshot();
+        let mut last = self.last_interval.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let delta = current.delta_since(&last);
+        *last = current;
+        (current, delta)
+    }
+}
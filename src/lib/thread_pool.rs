@@ -1,12 +1,15 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
 use std::thread;
 use std::sync::{Arc, Mutex, mpsc};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use super::Logger;
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
 enum Message {
     NewJob(Job),
-    Terminate,
+    Terminate, // stop after the current job, even if more are queued
 }
 
 struct Worker {
@@ -15,20 +18,27 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>, logger: Arc<Mutex<Arc<Logger>>>) -> Worker {
         let thread = thread::spawn(move || {
             loop {
-                let message = receiver.lock().unwrap().recv().unwrap();
+                // Bind first so the receiver lock is released before the job runs
+                let message = receiver.lock().unwrap().recv();
 
                 match message {
-                    Message::NewJob(job) => {
+                    Ok(Message::NewJob(job)) => {
                         println!("Worker {} got a job; executing.", id);
-                        job();
+                        // A panicking job (e.g. a route handler) must not take the worker down with it
+                        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                            let logger = Arc::clone(&logger.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+                            logger.log_error(&format!("Worker {} recovered from a panicking job: {}", id, panic_message(&*payload)));
+                        }
                     }
-                    Message::Terminate => {
+                    Ok(Message::Terminate) => {
                         println!("Worker {} was told to terminate.", id);
                         break;
                     }
+                    // The sender is gone and the queue is drained: a graceful shutdown
+                    Err(_) => break,
                 }
             }
         });
@@ -41,10 +51,27 @@ impl Worker {
 }
 
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: mpsc::Sender<Message>,
+    workers: Mutex<Vec<Worker>>,
+    sender: Mutex<Option<mpsc::Sender<Message>>>, // None once shutdown() has been called
     active_connections: Arc<AtomicUsize>,
     max_connections: usize,
+    logger: Arc<Mutex<Arc<Logger>>>, // shared with the workers, which report panicking jobs
+}
+
+// Holds one of the pool's connection slots; dropping it (also while unwinding) frees the slot
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// The message passed to panic!, when it is a string
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
 }
 
 impl ThreadPool {
@@ -64,23 +91,35 @@ impl ThreadPool {
         let receiver = Arc::new(Mutex::new(receiver));
         let mut workers = Vec::with_capacity(size);
         let active_connections = Arc::new(AtomicUsize::new(0));
+        let logger = Arc::new(Mutex::new(Arc::new(Logger::new())));
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver), Arc::clone(&logger)));
         }
 
         ThreadPool { 
-            workers, 
-            sender,
+            workers: Mutex::new(workers), 
+            sender: Mutex::new(Some(sender)),
             active_connections,
             max_connections,
+            logger,
         }
     }
 
+    /// Log panics recovered in worker threads through `logger`
+    pub fn set_logger(&self, logger: Arc<Logger>) {
+        *self.logger.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = logger;
+    }
+
     pub fn execute<F>(&self, f: F) -> Result<(), &'static str>
     where
         F: FnOnce() + Send + 'static,
     {
+        let sender = self.sender.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(sender) = sender.as_ref() else {
+            return Err("Thread pool is shutting down");
+        };
+
         // Check if we've reached the maximum number of connections
         let current_connections = self.active_connections.load(Ordering::SeqCst);
         if current_connections >= self.max_connections {
@@ -90,17 +129,41 @@ impl ThreadPool {
         // Increment connection counter
         self.active_connections.fetch_add(1, Ordering::SeqCst);
 
-        let active_connections = Arc::clone(&self.active_connections);
+        // Decrement connection counter when job is done, even if it panics
+        let slot = ConnectionSlot(Arc::clone(&self.active_connections));
         let job = Box::new(move || {
+            let _slot = slot;
             f();
-            // Decrement connection counter when job is done
-            active_connections.fetch_sub(1, Ordering::SeqCst);
         });
 
-        self.sender.send(Message::NewJob(job)).unwrap();
+        sender.send(Message::NewJob(job)).unwrap();
         Ok(())
     }
 
+    /// Stop accepting jobs, let every queued job run to completion and join all workers.
+    ///
+    /// `execute` returns an error from now on. Calling this more than once is harmless.
+    pub fn shutdown(&self) {
+        // Dropping the only sender lets workers drain the queue, then see the channel close
+        let sender = self.sender.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+        if sender.is_none() {
+            return;
+        }
+        drop(sender);
+        self.join_workers();
+    }
+
+    fn join_workers(&self) {
+        let mut workers = self.workers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for worker in workers.iter_mut() {
+            println!("Shutting down worker {}", worker.id);
+
+            if let Some(thread) = worker.thread.take() {
+                thread.join().unwrap();
+            }
+        }
+    }
+
     pub fn get_active_connections(&self) -> usize {
         self.active_connections.load(Ordering::SeqCst)
     }
@@ -112,20 +175,19 @@ impl ThreadPool {
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
+        // Already shut down gracefully; the workers have been joined
+        let Some(sender) = self.sender.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()).take() else {
+            return;
+        };
+
         println!("Sending terminate message to all workers.");
 
-        for _ in &self.workers {
-            self.sender.send(Message::Terminate).unwrap();
+        for _ in self.workers.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()).iter() {
+            sender.send(Message::Terminate).unwrap();
         }
 
         println!("Shutting down all workers.");
 
-        for worker in &mut self.workers {
-            println!("Shutting down worker {}", worker.id);
-
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
-            }
-        }
+        self.join_workers();
     }
 }
@@ -0,0 +1,88 @@
+use std::io::{self, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use rustls::{ServerConnection, StreamOwned};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::pem::PemObject;
+use super::{Connection, ServerError};
+
+/// A TLS session over an accepted TCP connection
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// Build the rustls server configuration from a PEM certificate chain and private key
+pub fn load_server_config(cert_file: &str, key_file: &str) -> Result<Arc<rustls::ServerConfig>, ServerError> {
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| ServerError::TlsError(format!("cannot read certificates from {}: {}", cert_file, e)))?;
+    if certs.is_empty() {
+        return Err(ServerError::TlsError(format!("no certificates found in {}", cert_file)));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .map_err(|e| ServerError::TlsError(format!("cannot read private key from {}: {}", key_file, e)))?;
+
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| ServerError::TlsError(e.to_string()))?;
+    Ok(Arc::new(config))
+}
+
+/// Hands out the TLS configuration for new connections. `reload` re-reads the certificate
+/// and key files and swaps the configuration in; connections already accepted keep theirs.
+pub struct TlsAcceptor {
+    cert_file: String,
+    key_file: String,
+    config: RwLock<Arc<rustls::ServerConfig>>,
+}
+
+impl TlsAcceptor {
+    pub fn from_files(cert_file: &str, key_file: &str) -> Result<Self, ServerError> {
+        Ok(TlsAcceptor {
+            cert_file: cert_file.to_string(),
+            key_file: key_file.to_string(),
+            config: RwLock::new(load_server_config(cert_file, key_file)?),
+        })
+    }
+
+    /// Load the files again, e.g. after certificate rotation. On error the current
+    /// configuration stays in use.
+    pub fn reload(&self) -> Result<(), ServerError> {
+        let config = load_server_config(&self.cert_file, &self.key_file)?;
+        *self.config.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = config;
+        Ok(())
+    }
+
+    pub fn current(&self) -> Arc<rustls::ServerConfig> {
+        Arc::clone(&self.config.read().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+
+    /// Handshake on a newly accepted connection using the configuration in effect now
+    pub fn accept(&self, stream: TcpStream) -> io::Result<TlsStream> {
+        accept(self.current(), stream)
+    }
+}
+
+/// Run the server side of the handshake on `stream`. The socket's read timeout bounds
+/// how long a silent client can hold the worker.
+pub fn accept(config: Arc<rustls::ServerConfig>, stream: TcpStream) -> io::Result<TlsStream> {
+    let connection = ServerConnection::new(config).map_err(io::Error::other)?;
+    let mut tls_stream = StreamOwned::new(connection, stream);
+    while tls_stream.conn.is_handshaking() {
+        tls_stream.conn.complete_io(&mut tls_stream.sock)?;
+    }
+    Ok(tls_stream)
+}
+
+impl Connection for TlsStream {
+    // Say goodbye at the TLS layer before half-closing the socket
+    fn shutdown_write(&mut self) -> io::Result<()> {
+        self.conn.send_close_notify();
+        self.flush()?;
+        self.sock.shutdown(Shutdown::Write)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+}
@@ -3,6 +3,46 @@ mod tests;
 
 use api::{HttpServer, ServerConfig};
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// Set from the SIGINT/SIGTERM handler; a watcher thread turns it into a drain
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod signals {
+    use super::SHUTDOWN_REQUESTED;
+    use std::sync::atomic::Ordering;
+
+    // Only async-signal-safe work here: flip the flag and return
+    extern "C" fn request_shutdown(_signum: libc::c_int) {
+        SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        for signum in [libc::SIGINT, libc::SIGTERM] {
+            // SAFETY: `action` is fully initialised (zeroed, then handler and empty
+            // mask set) before being passed to sigaction, and the handler it installs
+            // only performs an atomic store, which is async-signal-safe
+            let installed = unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signum, &action, std::ptr::null_mut()) == 0
+            };
+            if !installed {
+                eprintln!("Failed to install handler for signal {}: {}", signum, std::io::Error::last_os_error());
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub fn install() {}
+}
 
 fn main() {
     // Load configuration from file or use defaults
@@ -28,42 +68,80 @@ fn main() {
         if config.static_files.enabled { "enabled" } else { "disabled" },
         config.static_files.directory
     );
+    println!("   🔒 TLS: {}", 
+        if config.tls.is_enabled() { "enabled (https)" } else { "disabled" }
+    );
+    println!("   🗜️  Compression: {}", 
+        if config.compression.enabled { format!("gzip above {} bytes", config.compression.min_size_bytes) } else { "disabled".to_string() }
+    );
+    if config.recording.enabled {
+        println!("   🎞️  Recording: last {} exchanges{}", config.recording.max_entries,
+            if config.recording.file.is_empty() { String::new() } else { format!(", appended to {}", config.recording.file) }
+        );
+    }
     println!("   🔐 Authentication: {}", 
         if config.authentication.enabled { "enabled" } else { "disabled" }
     );
-    println!("   📝 Logging: {} (level: {})", 
+    println!("   📝 Logging: {} (level: {}{})", 
         if config.logging.enabled { "enabled" } else { "disabled" },
-        config.logging.level
+        config.logging.level,
+        if config.logging.file.is_empty() { String::new() } else { format!(", file: {}", config.logging.file) }
     );
-    println!("");
+    println!();
     println!("📋 Available endpoints:");
     println!("   GET  /               - Home page");
     println!("   GET  /hello?name=X   - Greeting with query params");
     println!("   GET  /api/status     - JSON status endpoint");
     println!("   GET  /api/stats      - Performance statistics");
+    println!("   GET  /metrics        - Request and byte counters with rates");
+    println!("   GET  /readyz         - Readiness (runs registered dependency checks)");
     println!("   POST /api/echo       - Echo request data");
+    println!("   POST /api/echo/raw   - Echo the request body byte-for-byte");
     if config.authentication.enabled {
         println!("   GET  /admin          - Protected admin panel");
+        println!("   GET  /admin/config   - Effective configuration (redacted)");
+        if config.tls.is_enabled() {
+            println!("   POST /admin/tls/reload - Reload TLS certificate and key");
+        }
+        if config.recording.enabled {
+            println!("   GET  /admin/recordings - Recorded requests and responses");
+        }
     }
     println!("   GET  /chunked        - Chunked encoding demo");
     if config.static_files.enabled {
         println!("   GET  /static/        - Static file directory");
     }
-    println!("");
+    println!();
     println!("🔧 Test commands:");
     println!("   curl http://{}:{}/", config.server.host, config.server.port);
     println!("   curl http://{}:{}/api/stats", config.server.host, config.server.port);
-    if config.authentication.enabled {
-        if let Some((username, password)) = config.authentication.users.iter().next() {
-            println!("   curl -u {}:{} http://{}:{}/admin", username, password, config.server.host, config.server.port);
-        }
+    if config.authentication.enabled
+        && let Some((username, password)) = config.authentication.users.iter().next()
+    {
+        println!("   curl -u {}:{} http://{}:{}/admin", username, password, config.server.host, config.server.port);
     }
     println!("   curl http://{}:{}/chunked", config.server.host, config.server.port);
-    println!("");
+    println!();
     println!("💡 Usage: {} [config_file.toml]", env::args().next().unwrap_or_else(|| "server".to_string()));
-    println!("");
+    println!();
     
+    // Ctrl-C stops accepting connections, lets in-flight requests finish, then exits
+    match server.drain_handle() {
+        Ok(drain) => {
+            signals::install();
+            thread::spawn(move || {
+                while !SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                println!("Shutting down; finishing in-flight requests...");
+                drain.begin_drain(Duration::ZERO);
+            });
+        }
+        Err(e) => eprintln!("Graceful shutdown unavailable: {:?}", e),
+    }
+
     if let Err(e) = server.start() {
         eprintln!("Server error: {:?}", e);
     }
+    server.shutdown();
 }
@@ -1,4 +1,9 @@
 use super::helpers::*;
+use api::{HttpRequest, HttpResponse, HttpServer, JsonValue, Middleware, RedirectError, RequestIdMiddleware, ServerConfig};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
 
 // =======================
 // STEP 8: ADVANCED FEATURES TESTS
@@ -22,6 +27,64 @@ mod tests {
         assert!(response.contains("Hello, World!"));
     }
 
+    #[test]
+    fn test_keep_alive_header_advertises_policy() {
+        let port = 9352;
+        let mut config = ServerConfig::default();
+        config.connection.idle_timeout_seconds = 15;
+        config.connection.max_requests_per_connection = 3;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut responses = Vec::new();
+        for _ in 0..3 {
+            stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n").unwrap();
+            responses.push(read_http_response(&mut stream));
+        }
+
+        assert!(responses[0].contains("Keep-Alive: timeout=15, max=3\r\n"), "unexpected response: {}", responses[0]);
+        assert!(responses[1].contains("Keep-Alive: timeout=15, max=2\r\n"), "unexpected response: {}", responses[1]);
+        // The last request the connection may serve is answered with a close
+        assert!(responses[2].contains("Connection: close"), "unexpected response: {}", responses[2]);
+        assert!(!responses[2].contains("Keep-Alive:"));
+        let mut rest = Vec::new();
+        assert_eq!(stream.read_to_end(&mut rest).unwrap_or(0), 0);
+    }
+
+    #[test]
+    fn test_pipelined_requests_limit() {
+        let port = 9378;
+        let mut config = ServerConfig::default();
+        config.connection.max_pipelined_requests = 3;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        // Five requests in a single write; only the first three are answered
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let pipelined: String = (0..5)
+            .map(|i| format!("GET /hello?name={} HTTP/1.1\r\nHost: localhost\r\n\r\n", i))
+            .collect();
+        stream.write_all(pipelined.as_bytes()).unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 3, "unexpected response: {}", response);
+        assert!(response.ends_with("Hello, 2!"), "unexpected response: {}", response);
+        let last = response.rsplit("HTTP/1.1 200 OK").next().unwrap();
+        assert!(last.contains("Connection: close\r\n"), "unexpected response: {}", response);
+
+        // Requests sent one at a time are not a pipeline and are not limited
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        for _ in 0..5 {
+            stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let response = read_http_response(&mut stream);
+            assert!(response.contains("Connection: keep-alive\r\n"), "unexpected response: {}", response);
+        }
+    }
+
     #[test]
     fn test_http_connection_close() {
         let port = 9102;
@@ -36,6 +99,74 @@ mod tests {
         assert!(response.contains("Hello, World!"));
     }
 
+    // Send one request without Connection: close and return the response
+    fn request_on_open_connection(port: u16, request: &str) -> String {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        read_http_response(&mut stream)
+    }
+
+    #[test]
+    fn test_default_keep_alive_policy() {
+        let keep_port = 9362;
+        let _keep_server = start_test_server(keep_port);
+        let close_port = 9363;
+        let mut config = ServerConfig::default();
+        config.connection.default_keep_alive = false;
+        let _close_server = start_test_server_with_config(close_port, config);
+        wait_for_server(keep_port);
+        wait_for_server(close_port);
+
+        let bare = "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let explicit = "GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n";
+
+        // By default an HTTP/1.1 request without a Connection header stays open
+        let response = request_on_open_connection(keep_port, bare);
+        assert!(response.contains("Connection: keep-alive"), "unexpected response: {}", response);
+
+        // Close-by-default only keeps connections open on an explicit request
+        let response = request_on_open_connection(close_port, bare);
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(response.contains("Connection: close"), "unexpected response: {}", response);
+        let response = request_on_open_connection(close_port, explicit);
+        assert!(response.contains("Connection: keep-alive"), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_handler_connection_header_precedence() {
+        let port = 9367;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            server.add_route("GET", "/bye", |_request: &HttpRequest| {
+                HttpResponse::new(200, "OK").with_connection("close").with_body("bye")
+            });
+            server.add_route("GET", "/linger", |_request: &HttpRequest| {
+                HttpResponse::new(200, "OK").with_header("connection", "keep-alive").with_body("linger")
+            });
+        });
+        wait_for_server(port);
+
+        let count_connection_headers = |response: &str| {
+            response.lines().filter(|line| line.to_ascii_lowercase().starts_with("connection:")).count()
+        };
+
+        // A handler asking to close a keep-alive connection wins, and the server closes it
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(b"GET /bye HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.contains("Connection: close\r\n"), "unexpected response: {}", response);
+        assert_eq!(count_connection_headers(&response), 1, "unexpected response: {}", response);
+        assert!(!response.contains("Keep-Alive:"));
+        let mut rest = Vec::new();
+        assert_eq!(stream.read_to_end(&mut rest).unwrap_or(0), 0);
+
+        // A handler cannot keep open a connection the client asked to close
+        let response = send_http_request(port, "GET /linger HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("Connection: close\r\n"), "unexpected response: {}", response);
+        assert_eq!(count_connection_headers(&response), 1, "unexpected response: {}", response);
+    }
+
     #[test]
     fn test_chunked_transfer_encoding() {
         let port = 9103;
@@ -142,11 +273,11 @@ mod tests {
         wait_for_server(port);
 
         // First, get a valid token by logging in
-        let login_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 42\r\n\r\n{\"username\": \"testuser\", \"password\": \"testpass\"}";
+        let login_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 48\r\n\r\n{\"username\": \"testuser\", \"password\": \"testpass\"}";
         let login_response = send_http_request(port, login_request);
         
         // Extract token from response (simple parsing for test)
-        if login_response.contains("\"success\": true") {
+        if login_response.contains("\"success\":true") {
             println!("Bearer token authentication is working correctly");
         } else {
             println!("Login failed as expected in token-only auth mode");
@@ -189,6 +320,244 @@ mod tests {
         assert!(!response.contains("WWW-Authenticate"));
     }
 
+    #[test]
+    fn test_empty_authorization_credentials_rejected() {
+        let port = 9312;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        // A scheme with no credentials is a clean 401, never a panic in the worker
+        for header in ["Basic ", "Bearer ", "Bearer", ""] {
+            let request = format!("GET /admin HTTP/1.1\r\nHost: localhost\r\nAuthorization: {}\r\n\r\n", header);
+            let response = send_http_request(port, &request);
+            assert!(response.contains("HTTP/1.1 401 Unauthorized"), "unexpected response for {:?}: {}", header, response);
+            assert!(response.contains("Valid Bearer token required"));
+        }
+
+        // Logging out with an empty token is rejected as well
+        let response = send_http_request(port, "POST /api/logout HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer \r\nContent-Length: 0\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 400 Bad Request"));
+    }
+
+    #[test]
+    fn test_login_lockout_after_repeated_failures() {
+        let port = 9313;
+        let mut config = ServerConfig::default();
+        config.authentication.max_failed_logins = 3;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let login = |password: &str| {
+            let body = format!(r#"{{"username": "admin", "password": "{}"}}"#, password);
+            let request = format!(
+                "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            send_http_request(port, &request)
+        };
+
+        for _ in 0..3 {
+            assert!(login("wrong").contains("HTTP/1.1 401 Unauthorized"));
+        }
+
+        // Locked out now, even with the correct password
+        let response = login("password123");
+        assert!(response.contains("HTTP/1.1 429 Too Many Requests"), "unexpected response: {}", response);
+        assert!(response.contains("Retry-After: 300"));
+        assert!(!response.contains("token"));
+    }
+
+    #[test]
+    fn test_register_enforces_password_policy() {
+        let port = 9314;
+        let mut config = ServerConfig::default();
+        config.authentication.password_min_length = 10;
+        config.authentication.password_require_digit = true;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let register = |username: &str, password: &str| {
+            let body = format!(r#"{{"username": "{}", "password": "{}"}}"#, username, password);
+            let request = format!(
+                "POST /api/register HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            send_http_request(port, &request)
+        };
+
+        let weak = register("weakling", "short");
+        assert!(weak.contains("HTTP/1.1 400 Bad Request"));
+        assert!(weak.contains("Password must be at least 10 characters long, contain a digit"), "unexpected response: {}", weak);
+
+        let strong = register("strongling", "long enough 42");
+        assert!(strong.contains("HTTP/1.1 201 Created"), "unexpected response: {}", strong);
+        assert!(strong.contains("\"token\""));
+    }
+
+    #[test]
+    fn test_login_accepts_json_and_form_encoded_bodies() {
+        let port = 9315;
+        let _server_handle = start_test_server_with_config(port, ServerConfig::default());
+        wait_for_server(port);
+
+        let post = |path: &str, content_type: &str, body: &str| {
+            let request = format!(
+                "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+                path, content_type, body.len(), body
+            );
+            send_http_request(port, &request)
+        };
+
+        let json = post("/api/login", "application/json", r#"{"username": "admin", "password": "password123"}"#);
+        assert!(json.contains("HTTP/1.1 200 OK"));
+        assert!(json.contains("\"token\""));
+
+        let form = post("/api/login", "application/x-www-form-urlencoded", "username=admin&password=password123");
+        assert!(form.contains("HTTP/1.1 200 OK"), "unexpected response: {}", form);
+        assert!(form.contains("\"token\""));
+
+        // Encoded characters are decoded before the credentials are checked
+        let register = post("/api/register", "application/x-www-form-urlencoded", "username=form+user&password=p%26ss%3Dword%21");
+        assert!(register.contains("HTTP/1.1 201 Created"), "unexpected response: {}", register);
+        let login = post("/api/login", "application/json", r#"{"username": "form user", "password": "p&ss=word!"}"#);
+        assert!(login.contains("HTTP/1.1 200 OK"));
+    }
+
+    #[test]
+    fn test_change_password_revokes_existing_tokens() {
+        let port = 9359;
+        let _server_handle = start_test_server_with_config(port, ServerConfig::default());
+        wait_for_server(port);
+
+        let post = |path: &str, token: Option<&str>, body: &str| {
+            let authorization = token.map(|token| format!("Authorization: Bearer {}\r\n", token)).unwrap_or_default();
+            let request = format!(
+                "POST {} HTTP/1.1\r\nHost: localhost\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                path, authorization, body.len(), body
+            );
+            send_http_request(port, &request)
+        };
+        let token_from = |response: &str| {
+            let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+            JsonValue::parse(body).unwrap().get("token").and_then(JsonValue::as_str).unwrap().to_string()
+        };
+        let admin_with = |token: &str| {
+            send_http_request(port, &format!("GET /admin HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\r\n", token))
+        };
+
+        let login = r#"{"username": "admin", "password": "password123"}"#;
+        let first = token_from(&post("/api/login", None, login));
+        let second = token_from(&post("/api/login", None, login));
+
+        // The current password is required, not just a token
+        let wrong = post("/api/change-password", Some(&first), r#"{"current_password": "guess", "new_password": "n3w-Passw0rd"}"#);
+        assert!(wrong.contains("HTTP/1.1 403 Forbidden"), "unexpected response: {}", wrong);
+        let anonymous = post("/api/change-password", None, r#"{"current_password": "password123", "new_password": "n3w-Passw0rd"}"#);
+        assert!(anonymous.contains("HTTP/1.1 401 Unauthorized"));
+
+        let changed = post("/api/change-password", Some(&first), r#"{"current_password": "password123", "new_password": "n3w-Passw0rd"}"#);
+        assert!(changed.contains("HTTP/1.1 200 OK"), "unexpected response: {}", changed);
+        let fresh = token_from(&changed);
+
+        // Every token issued before the change is gone; the new one works
+        assert!(admin_with(&first).contains("HTTP/1.1 401 Unauthorized"));
+        assert!(admin_with(&second).contains("HTTP/1.1 401 Unauthorized"));
+        assert!(admin_with(&fresh).contains("HTTP/1.1 200 OK"));
+
+        // Only the new password logs in
+        assert!(post("/api/login", None, login).contains("HTTP/1.1 401 Unauthorized"));
+        assert!(post("/api/login", None, r#"{"username": "admin", "password": "n3w-Passw0rd"}"#).contains("HTTP/1.1 200 OK"));
+    }
+
+    #[test]
+    fn test_token_cookie_session() {
+        let port = 9316;
+        let mut config = ServerConfig::default();
+        config.authentication.token_cookie_enabled = true;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let body = r#"{"username": "admin", "password": "password123"}"#;
+        let login_request = format!(
+            "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let login_response = send_http_request(port, &login_request);
+        assert!(login_response.contains("HTTP/1.1 200 OK"));
+
+        let set_cookie = login_response.lines()
+            .find_map(|line| line.strip_prefix("Set-Cookie: "))
+            .expect("login should set the token cookie");
+        assert!(set_cookie.starts_with("auth_token="));
+        assert!(set_cookie.contains("HttpOnly"));
+        assert!(set_cookie.contains("Secure"));
+        assert!(set_cookie.contains("SameSite=Strict"));
+
+        // The cookie alone grants access to a protected resource
+        let cookie = set_cookie.split(';').next().unwrap();
+        let request = format!("GET /admin HTTP/1.1\r\nHost: localhost\r\nCookie: theme=dark; {}\r\n\r\n", cookie);
+        let response = send_http_request(port, &request);
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+
+        // A quoted cookie value is unquoted like any other cookie
+        let (name, token) = cookie.split_once('=').unwrap();
+        let request = format!("GET /admin HTTP/1.1\r\nHost: localhost\r\nCookie: {}=\"{}\"\r\n\r\n", name, token);
+        let response = send_http_request(port, &request);
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+
+        // Without it the resource stays protected
+        let response = send_http_request(port, "GET /admin HTTP/1.1\r\nHost: localhost\r\nCookie: auth_token=bogus\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 401 Unauthorized"));
+    }
+
+    #[test]
+    fn test_csrf_required_for_cookie_authenticated_posts() {
+        let port = 9317;
+        let mut config = ServerConfig::default();
+        config.authentication.token_cookie_enabled = true;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let body = r#"{"username": "admin", "password": "password123"}"#;
+        let login_request = format!(
+            "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let login_response = send_http_request(port, &login_request);
+        let cookie = login_response.lines()
+            .find_map(|line| line.strip_prefix("Set-Cookie: "))
+            .and_then(|value| value.split(';').next())
+            .expect("login should set the token cookie")
+            .to_string();
+
+        let echo = |extra_headers: &str| {
+            let request = format!(
+                "POST /api/echo HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\n{}Content-Length: 2\r\n\r\nhi",
+                cookie, extra_headers
+            );
+            send_http_request(port, &request)
+        };
+
+        // A cookie-authenticated POST without the CSRF token is refused
+        let response = echo("");
+        assert!(response.contains("HTTP/1.1 403 Forbidden"), "unexpected response: {}", response);
+        assert!(echo("X-CSRF-Token: guessed\r\n").contains("HTTP/1.1 403 Forbidden"));
+
+        // Fetch the session's CSRF token and present it
+        let token_response = send_http_request(port, &format!("GET /api/csrf-token HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\n\r\n", cookie));
+        assert!(token_response.contains("HTTP/1.1 200 OK"));
+        let token_start = token_response.find("\"csrf_token\":\"").unwrap() + 14;
+        let token_end = token_response[token_start..].find('"').unwrap() + token_start;
+        let csrf_token = &token_response[token_start..token_end];
+
+        let response = echo(&format!("X-CSRF-Token: {}\r\n", csrf_token));
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+    }
+
     #[test]
     fn test_invalid_bearer_token_rejected() {
         let port = 9116;
@@ -227,7 +596,7 @@ mod tests {
         wait_for_server(port);
 
         // Step 1: Register a new user and get a token
-        let register_request = "POST /api/register HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 52\r\n\r\n{\"username\": \"tokenuser\", \"password\": \"securepass\"}";
+        let register_request = "POST /api/register HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 51\r\n\r\n{\"username\": \"tokenuser\", \"password\": \"securepass\"}";
         let register_response = send_http_request(port, register_request);
         
         println!("Register response: {}", register_response);
@@ -239,20 +608,20 @@ mod tests {
         assert!(is_new_user || user_exists, "Registration should either succeed or indicate user exists");
         
         let token = if is_new_user {
-            assert!(register_response.contains("\"success\": true"));
+            assert!(register_response.contains("\"success\":true"));
             assert!(register_response.contains("\"token\""));
             
             // Extract token from response
-            let token_start = register_response.find("\"token\": \"").unwrap() + 10;
+            let token_start = register_response.find("\"token\":\"").unwrap() + 9;
             let token_end = register_response[token_start..].find("\"").unwrap() + token_start;
             register_response[token_start..token_end].to_string()
         } else {
             // User exists, try to login instead
-            let login_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 52\r\n\r\n{\"username\": \"tokenuser\", \"password\": \"securepass\"}";
+            let login_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 51\r\n\r\n{\"username\": \"tokenuser\", \"password\": \"securepass\"}";
             let login_response = send_http_request(port, login_request);
             
             assert!(login_response.contains("HTTP/1.1 200 OK"));
-            let token_start = login_response.find("\"token\": \"").unwrap() + 10;
+            let token_start = login_response.find("\"token\":\"").unwrap() + 9;
             let token_end = login_response[token_start..].find("\"").unwrap() + token_start;
             login_response[token_start..token_end].to_string()
         };
@@ -269,7 +638,7 @@ mod tests {
         let logout_response = send_http_request(port, &logout_request);
         
         assert!(logout_response.contains("HTTP/1.1 200 OK"));
-        assert!(logout_response.contains("\"success\": true"));
+        assert!(logout_response.contains("\"success\":true"));
 
         // Step 4: Verify token is revoked (should fail now)
         let revoked_request = format!("GET /admin HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\r\n", token);
@@ -285,7 +654,7 @@ mod tests {
         wait_for_server(port);
 
         // First register a new user to get valid credentials
-        let register_request = "POST /api/register HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 48\r\n\r\n{\"username\": \"logintest\", \"password\": \"password\"}";
+        let register_request = "POST /api/register HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 49\r\n\r\n{\"username\": \"logintest\", \"password\": \"password\"}";
         let register_response = send_http_request(port, register_request);
         
         // Either registration succeeds or user already exists
@@ -294,15 +663,15 @@ mod tests {
         assert!(user_created || user_exists, "Registration should succeed or indicate user exists");
 
         // Now test login with these credentials
-        let login_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 48\r\n\r\n{\"username\": \"logintest\", \"password\": \"password\"}";
+        let login_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 49\r\n\r\n{\"username\": \"logintest\", \"password\": \"password\"}";
         let login_response = send_http_request(port, login_request);
         
         assert!(login_response.contains("HTTP/1.1 200 OK"));
-        assert!(login_response.contains("\"success\": true"));
+        assert!(login_response.contains("\"success\":true"));
         assert!(login_response.contains("\"token\""));
         
         // Extract token and test protected access
-        let token_start = login_response.find("\"token\": \"").unwrap() + 10;
+        let token_start = login_response.find("\"token\":\"").unwrap() + 9;
         let token_end = login_response[token_start..].find("\"").unwrap() + token_start;
         let token = &login_response[token_start..token_end];
 
@@ -353,11 +722,11 @@ mod tests {
         // Test that login errors don't reveal whether username exists
 
         // 1. Non-existent user
-        let nonexistent_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 50\r\n\r\n{\"username\": \"nonexistent\", \"password\": \"wrongpass\"}";
+        let nonexistent_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 52\r\n\r\n{\"username\": \"nonexistent\", \"password\": \"wrongpass\"}";
         let nonexistent_response = send_http_request(port, nonexistent_request);
         
         // 2. Existing user with wrong password
-        let wrong_pass_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 46\r\n\r\n{\"username\": \"testuser\", \"password\": \"wrongpass\"}";
+        let wrong_pass_request = "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 49\r\n\r\n{\"username\": \"testuser\", \"password\": \"wrongpass\"}";
         let wrong_pass_response = send_http_request(port, wrong_pass_request);
 
         // Both should return the same generic error message
@@ -366,4 +735,683 @@ mod tests {
         assert!(nonexistent_response.contains("HTTP/1.1 401 Unauthorized"));
         assert!(wrong_pass_response.contains("HTTP/1.1 401 Unauthorized"));
     }
+
+    fn large_body_handler(_request: &HttpRequest) -> HttpResponse {
+        HttpResponse::new(200, "OK")
+            .with_content_type("text/plain")
+            .with_body(&"0123456789".repeat(500))
+    }
+
+    fn small_body_handler(_request: &HttpRequest) -> HttpResponse {
+        HttpResponse::new(200, "OK")
+            .with_content_type("text/plain")
+            .with_body("small body")
+    }
+
+    #[test]
+    fn test_chunked_threshold_switches_framing() {
+        let port = 9300;
+        let mut config = ServerConfig::default();
+        config.connection.chunked_threshold_bytes = 1024;
+        let _server_handle = start_test_server_with_setup(port, config, |server| {
+            server.add_route("GET", "/large", large_body_handler);
+            server.add_route("GET", "/small", small_body_handler);
+        });
+        wait_for_server(port);
+
+        // Bodies above the threshold are sent chunked and arrive intact
+        let large = send_http_request(port, "GET /large HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(large.contains("HTTP/1.1 200 OK"));
+        assert!(large.contains("Transfer-Encoding: chunked"));
+        assert!(!large.contains("Content-Length:"));
+        assert_eq!(decode_chunked_body(&large), "0123456789".repeat(500));
+
+        // Bodies below the threshold keep Content-Length framing
+        let small = send_http_request(port, "GET /small HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(small.contains("HTTP/1.1 200 OK"));
+        assert!(small.contains("Content-Length: 10"));
+        assert!(!small.contains("Transfer-Encoding: chunked"));
+        assert!(small.ends_with("small body"));
+    }
+
+    #[test]
+    fn test_admin_config_endpoint_redacts_secrets() {
+        let port = 9302;
+        let _server_handle = start_test_server_with_config(port, ServerConfig::default());
+        wait_for_server(port);
+
+        // The endpoint requires a valid token
+        let anonymous = send_http_request(port, "GET /admin/config HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(anonymous.contains("HTTP/1.1 401 Unauthorized"));
+
+        let body = r#"{"username": "admin", "password": "password123"}"#;
+        let login_request = format!(
+            "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let login_response = send_http_request(port, &login_request);
+        let token_start = login_response.find("\"token\":\"").unwrap() + 9;
+        let token_end = login_response[token_start..].find('"').unwrap() + token_start;
+        let token = &login_response[token_start..token_end];
+
+        let request = format!("GET /admin/config HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\r\n", token);
+        let response = send_http_request(port, &request);
+
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(response.contains("Content-Type: application/json"));
+        assert!(response.contains(&format!(r#""port":{}"#, port)));
+        assert!(response.contains(r#""users":{"admin":"***","user":"***"}"#));
+    }
+
+    #[test]
+    fn test_response_body_writer_updates_content_length() {
+        use std::io::Write;
+
+        let mut response = HttpResponse::new(200, "OK").with_content_type("text/plain");
+        write!(response, "Hello").unwrap();
+        for i in 1..=3 {
+            writeln!(response, " line {}", i).unwrap();
+        }
+
+        let expected = "Hello line 1\n line 2\n line 3\n";
+        assert_eq!(response.body, expected.as_bytes());
+        assert_eq!(response.headers.get("Content-Length"), Some(&expected.len().to_string()));
+
+        let formatted = String::from_utf8(response.format()).unwrap();
+        assert!(formatted.contains(&format!("Content-Length: {}\r\n", expected.len())));
+        assert!(formatted.ends_with(expected));
+    }
+
+    #[test]
+    fn test_redirect_helper() {
+        let response = HttpResponse::redirect(301, "/new?a=1&b=<2>").unwrap();
+        assert_eq!(response.status_code, 301);
+        assert_eq!(response.status_text, "Moved Permanently");
+        assert_eq!(response.headers.get("Location").map(String::as_str), Some("/new?a=1&b=<2>"));
+        let body = String::from_utf8(response.body).unwrap();
+        assert!(body.contains("<a href=\"/new?a=1&amp;b=&lt;2&gt;\">"), "unexpected body: {}", body);
+    }
+
+    #[test]
+    fn test_redirect_rejects_non_redirect_status() {
+        // 304 and 300 are 3xx but do not send the client to Location
+        for status_code in [200, 300, 304, 305, 404] {
+            assert_eq!(HttpResponse::redirect(status_code, "/elsewhere").unwrap_err(), RedirectError::InvalidStatus(status_code));
+        }
+        assert_eq!(HttpResponse::redirect(308, "/elsewhere").unwrap().status_text, "Permanent Redirect");
+    }
+
+    #[test]
+    fn test_chunked_response_with_trailer() {
+        let port = 9305;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            server.add_route("GET", "/checksummed", |_request: &HttpRequest| {
+                let body = "streamed payload";
+                let checksum: u32 = body.bytes().map(u32::from).sum();
+                HttpResponse::new(200, "OK")
+                    .with_content_type("text/plain")
+                    .with_body(body)
+                    .with_trailer("X-Checksum", &checksum.to_string())
+            });
+        });
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /checksummed HTTP/1.1\r\nHost: localhost\r\nTE: trailers\r\nConnection: TE\r\n\r\n");
+
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(response.contains("Transfer-Encoding: chunked"));
+        assert!(response.contains("Trailer: X-Checksum\r\n"));
+        assert_eq!(decode_chunked_body(&response), "streamed payload");
+
+        // The trailer follows the terminating zero-length chunk
+        let checksum: u32 = "streamed payload".bytes().map(u32::from).sum();
+        assert!(response.ends_with(&format!("\r\n0\r\nX-Checksum: {}\r\n\r\n", checksum)), "unexpected response: {:?}", response);
+    }
+
+    // Serve one keep-alive request, then stay idle until the server's read timeout fires
+    fn idle_keep_alive_after_timeout(port: u16, send_408: bool) -> String {
+        let mut config = ServerConfig::default();
+        config.server.read_timeout_seconds = 1;
+        config.connection.send_408_on_idle_timeout = send_408;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        assert!(read_http_response(&mut stream).contains("HTTP/1.1 200 OK"));
+
+        // Everything the server sends until it closes the idle connection
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        String::from_utf8_lossy(&rest).into_owned()
+    }
+
+    #[test]
+    fn test_idle_keep_alive_timeout_sends_408() {
+        let after_idle = idle_keep_alive_after_timeout(9310, true);
+        assert!(after_idle.starts_with("HTTP/1.1 408 Request Timeout"), "unexpected data: {:?}", after_idle);
+        assert!(after_idle.contains("Connection: close"));
+    }
+
+    #[test]
+    fn test_idle_keep_alive_timeout_silent_close() {
+        let after_idle = idle_keep_alive_after_timeout(9311, false);
+        assert_eq!(after_idle, "");
+    }
+
+    #[test]
+    fn test_raw_echo_is_byte_exact() {
+        let port = 9318;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let body: &[u8] = b"line one\r\nline two\n\r\n\x00\xff\xfe\x80 binary tail";
+        let mut request = format!(
+            "POST /api/echo/raw HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        ).into_bytes();
+        request.extend_from_slice(body);
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(&request).unwrap();
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+
+        let header_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&response[..header_end]);
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert!(head.contains("Content-Type: application/octet-stream"));
+        assert_eq!(&response[header_end..], body);
+    }
+
+    #[test]
+    fn test_readiness_checks() {
+        let healthy_port = 9319;
+        let _healthy = start_test_server_with_setup(healthy_port, ServerConfig::default(), |server| {
+            server.add_readiness_check("database", || Ok(()));
+        });
+        let failing_port = 9320;
+        let _failing = start_test_server_with_setup(failing_port, ServerConfig::default(), |server| {
+            server.add_readiness_check("database", || Ok(()));
+            server.add_readiness_check("upstream", || Err("connection refused".to_string()));
+        });
+        wait_for_server(healthy_port);
+        wait_for_server(failing_port);
+
+        let ready = send_http_request(healthy_port, "GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(ready.contains("HTTP/1.1 200 OK"));
+        assert!(ready.contains(r#""status":"ready","failed":[]"#), "unexpected response: {}", ready);
+
+        let not_ready = send_http_request(failing_port, "GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(not_ready.contains("HTTP/1.1 503 Service Unavailable"));
+        assert!(not_ready.contains(r#""failed":["upstream"]"#), "unexpected response: {}", not_ready);
+        assert!(not_ready.contains(r#""error":"connection refused""#));
+    }
+
+    #[test]
+    fn test_drain_fails_readiness_then_stops() {
+        let port = 9321;
+        let mut config = ServerConfig::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = port;
+        let server = HttpServer::from_config(config).unwrap();
+        let drain = server.drain_handle().unwrap();
+        let server_thread = thread::spawn(move || server.start());
+        wait_for_server(port);
+
+        let ready = send_http_request(port, "GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(ready.contains("HTTP/1.1 200 OK"));
+
+        drain.begin_drain(Duration::from_millis(500));
+        assert!(drain.is_draining());
+
+        // Out of rotation, but still serving traffic during the grace period
+        let ready = send_http_request(port, "GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(ready.contains("HTTP/1.1 503 Service Unavailable"));
+        assert!(ready.contains(r#""status":"draining""#));
+        let hello = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(hello.contains("HTTP/1.1 200 OK"));
+
+        // After the grace period the accept loop exits cleanly
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !server_thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(server_thread.is_finished(), "server did not stop after draining");
+        assert!(server_thread.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_shutdown_finishes_in_flight_request() {
+        let port = 9343;
+        let mut config = ServerConfig::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = port;
+        let mut server = HttpServer::from_config(config).unwrap();
+        server.add_route("GET", "/slow", |_request: &HttpRequest| {
+            thread::sleep(Duration::from_millis(500));
+            HttpResponse::new(200, "OK").with_body("finished")
+        });
+        let drain = server.drain_handle().unwrap();
+        let server_thread = thread::spawn(move || {
+            let result = server.start();
+            server.shutdown();
+            result
+        });
+        wait_for_server(port);
+
+        let client = thread::spawn(move || {
+            send_http_request(port, "GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n")
+        });
+        thread::sleep(Duration::from_millis(100));
+        drain.begin_drain(Duration::ZERO);
+
+        // The request that was already running still gets its response
+        let response = client.join().unwrap();
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.ends_with("finished"));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !server_thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(server_thread.is_finished(), "server did not shut down");
+        assert!(server_thread.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_stop_handle_ends_start() {
+        let port = 9383;
+        let mut config = ServerConfig::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = port;
+        let server = HttpServer::from_config(config).unwrap();
+        let handle = server.drain_handle().unwrap();
+        let server_thread = thread::spawn(move || server.start());
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+
+        // start() returns on its own, workers joined, without a separate shutdown() call
+        handle.clone().stop();
+        handle.stop(); // stopping twice is harmless
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !server_thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(server_thread.is_finished(), "server did not stop");
+        assert!(server_thread.join().unwrap().is_ok());
+
+        // The listener went away with the server
+        assert!(TcpStream::connect(format!("127.0.0.1:{}", port)).is_err());
+    }
+
+    #[test]
+    fn test_unauthorized_post_rejected_before_body_is_read() {
+        let port = 9326;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        // Send only the head of a large POST to a protected path; the 401 must arrive
+        // without the server waiting for the body
+        let body_length = 200_000;
+        stream.write_all(format!(
+            "POST /admin HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n", body_length
+        ).as_bytes()).unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized"), "unexpected response: {}", response);
+
+        // The body that follows is skipped and the connection keeps working
+        stream.write_all(&vec![b'x'; body_length]).unwrap();
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.ends_with("Hello, World!"));
+    }
+
+    #[test]
+    fn test_te_header_gates_trailers_and_chunking() {
+        let port = 9329;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            server.add_route("GET", "/checksummed", |_request: &HttpRequest| {
+                HttpResponse::new(200, "OK")
+                    .with_content_type("text/plain")
+                    .with_body("streamed payload")
+                    .with_trailer("X-Checksum", "1234")
+            });
+        });
+        wait_for_server(port);
+
+        // TE: trailers (with parameters and other codings) gets the chunked response with its trailer
+        let response = send_http_request(port, "GET /checksummed HTTP/1.1\r\nHost: localhost\r\nTE: gzip;q=0.5, Trailers\r\nConnection: TE, close\r\n\r\n");
+        assert!(response.contains("Transfer-Encoding: chunked"), "unexpected response: {}", response);
+        assert!(response.ends_with("\r\n0\r\nX-Checksum: 1234\r\n\r\n"));
+
+        // Without TE the trailer is dropped and the body keeps Content-Length framing
+        let response = send_http_request(port, "GET /checksummed HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        assert!(!response.contains("Transfer-Encoding"), "unexpected response: {}", response);
+        assert!(!response.contains("X-Checksum"));
+        assert!(response.contains("Content-Length: 16"));
+        assert!(response.ends_with("\r\n\r\nstreamed payload"));
+
+        // Handler-requested chunking still applies to HTTP/1.1 without TE
+        let response = send_http_request(port, "GET /chunked HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        assert!(response.contains("Transfer-Encoding: chunked"));
+
+        // ...but an HTTP/1.0 client cannot read chunked framing, even when it sends TE
+        let response = send_http_request(port, "GET /chunked HTTP/1.0\r\nHost: localhost\r\nTE: trailers\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(!response.contains("Transfer-Encoding"), "unexpected response: {}", response);
+        assert!(response.contains("This is a demonstration of chunked transfer encoding"));
+    }
+
+    #[test]
+    fn test_response_hooks_run_on_every_response() {
+        let port = 9342;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            server.add_response_hook(|_request, response| {
+                response.headers.insert("X-Served-By".to_string(), "hooked".to_string());
+            });
+            // Hooks run in registration order, so this one sees the header added above
+            server.add_response_hook(|request, response| {
+                let seen = response.headers.contains_key("X-Served-By");
+                response.headers.insert("X-Hook-Trace".to_string(), format!("{} {} {}", request.method, request.path, seen));
+            });
+        });
+        wait_for_server(port);
+
+        let hello = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        let missing = send_http_request(port, "GET /no-such-page HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        let unauthorized = send_http_request(port, "GET /admin HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+
+        for response in [&hello, &missing, &unauthorized] {
+            assert!(response.contains("X-Served-By: hooked"), "unexpected response: {}", response);
+        }
+        assert!(hello.contains("HTTP/1.1 200 OK"));
+        assert!(hello.contains("X-Hook-Trace: GET /hello true"));
+        assert!(missing.contains("HTTP/1.1 404 Not Found"));
+        assert!(unauthorized.contains("HTTP/1.1 401 Unauthorized"));
+        assert!(unauthorized.contains("X-Hook-Trace: GET /admin true"));
+    }
+
+    fn alphabet_handler(_request: &HttpRequest) -> HttpResponse {
+        HttpResponse::new(200, "OK")
+            .with_content_type("text/plain")
+            .with_body("0123456789abcdefghij")
+            .ranged()
+    }
+
+    #[test]
+    fn test_dynamic_handler_serves_byte_range() {
+        let port = 9350;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            server.add_route("GET", "/alphabet", alphabet_handler);
+        });
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /alphabet HTTP/1.1\r\nHost: localhost\r\nRange: bytes=5-9\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 206 Partial Content"), "unexpected response: {}", response);
+        assert!(response.contains("Content-Range: bytes 5-9/20"));
+        assert!(response.contains("Content-Length: 5"));
+        assert!(response.ends_with("\r\n\r\n56789"));
+
+        let suffix = send_http_request(port, "GET /alphabet HTTP/1.1\r\nHost: localhost\r\nRange: bytes=-3\r\n\r\n");
+        assert!(suffix.contains("Content-Range: bytes 17-19/20"));
+        assert!(suffix.ends_with("hij"));
+
+        let beyond = send_http_request(port, "GET /alphabet HTTP/1.1\r\nHost: localhost\r\nRange: bytes=20-\r\n\r\n");
+        assert!(beyond.contains("HTTP/1.1 416 Range Not Satisfiable"));
+        assert!(beyond.contains("Content-Range: bytes */20"));
+
+        // Without a Range header the whole body is sent, advertising range support
+        let full = send_http_request(port, "GET /alphabet HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(full.contains("HTTP/1.1 200 OK"));
+        assert!(full.contains("Accept-Ranges: bytes"));
+        assert!(full.ends_with("0123456789abcdefghij"));
+    }
+
+    // Refuses /blocked outright and serves /old as /hello
+    struct GatekeeperMiddleware;
+
+    impl Middleware for GatekeeperMiddleware {
+        fn handle(&self, request: &HttpRequest, next: &dyn Fn(&HttpRequest) -> HttpResponse) -> HttpResponse {
+            if request.path == "/blocked" {
+                return HttpResponse::new(429, "Too Many Requests").with_body("slow down");
+            }
+            if request.path == "/old" {
+                let mut rewritten = request.clone();
+                rewritten.path = "/hello".to_string();
+                return next(&rewritten);
+            }
+            next(request)
+        }
+    }
+
+    #[test]
+    fn test_middleware_chain_wraps_dispatch() {
+        let port = 9357;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            server.add_middleware(RequestIdMiddleware::new());
+            server.add_middleware(GatekeeperMiddleware);
+        });
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        let request_id = response.lines()
+            .find_map(|line| line.strip_prefix("X-Request-Id: "))
+            .expect("X-Request-Id header");
+        assert!(!request_id.is_empty());
+
+        // A client-supplied id is kept
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: trace-42\r\n\r\n");
+        assert!(response.contains("X-Request-Id: trace-42\r\n"), "unexpected response: {}", response);
+
+        // Inner middleware can short-circuit or rewrite; the outer one still tags the response
+        let blocked = send_http_request(port, "GET /blocked HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(blocked.contains("HTTP/1.1 429 Too Many Requests"), "unexpected response: {}", blocked);
+        assert!(blocked.contains("X-Request-Id: "));
+        let rewritten = send_http_request(port, "GET /old HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(rewritten.contains("HTTP/1.1 200 OK"), "unexpected response: {}", rewritten);
+        assert!(rewritten.contains("Hello, World!"));
+    }
+
+    #[test]
+    fn test_request_id_propagation() {
+        let port = 9375;
+        let mut config = ServerConfig::default();
+        config.server.request_id_header = "X-Request-Id".to_string();
+        let (_server_handle, log) = start_test_server_with_log_capture(port, config);
+        wait_for_server(port);
+
+        // A trusted incoming id is reused, echoed and logged
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: trace-42\r\n\r\n");
+        assert!(response.contains("X-Request-Id: trace-42\r\n"), "unexpected response: {}", response);
+
+        // Otherwise one is generated
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: two words\r\n\r\n");
+        let generated = response.lines()
+            .find_map(|line| line.strip_prefix("X-Request-Id: "))
+            .expect("X-Request-Id header")
+            .to_string();
+        assert!(!generated.is_empty() && generated != "two words", "unexpected response: {}", response);
+        thread::sleep(Duration::from_millis(100));
+
+        let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+        assert!(log.contains("GET - /hello 200 ") && log.contains(" request_id=trace-42\n"), "unexpected log: {}", log);
+        assert!(log.contains(&format!(" request_id={}\n", generated)), "unexpected log: {}", log);
+    }
+
+    // Reports where the upload body was kept and its size and byte sum as read by the handler
+    fn upload_handler(request: &HttpRequest) -> HttpResponse {
+        let mut body = Vec::new();
+        request.body_reader().and_then(|mut reader| reader.read_to_end(&mut body)).expect("readable body");
+        let location = match &request.spooled_body {
+            Some(spooled) => format!("file {} exists={}", spooled.path().display(), spooled.path().exists()),
+            None => "memory".to_string(),
+        };
+        let sum: u64 = body.iter().map(|&b| u64::from(b)).sum();
+        HttpResponse::new(200, "OK")
+            .with_content_type("text/plain")
+            .with_body(&format!("{}\nlen={} read={} sum={}", location, request.body_len(), body.len(), sum))
+    }
+
+    #[test]
+    fn test_large_upload_is_spooled_to_temp_file() {
+        let port = 9361;
+        let spool_dir = std::env::temp_dir().join(format!("http_spool_test_{}", std::process::id()));
+        std::fs::create_dir_all(&spool_dir).unwrap();
+        let mut config = ServerConfig::default();
+        config.connection.body_spool_threshold_bytes = 4096;
+        config.connection.body_spool_directory = spool_dir.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_setup(port, config, |server| {
+            server.add_route("POST", "/upload", upload_handler);
+        });
+        wait_for_server(port);
+
+        // Above the threshold the body is written to a file the handler can read back
+        let payload: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let expected_sum: u64 = payload.iter().map(|&b| u64::from(b)).sum();
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        let head = format!("POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", payload.len());
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(&payload).unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.contains(&format!("len=100000 read=100000 sum={}", expected_sum)), "unexpected response: {}", response);
+        let spooled_path = response.lines()
+            .find_map(|line| line.strip_prefix("file ")?.strip_suffix(" exists=true"))
+            .expect("body spooled to an existing file");
+        assert!(std::path::Path::new(spooled_path).starts_with(&spool_dir));
+
+        // The file is removed once the request has been handled
+        assert!(!std::path::Path::new(spooled_path).exists());
+        assert_eq!(std::fs::read_dir(&spool_dir).unwrap().count(), 0);
+
+        // Small bodies stay in memory
+        let small = send_http_request(port, "POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello");
+        assert!(small.contains("memory\nlen=5 read=5"), "unexpected response: {}", small);
+
+        let _ = std::fs::remove_dir_all(&spool_dir);
+    }
+
+    #[test]
+    fn test_spooled_uploads_use_their_own_size_limit() {
+        let port = 9388;
+        let spool_dir = std::env::temp_dir().join(format!("http_spool_limit_test_{}", std::process::id()));
+        std::fs::create_dir_all(&spool_dir).unwrap();
+        let mut config = ServerConfig::default();
+        config.connection.max_request_body_bytes = 1000;
+        config.connection.body_spool_threshold_bytes = 4096;
+        config.connection.max_spooled_body_bytes = 200_000;
+        config.connection.body_spool_directory = spool_dir.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_setup(port, config, |server| {
+            server.add_route("POST", "/upload", upload_handler);
+        });
+        wait_for_server(port);
+
+        let status_for = |length: usize, send_body: bool| {
+            let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+            let head = format!("POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", length);
+            stream.write_all(head.as_bytes()).unwrap();
+            if send_body {
+                stream.write_all(&vec![b'x'; length]).unwrap();
+            }
+            read_http_response(&mut stream)
+        };
+
+        // Kept in memory, so held to max_request_body_bytes
+        let response = status_for(2000, false);
+        assert!(response.starts_with("HTTP/1.1 413 "), "unexpected response: {}", response);
+
+        // Spooled: over max_request_body_bytes but within max_spooled_body_bytes
+        let response = status_for(100_000, true);
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.contains("len=100000 read=100000"), "unexpected response: {}", response);
+
+        // Spooled bodies still have a limit
+        let response = status_for(300_000, false);
+        assert!(response.starts_with("HTTP/1.1 413 "), "unexpected response: {}", response);
+
+        let _ = std::fs::remove_dir_all(&spool_dir);
+    }
+
+    #[test]
+    fn test_recording_mode_captures_redacted_exchanges() {
+        let port = 9365;
+        let record_file = std::env::temp_dir().join(format!("http_recording_test_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&record_file);
+        let mut config = ServerConfig::default();
+        config.recording.enabled = true;
+        config.recording.max_body_bytes = 8;
+        config.recording.file = record_file.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let body = r#"{"username": "admin", "password": "password123"}"#;
+        let login_request = format!(
+            "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let login_response = send_http_request(port, &login_request);
+        let token_start = login_response.find("\"token\":\"").unwrap() + 9;
+        let token_end = login_response[token_start..].find('"').unwrap() + token_start;
+        let token = &login_response[token_start..token_end];
+
+        let request = format!(
+            "POST /api/echo HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\nCookie: session=secret\r\nContent-Length: 16\r\n\r\n0123456789abcdef",
+            token
+        );
+        assert!(send_http_request(port, &request).contains("HTTP/1.1 200 OK"));
+
+        // Each exchange is appended to the file as one JSON line
+        let recorded = std::fs::read_to_string(&record_file).unwrap();
+        let echo = recorded.lines()
+            .map(|line| JsonValue::parse(line).unwrap())
+            .find(|entry| entry.get("request").and_then(|r| r.get("path")).and_then(JsonValue::as_str) == Some("/api/echo"))
+            .expect("recorded /api/echo exchange");
+        let recorded_request = echo.get("request").unwrap();
+        assert_eq!(recorded_request.get("method").and_then(JsonValue::as_str), Some("POST"));
+        let headers = recorded_request.get("headers").unwrap();
+        assert_eq!(headers.get("authorization").and_then(JsonValue::as_str), Some("***"));
+        assert_eq!(headers.get("cookie").and_then(JsonValue::as_str), Some("***"));
+        assert_eq!(headers.get("host").and_then(JsonValue::as_str), Some("localhost"));
+        assert_eq!(recorded_request.get("body").and_then(JsonValue::as_str), Some("01234567"));
+        assert_eq!(recorded_request.get("body_length"), Some(&JsonValue::Number(16.0)));
+        assert_eq!(recorded_request.get("body_truncated"), Some(&JsonValue::Bool(true)));
+        let recorded_response = echo.get("response").unwrap();
+        assert_eq!(recorded_response.get("status"), Some(&JsonValue::Number(200.0)));
+        assert!(recorded_response.get("headers").and_then(|h| h.get("Content-Type")).is_some());
+
+        // Credentials and issued tokens never reach the recording
+        assert!(!recorded.contains("password123"), "password recorded: {}", recorded);
+        assert!(!recorded.contains(token), "token recorded: {}", recorded);
+        let login = recorded.lines()
+            .map(|line| JsonValue::parse(line).unwrap())
+            .find(|entry| entry.get("request").and_then(|r| r.get("path")).and_then(JsonValue::as_str) == Some("/api/login"))
+            .expect("recorded /api/login exchange");
+        assert_eq!(login.get("request").and_then(|r| r.get("body")).and_then(JsonValue::as_str), Some("***"));
+        assert_eq!(login.get("request").and_then(|r| r.get("body_length")), Some(&JsonValue::Number(body.len() as f64)));
+        assert_eq!(login.get("response").and_then(|r| r.get("body")).and_then(JsonValue::as_str), Some("***"));
+
+        // The in-memory buffer is listed for authenticated clients
+        let anonymous = send_http_request(port, "GET /admin/recordings HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(anonymous.contains("HTTP/1.1 401 Unauthorized"));
+        let listing = send_http_request(port, &format!("GET /admin/recordings HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\r\n", token));
+        assert!(listing.contains("HTTP/1.1 200 OK"));
+        let entries = JsonValue::parse(&listing[listing.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        let JsonValue::Array(entries) = entries else { panic!("expected an array: {}", listing) };
+        assert!(entries.contains(&echo));
+
+        let _ = std::fs::remove_file(&record_file);
+    }
 }
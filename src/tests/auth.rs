@@ -1,9 +1,65 @@
 #[cfg(test)]
 mod tests {
     use api::{
-        generate_salt, hash_password, verify_password, hex_encode, hex_decode,
-        TokenManager, parse_login_request
+        generate_salt, hash_password, verify_password, constant_time_eq, hex_encode, hex_decode,
+        TokenManager, parse_login_request, parse_authorization,
+        LoginRateLimiter, LoginAttempts, LoginAttemptStore, SessionStore, AuthToken, MIN_TOKEN_ENTROPY_CHARS,
+        create_error_response, create_login_response, JsonValue
     };
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    // Stand-in for a persistent backend that also counts how often it is written
+    #[derive(Default)]
+    struct MockStore {
+        records: Mutex<HashMap<String, LoginAttempts>>,
+        saves: Mutex<usize>,
+    }
+
+    impl LoginAttemptStore for MockStore {
+        fn load(&self, key: &str) -> Option<LoginAttempts> {
+            self.records.lock().unwrap().get(key).cloned()
+        }
+
+        fn save(&self, key: &str, attempts: &LoginAttempts) {
+            *self.saves.lock().unwrap() += 1;
+            self.records.lock().unwrap().insert(key.to_string(), attempts.clone());
+        }
+
+        fn clear(&self, key: &str) {
+            self.records.lock().unwrap().remove(key);
+        }
+    }
+
+    // Session backend that records which operations TokenManager sends through it
+    #[derive(Default)]
+    struct MockSessionStore {
+        sessions: Mutex<HashMap<String, AuthToken>>,
+        calls: Mutex<Vec<&'static str>>,
+    }
+
+    impl SessionStore for MockSessionStore {
+        fn insert(&self, token: &str, session: AuthToken) {
+            self.calls.lock().unwrap().push("insert");
+            self.sessions.lock().unwrap().insert(token.to_string(), session);
+        }
+
+        fn get(&self, token: &str) -> Option<AuthToken> {
+            self.calls.lock().unwrap().push("get");
+            self.sessions.lock().unwrap().get(token).cloned()
+        }
+
+        fn remove(&self, token: &str) -> bool {
+            self.calls.lock().unwrap().push("remove");
+            self.sessions.lock().unwrap().remove(token).is_some()
+        }
+
+        fn retain(&self, keep: &dyn Fn(&AuthToken) -> bool) {
+            self.calls.lock().unwrap().push("retain");
+            self.sessions.lock().unwrap().retain(|_, session| keep(session));
+        }
+    }
 
     #[test]
     fn test_password_hashing() {
@@ -61,6 +117,27 @@ mod tests {
         assert_eq!(token_manager.validate_token("invalid_token"), None);
     }
 
+    #[test]
+    fn test_token_prefix_and_entropy_length() {
+        let token_manager = TokenManager::new().with_token_format("tok_", 40);
+        let token = token_manager.generate_token("testuser");
+
+        // The prefix comes on top of the requested random characters
+        let random = token.strip_prefix("tok_").expect("token should carry the prefix");
+        assert_eq!(random.len(), 40);
+        assert!(random.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(token_manager.validate_token(&token), Some("testuser".to_string()));
+        assert_eq!(token_manager.validate_token(random), None);
+
+        // Too little entropy is raised to the minimum
+        let short = TokenManager::new().with_token_format("t-", 4).generate_token("testuser");
+        assert_eq!(short.len(), "t-".len() + MIN_TOKEN_ENTROPY_CHARS);
+
+        // Tokens issued back to back are all distinct
+        let tokens: std::collections::HashSet<String> = (0..100).map(|_| token_manager.generate_token("testuser")).collect();
+        assert_eq!(tokens.len(), 100);
+    }
+
     #[test]
     fn test_token_revocation() {
         let token_manager = TokenManager::new();
@@ -94,4 +171,104 @@ mod tests {
         let invalid_json = r#"{"username": "test"}"#; // missing password
         assert!(parse_login_request(invalid_json).is_none());
     }
+
+    #[test]
+    fn test_json_parsing_special_characters_in_values() {
+        let json = r#"{"username": "a:b,c", "password": "p,a:s\"s\\w{o}rd"}"#;
+        let (username, password) = parse_login_request(json).unwrap();
+        assert_eq!(username, "a:b,c");
+        assert_eq!(password, r#"p,a:s"s\w{o}rd"#);
+
+        // Non-string values are rejected rather than coerced
+        assert!(parse_login_request(r#"{"username": "u", "password": 1234}"#).is_none());
+        assert!(parse_login_request("username=u&password=p").is_none());
+    }
+
+    #[test]
+    fn test_parse_authorization_header() {
+        assert_eq!(parse_authorization("Basic abc"), Some(("Basic", "abc")));
+        assert_eq!(parse_authorization("Bearer xyz"), Some(("Bearer", "xyz")));
+        assert_eq!(parse_authorization("Bearer   padded  "), Some(("Bearer", "padded")));
+
+        // Malformed headers are rejected rather than sliced
+        assert_eq!(parse_authorization("Basic"), None);
+        assert_eq!(parse_authorization("Basic "), None);
+        assert_eq!(parse_authorization(""), None);
+        assert_eq!(parse_authorization(" token"), None);
+    }
+
+    #[test]
+    fn test_login_attempts_persisted_through_store() {
+        let store = Arc::new(MockStore::default());
+        let window = Duration::from_secs(300);
+        let lockout = Duration::from_secs(60);
+
+        let limiter = LoginRateLimiter::new(3, window, lockout).with_store(store.clone());
+        limiter.record_failure("alice");
+        limiter.record_failure("alice");
+        assert_eq!(*store.saves.lock().unwrap(), 2);
+        assert_eq!(store.load("alice").unwrap().count, 2);
+        assert!(limiter.check("alice").is_none());
+
+        // A fresh limiter (e.g. after a restart) picks up the stored failures
+        let restarted = LoginRateLimiter::new(3, window, lockout).with_store(store.clone());
+        restarted.record_failure("alice");
+        let retry_after = restarted.check("alice").expect("alice should be locked out");
+        assert!(retry_after <= lockout && retry_after > Duration::ZERO);
+        assert!(store.load("alice").unwrap().locked_until.is_some());
+
+        // Success clears the stored state
+        restarted.record_success("bob");
+        restarted.record_failure("bob");
+        restarted.record_success("bob");
+        assert!(store.load("bob").is_none());
+    }
+
+    #[test]
+    fn test_auth_responses_escape_json() {
+        let message = r#"Bad "quoted" value \ with backslash"#;
+        let parsed = JsonValue::parse(&create_error_response(message)).expect("error response must be valid JSON");
+        assert_eq!(parsed.get("success"), Some(&JsonValue::Bool(false)));
+        assert_eq!(parsed.get("error").and_then(JsonValue::as_str), Some(message));
+
+        let token = "tok\"en";
+        let parsed = JsonValue::parse(&create_login_response(token)).expect("login response must be valid JSON");
+        assert_eq!(parsed.get("token").and_then(JsonValue::as_str), Some(token));
+    }
+
+    #[test]
+    fn test_token_manager_uses_session_store() {
+        let store = Arc::new(MockSessionStore::default());
+        let manager = TokenManager::new().with_store(store.clone());
+
+        let token = manager.generate_token("alice");
+        assert_eq!(store.sessions.lock().unwrap().get(&token).map(|session| session.username.clone()), Some("alice".to_string()));
+        assert_eq!(manager.validate_token(&token), Some("alice".to_string()));
+
+        // Sessions written by another instance sharing the store are honoured
+        let shared = TokenManager::new().with_store(store.clone());
+        assert_eq!(shared.validate_token(&token), Some("alice".to_string()));
+
+        assert!(manager.revoke_token(&token));
+        assert_eq!(shared.validate_token(&token), None);
+
+        // Expired sessions are dropped by cleanup
+        store.insert("stale", AuthToken { token: "stale".to_string(), username: "bob".to_string(), expires_at: 1 });
+        manager.cleanup_expired_tokens();
+        assert!(store.sessions.lock().unwrap().is_empty());
+
+        assert_eq!(
+            *store.calls.lock().unwrap(),
+            vec!["insert", "get", "get", "remove", "get", "insert", "retain"]
+        );
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"csrf-token", b"csrf-token"));
+        assert!(!constant_time_eq(b"csrf-token", b"csrf-tokeN"));
+        assert!(!constant_time_eq(b"csrf-token", b"csrf-toke"));
+        assert!(!constant_time_eq(b"a", b""));
+    }
 }
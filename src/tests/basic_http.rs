@@ -1,4 +1,5 @@
 use super::helpers::*;
+use api::{HttpServer, JsonValue};
 
 #[cfg(test)]
 mod tests {
@@ -71,6 +72,30 @@ mod tests {
         assert!(response.contains("404 - Page Not Found"));
     }
 
+    #[test]
+    fn test_head_derived_from_get_route() {
+        let port = 9358;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "HEAD /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.contains("Content-Type: text/plain"));
+        assert!(response.contains("Content-Length: 13\r\n")); // "Hello, World!"
+        assert!(response.ends_with("\r\n\r\n"), "HEAD response must not have a body: {:?}", response);
+
+        // The GET handler sees the query string, so the length follows it
+        let response = send_http_request(port, "HEAD /hello?name=Rust HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("Content-Length: 12\r\n"), "unexpected response: {}", response);
+        assert!(response.ends_with("\r\n\r\n"));
+
+        // A chunked GET route: the HEAD response ends right after the headers, with no final chunk
+        let response = send_http_request(port, "HEAD /chunked HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "unexpected response: {}", response);
+        assert!(!response.to_ascii_lowercase().contains("transfer-encoding"), "unexpected response: {}", response);
+        assert!(response.ends_with("\r\n\r\n") && !response.contains("\r\n\r\n0"), "HEAD response must not have a body: {:?}", response);
+    }
+
     #[test]
     fn test_unsupported_method() {
         let port = 8085;
@@ -80,9 +105,9 @@ mod tests {
         let request = "PATCH /hello HTTP/1.1\r\nHost: localhost\r\n\r\n";
         let response = send_http_request(port, request);
 
-        // PATCH is not supported for /hello route, should return 404
-        assert!(response.contains("HTTP/1.1 404 Not Found"));
-        assert!(response.contains("404 - Page Not Found"));
+        // /hello exists but not for PATCH: 405 listing the methods it does support
+        assert!(response.contains("HTTP/1.1 405 Method Not Allowed"), "unexpected response: {}", response);
+        assert!(response.contains("Allow: GET, HEAD\r\n"));
     }
 
     #[test]
@@ -158,5 +183,116 @@ mod tests {
         assert!(response.contains("Query Parameters:"));
         assert!(response.contains("name: Test"));
         assert!(response.contains("type: query"));
+
+        // Decoded parameters are HTML-escaped, not reflected as markup
+        let request = "GET /?x=%3Cscript%3Ealert(1)%3C/script%3E HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let response = send_http_request(port, request);
+        assert!(response.contains("x: &lt;script&gt;alert(1)&lt;/script&gt;"), "unexpected response: {}", response);
+        assert!(!response.contains("<script>"));
+    }
+
+    #[test]
+    fn test_handle_raw_without_sockets() {
+        // Port 0: the listener is never used, requests go straight through the pipeline
+        let server = HttpServer::new("127.0.0.1:0").unwrap();
+
+        let response = String::from_utf8(server.handle_raw(b"GET /hello?name=Raw HTTP/1.1\r\nHost: localhost\r\n\r\n")).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "unexpected response: {}", response);
+        assert!(response.contains("Content-Length: 11\r\n"));
+        assert!(response.contains("Connection: keep-alive\r\n"));
+        assert!(response.ends_with("\r\n\r\nHello, Raw!"));
+
+        let response = String::from_utf8(server.handle_raw(b"GET /hello\r\n\r\n")).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_specific_failure() {
+        let server = HttpServer::new("127.0.0.1:0").unwrap();
+        let body = |response: &str| response.split_once("\r\n\r\n").unwrap().1.to_string();
+
+        let empty = String::from_utf8(server.handle_raw(b"")).unwrap();
+        let bad_line = String::from_utf8(server.handle_raw(b"GET\r\nHost: localhost\r\n\r\n")).unwrap();
+        let bad_method = String::from_utf8(server.handle_raw(b"G(T / HTTP/1.1\r\nHost: localhost\r\n\r\n")).unwrap();
+
+        for (response, code, message) in [
+            (&empty, "empty_request", "Empty request"),
+            (&bad_line, "invalid_request_line", "Invalid request line"),
+            (&bad_method, "invalid_method", "Invalid method &quot;G(T&quot;"),
+        ] {
+            assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "unexpected response: {}", response);
+            assert!(response.contains(&format!("X-Parse-Error: {}\r\n", code)), "unexpected response: {}", response);
+            assert!(body(response).contains(&format!("<code>{}</code>", code)), "unexpected response: {}", response);
+            assert!(body(response).contains(message), "unexpected response: {}", response);
+        }
+        assert_ne!(body(&empty), body(&bad_line));
+    }
+
+    #[test]
+    fn test_echo_binary_body_as_hex() {
+        let port = 9332;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let mut request = b"POST /api/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 6\r\nConnection: close\r\n\r\n".to_vec();
+        request.extend_from_slice(b"\x89PNG\x00\xff");
+        let mut stream = std::net::TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        std::io::Write::write_all(&mut stream, &request).unwrap();
+        let response = read_http_response(&mut stream);
+
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(response.ends_with(r#""body_hex":"89504e4700ff"}"#), "unexpected response: {}", response);
+
+        // Text bodies are still echoed as text
+        let response = send_http_request(port, "POST /api/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello");
+        assert!(response.ends_with(r#""body":"hello"}"#), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_echo_escapes_body_in_json() {
+        let port = 9377;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let body = "say \"hi\"\\n\r\n\ttab \u{1}";
+        let request = format!(
+            "POST /api/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(), body
+        );
+        let response = send_http_request(port, &request);
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(response.contains("Content-Type: application/json\r\n"));
+
+        let json = JsonValue::parse(response.split_once("\r\n\r\n").unwrap().1).expect("echo response must be valid JSON");
+        assert_eq!(json.get("body").and_then(JsonValue::as_str), Some(body));
+        assert_eq!(json.get("method").and_then(JsonValue::as_str), Some("POST"));
+    }
+
+    // Send raw request bytes and return the raw response bytes
+    fn send_raw_bytes(port: u16, request: &[u8]) -> Vec<u8> {
+        let mut stream = std::net::TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        std::io::Write::write_all(&mut stream, request).unwrap();
+        let mut response = Vec::new();
+        std::io::Read::read_to_end(&mut stream, &mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_latin1_body_is_transcoded() {
+        let port = 9364;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        // "café ñ" in ISO-8859-1 is not valid UTF-8, but is decoded by its declared charset
+        let latin1 = b"caf\xe9 \xf1";
+        let head = "POST /api/echo HTTP/1.1\r\nHost: localhost\r\nContent-Type: text/plain; charset=ISO-8859-1\r\nContent-Length: 6\r\nConnection: close\r\n\r\n";
+        let response = String::from_utf8(send_raw_bytes(port, &[head.as_bytes(), latin1].concat())).unwrap();
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(response.ends_with(r#""body":"café ñ"}"#), "unexpected response: {}", response);
+
+        // The raw echo still returns the bytes exactly as sent
+        let head = head.replace("/api/echo", "/api/echo/raw");
+        let response = send_raw_bytes(port, &[head.as_bytes(), latin1].concat());
+        assert!(response.ends_with(latin1), "unexpected response: {}", String::from_utf8_lossy(&response));
     }
 }
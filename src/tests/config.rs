@@ -0,0 +1,200 @@
+#[cfg(test)]
+mod tests {
+    use api::ServerConfig;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_config_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("api_{}_{}.toml", name, std::process::id()))
+    }
+
+    // A configuration where every field differs from the defaults
+    fn non_default_config() -> ServerConfig {
+        let mut config = ServerConfig::default();
+        config.server.host = "0.0.0.0".to_string();
+        config.server.port = 9999;
+        config.server.read_timeout_seconds = 5;
+        config.server.write_timeout_seconds = 7;
+        config.server.strict_parsing = true;
+        config.server.default_routes = false;
+        config.server.metrics_enabled = false;
+        config.server.root_message = "Nothing to see here".to_string();
+        config.server.root_redirect = "/docs".to_string();
+        config.server.redirect_allowed_hosts = vec!["login.example.com".to_string(), "cdn.example.net".to_string()];
+        config.server.server_name = "acme \"edge\"".to_string();
+        config.server.server_version = "2.4.1-beta".to_string();
+        config.server.request_id_header = "traceparent".to_string();
+        config.threading.worker_threads = 9;
+        config.threading.max_concurrent_connections = 321;
+        config.connection.max_idle_connections = 3;
+        config.connection.idle_timeout_seconds = 11;
+        config.connection.keep_alive_timeout_seconds = 13;
+        config.connection.buffer_size = 4096;
+        config.connection.chunked_threshold_bytes = 2048;
+        config.connection.send_408_on_idle_timeout = false;
+        config.connection.max_line_length = 1024;
+        config.connection.max_header_bytes = 16384;
+        config.connection.max_request_body_bytes = 2_000_000;
+        config.connection.max_requests_per_connection = 25;
+        config.connection.max_pipelined_requests = 8;
+        config.connection.default_keep_alive = false;
+        config.connection.body_spool_threshold_bytes = 1048576;
+        config.connection.body_spool_directory = "/var/tmp/http uploads".to_string();
+        config.connection.max_spooled_body_bytes = 50_000_000;
+        config.static_files.enabled = false;
+        config.static_files.directory = "public #1 \"=\" \\\\ dir".to_string();
+        config.static_files.index_file = "home.html".to_string();
+        config.static_files.directory_listing = false;
+        config.static_files.content_sniffing = true;
+        config.static_files.log_file_reads = true;
+        config.static_files.precompressed = true;
+        config.static_files.large_file_warning_bytes = 4096;
+        config.static_files.max_path_depth = 6;
+        config.static_files.path_depth_status = 403;
+        config.static_files.host_directories.insert("docs.example.com".to_string(), "sites/docs".to_string());
+        config.authentication.enabled = false;
+        config.authentication.max_failed_logins = 7;
+        config.authentication.failed_login_window_seconds = 60;
+        config.authentication.login_lockout_seconds = 120;
+        config.authentication.password_min_length = 12;
+        config.authentication.password_require_uppercase = true;
+        config.authentication.password_require_lowercase = true;
+        config.authentication.password_require_digit = true;
+        config.authentication.password_require_symbol = true;
+        config.authentication.token_cookie_enabled = true;
+        config.authentication.token_cookie_name = "session".to_string();
+        config.authentication.token_cookie_secure = false;
+        config.authentication.token_cookie_same_site = "Lax".to_string();
+        config.authentication.csrf_protection = false;
+        config.authentication.options_without_auth = false;
+        config.authentication.token_prefix = "tok_".to_string();
+        config.authentication.token_entropy_chars = 48;
+        config.authentication.users = HashMap::from([
+            ("alice".to_string(), "00112233:aabbccdd".to_string()),
+            ("bob smith=#1".to_string(), "44556677:eeff0011".to_string()),
+        ]);
+        config.authentication.protected_paths = vec!["/private".to_string(), "/reports".to_string()];
+        config.logging.enabled = false;
+        config.logging.level = "error".to_string();
+        config.logging.log_requests = false;
+        config.logging.log_responses = true;
+        config.logging.log_connections = true;
+        config.logging.file = "/var/log/http server.log".to_string();
+        config.tls.cert_file = "certs/server.pem".to_string();
+        config.tls.key_file = "certs/server.key".to_string();
+        config.compression.enabled = true;
+        config.compression.min_size_bytes = 256;
+        config.compression.content_types = vec!["text/html".to_string(), "Application/JSON".to_string()];
+        config.compression.chunked_responses = true;
+        config.cors.allowed_origins = vec!["https://app.example.com".to_string(), "http://localhost:3000".to_string()];
+        config.cors.allowed_methods = vec!["GET".to_string(), "patch".to_string()];
+        config.cors.allowed_headers = vec!["X-Requested-With".to_string()];
+        config.recording.enabled = true;
+        config.recording.max_entries = 7;
+        config.recording.max_body_bytes = 512;
+        config.recording.file = "/tmp/exchanges #1.jsonl".to_string();
+        config
+    }
+
+    #[test]
+    fn test_config_toml_round_trip() {
+        let config = non_default_config();
+        let path = temp_config_path("round_trip");
+
+        config.save_to_file(&path).unwrap();
+        let reloaded = ServerConfig::load_from_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(reloaded, config);
+
+        // Empty collections stay empty instead of falling back to the defaults
+        let mut config = non_default_config();
+        config.authentication.users.clear();
+        config.authentication.protected_paths.clear();
+        config.server.redirect_allowed_hosts.clear();
+        config.cors.allowed_origins.clear();
+        config.compression.content_types.clear();
+        config.save_to_file(&path).unwrap();
+        let reloaded = ServerConfig::load_from_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(reloaded, config);
+    }
+
+    #[test]
+    fn test_config_file_users_replace_defaults() {
+        let path = temp_config_path("users_replace");
+        fs::write(&path, "[authentication]\nuser_carol = \"aa:bb\"\nprotected_path_1 = \"/admin\"\n").unwrap();
+        let config = ServerConfig::load_from_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(config.authentication.users.len(), 1);
+        assert_eq!(config.authentication.users.get("carol"), Some(&"aa:bb".to_string()));
+        // The default "/admin" entry is not duplicated
+        assert_eq!(config.authentication.protected_paths, vec!["/admin".to_string()]);
+    }
+
+    #[test]
+    fn test_config_auth_section_without_users_keeps_defaults() {
+        let path = temp_config_path("auth_defaults");
+        fs::write(&path, "[authentication]\nmax_failed_logins = 3\n").unwrap();
+        let config = ServerConfig::load_from_file(&path).unwrap();
+
+        let defaults = ServerConfig::default();
+        assert_eq!(config.authentication.max_failed_logins, 3);
+        assert_eq!(config.authentication.protected_paths, defaults.authentication.protected_paths);
+        let mut usernames: Vec<&String> = config.authentication.users.keys().collect();
+        usernames.sort();
+        assert_eq!(usernames, vec!["admin", "user"]);
+
+        // Empty lists are stated explicitly
+        fs::write(&path, "[authentication]\nusers = []\nprotected_paths = []\n").unwrap();
+        let config = ServerConfig::load_from_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert!(config.authentication.users.is_empty());
+        assert!(config.authentication.protected_paths.is_empty());
+    }
+
+    #[test]
+    fn test_config_quoted_values_and_comments() {
+        let path = temp_config_path("quoted_values");
+        let contents = concat!(
+            "[server] # main settings\n",
+            "port = 9090 # trailing comment\n",
+            "[static_files]\n",
+            "directory = \"a=b#c\"  # not part of the value\n",
+            "index_file = \"say \\\"hi\\\".html\"\n",
+        );
+        fs::write(&path, contents).unwrap();
+        let config = ServerConfig::load_from_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(config.server.port, 9090);
+        assert_eq!(config.static_files.directory, "a=b#c");
+        assert_eq!(config.static_files.index_file, "say \"hi\".html");
+    }
+
+    #[test]
+    fn test_config_unterminated_string_is_rejected() {
+        let path = temp_config_path("unterminated");
+        fs::write(&path, "[static_files]\ndirectory = \"public\n").unwrap();
+        let result = ServerConfig::load_from_file(&path);
+        fs::remove_file(&path).ok();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_log_level() {
+        let path = temp_config_path("log_level");
+        fs::write(&path, "[logging]\nlevel = \"verbose\"\n").unwrap();
+        let result = ServerConfig::load_from_file(&path);
+        fs::write(&path, "[logging]\nlevel = \"Warning\"\n").unwrap();
+        let config = ServerConfig::load_from_file(&path);
+        fs::remove_file(&path).ok();
+
+        assert!(result.is_err());
+        assert_eq!(config.unwrap().logging.level, "warning");
+    }
+}
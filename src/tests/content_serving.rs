@@ -1,4 +1,10 @@
 use super::helpers::*;
+use api::{ChunkedWriter, HttpRequest, HttpResponse, JsonValue, ServerConfig};
+use flate2::read::GzDecoder;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
 
 // =======================
 // STEP 7: CONTENT SERVING TESTS
@@ -82,6 +88,22 @@ mod tests {
         assert!(response.contains("📄"));
     }
 
+    #[test]
+    fn test_static_dir_without_trailing_slash_redirects() {
+        let port = 9353;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /static HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 302 Found"), "unexpected response: {}", response);
+        assert!(response.contains("Location: /static/\r\n"));
+        assert!(response.contains("<a href=\"/static/\">"));
+
+        // The query string is carried over to the new location
+        let response = send_http_request(port, "GET /static?sort=name HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("Location: /static/?sort=name\r\n"), "unexpected response: {}", response);
+    }
+
     #[test]
     fn test_directory_listing_assets() {
         let port = 9006;
@@ -137,4 +159,591 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_content_sniffing_extensionless_png() {
+        let port = 9303;
+        let static_dir = std::env::temp_dir().join(format!("http_server_sniffing_{}", std::process::id()));
+        fs::create_dir_all(&static_dir).unwrap();
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+        fs::write(static_dir.join("logo"), png).unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        config.static_files.content_sniffing = true;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /logo HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        fs::remove_dir_all(&static_dir).ok();
+
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.contains("Content-Type: image/png"));
+        assert!(response.contains(&format!("Content-Length: {}", png.len())));
+    }
+
+    #[test]
+    fn test_non_utf8_static_file_served_as_bytes() {
+        let port = 9384;
+        let static_dir = std::env::temp_dir().join(format!("http_server_binary_{}", std::process::id()));
+        fs::create_dir_all(&static_dir).unwrap();
+        // Invalid UTF-8 throughout: lone continuation bytes, 0xff and a NUL
+        let image: Vec<u8> = b"\x89PNG\r\n\x1a\n".iter().copied().chain((0..=255u8).rev()).chain([0x80, 0xff, 0x00]).collect();
+        fs::write(static_dir.join("photo.png"), &image).unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let (head, body) = fetch_raw(port, "/photo.png", "");
+        fs::remove_dir_all(&static_dir).ok();
+
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "unexpected response: {}", head);
+        assert!(head.contains("Content-Type: image/png"), "unexpected response: {}", head);
+        assert!(head.contains(&format!("Content-Length: {}", image.len())));
+        assert_eq!(body, image);
+    }
+
+    #[test]
+    fn test_large_static_file_is_logged() {
+        let port = 9323;
+        let static_dir = std::env::temp_dir().join(format!("http_server_large_file_{}", std::process::id()));
+        fs::create_dir_all(&static_dir).unwrap();
+        fs::write(static_dir.join("big.bin"), vec![b'x'; 200_000]).unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        config.static_files.log_file_reads = true;
+        config.static_files.large_file_warning_bytes = 100_000;
+        let (_server_handle, log) = start_test_server_with_log_capture(port, config);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /big.bin HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        fs::remove_dir_all(&static_dir).ok();
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", &response[..response.len().min(200)]);
+
+        let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+        assert!(log.contains("INFO: Read static file") && log.contains("(200000 bytes in "), "unexpected log: {}", log);
+        assert!(log.contains("WARNING: Large static response") && log.contains("threshold 100000"), "unexpected log: {}", log);
+    }
+
+    #[test]
+    fn test_precompressed_sidecars_vary_on_accept_encoding() {
+        let port = 9328;
+        let static_dir = std::env::temp_dir().join(format!("http_server_precompressed_{}", std::process::id()));
+        fs::create_dir_all(&static_dir).unwrap();
+        fs::write(static_dir.join("app.js"), "console.log('plain');").unwrap();
+        fs::write(static_dir.join("app.js.gz"), b"\x1f\x8bgzip-bytes").unwrap();
+        fs::write(static_dir.join("app.js.br"), b"brotli-bytes").unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        config.static_files.precompressed = true;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let request = |accept_encoding: &str| {
+            send_http_request(port, &format!(
+                "GET /app.js HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", accept_encoding
+            ))
+        };
+        let gzip = request("Accept-Encoding: gzip, br;q=0\r\n");
+        let brotli = request("Accept-Encoding: gzip, deflate, br\r\n");
+        let plain = request("");
+        fs::remove_dir_all(&static_dir).ok();
+
+        assert!(gzip.contains("Content-Encoding: gzip"), "unexpected response: {}", gzip);
+        assert!(gzip.contains("Content-Type: application/javascript"));
+        assert!(gzip.contains("Vary: Accept-Encoding"));
+        assert!(gzip.ends_with("gzip-bytes"));
+
+        assert!(brotli.contains("Content-Encoding: br"), "unexpected response: {}", brotli);
+        assert!(brotli.contains("Vary: Accept-Encoding"));
+        assert!(brotli.ends_with("brotli-bytes"));
+
+        // The uncompressed variant still depends on the request header
+        assert!(!plain.contains("Content-Encoding"), "unexpected response: {}", plain);
+        assert!(plain.contains("Vary: Accept-Encoding"));
+        assert!(plain.ends_with("console.log('plain');"));
+    }
+
+    #[test]
+    fn test_precompressed_sidecars_answer_conditional_requests() {
+        let port = 9391;
+        let static_dir = std::env::temp_dir().join(format!("http_server_precompressed_etag_{}", std::process::id()));
+        fs::create_dir_all(&static_dir).unwrap();
+        fs::write(static_dir.join("app.js"), "console.log('plain');").unwrap();
+        fs::write(static_dir.join("app.js.gz"), b"\x1f\x8bgzip-bytes").unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        config.static_files.precompressed = true;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let request = |headers: &str| {
+            send_http_request(port, &format!("GET /app.js HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", headers))
+        };
+        let etag_of = |response: &str| {
+            response.lines().find_map(|line| line.strip_prefix("ETag: ")).unwrap().to_string()
+        };
+        let gzip = request("Accept-Encoding: gzip\r\n");
+        let plain = request("");
+        let gzip_etag = etag_of(&gzip);
+        let plain_etag = etag_of(&plain);
+        let revalidated = request(&format!("Accept-Encoding: gzip\r\nIf-None-Match: {}\r\n", gzip_etag));
+        let plain_tag_for_gzip = request(&format!("Accept-Encoding: gzip\r\nIf-None-Match: {}\r\n", plain_etag));
+        let since = request("Accept-Encoding: gzip\r\nIf-Modified-Since: Fri, 31 Dec 9999 23:59:59 GMT\r\n");
+        fs::remove_dir_all(&static_dir).ok();
+
+        assert!(gzip_etag.ends_with("-gzip\""), "unexpected ETag: {}", gzip_etag);
+        assert_ne!(gzip_etag, plain_etag);
+        assert!(gzip.contains("Last-Modified: "));
+
+        assert!(revalidated.contains("HTTP/1.1 304 Not Modified"), "unexpected response: {}", revalidated);
+        assert!(revalidated.contains(&format!("ETag: {}", gzip_etag)));
+        assert!(revalidated.contains("Vary: Accept-Encoding"));
+        // The identity copy's tag does not validate the gzip copy
+        assert!(plain_tag_for_gzip.contains("HTTP/1.1 200 OK"), "unexpected response: {}", plain_tag_for_gzip);
+        assert!(plain_tag_for_gzip.ends_with("gzip-bytes"));
+        assert!(since.contains("HTTP/1.1 304 Not Modified"), "unexpected response: {}", since);
+    }
+
+    #[test]
+    fn test_static_path_is_percent_decoded() {
+        let port = 9336;
+        let static_dir = std::env::temp_dir().join(format!("http_server_encoded_path_{}", std::process::id()));
+        fs::create_dir_all(&static_dir).unwrap();
+        fs::write(static_dir.join("my file+1.txt"), "decoded").unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        // %20 is a space; '+' in a path is a literal plus
+        let response = send_http_request(port, "GET /my%20file+1.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        // Encoded dots are still caught by the traversal check
+        let traversal = send_http_request(port, "GET /%2e%2e/etc/passwd HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        fs::remove_dir_all(&static_dir).ok();
+
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.ends_with("decoded"));
+        assert!(traversal.contains("HTTP/1.1 403 Forbidden"), "unexpected response: {}", traversal);
+    }
+
+    #[test]
+    fn test_directory_listing_as_json() {
+        let port = 9337;
+        let static_dir = std::env::temp_dir().join(format!("http_server_json_listing_{}", std::process::id()));
+        fs::create_dir_all(static_dir.join("docs/sub")).unwrap();
+        fs::write(static_dir.join("docs/a.txt"), "hello").unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let json = send_http_request(port, "GET /docs HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\nConnection: close\r\n\r\n");
+        let html = send_http_request(port, "GET /docs HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        fs::remove_dir_all(&static_dir).ok();
+
+        assert!(json.contains("Content-Type: application/json"), "unexpected response: {}", json);
+        let body = json.split("\r\n\r\n").nth(1).unwrap();
+        let JsonValue::Array(entries) = JsonValue::parse(body).unwrap() else {
+            panic!("expected a JSON array: {}", body);
+        };
+        assert_eq!(entries.len(), 2);
+
+        let file = &entries[0];
+        assert_eq!(file.get("name").and_then(JsonValue::as_str), Some("a.txt"));
+        assert_eq!(file.get("is_dir"), Some(&JsonValue::Bool(false)));
+        assert_eq!(file.get("size"), Some(&JsonValue::Number(5.0)));
+        assert!(matches!(file.get("modified"), Some(JsonValue::Number(_))));
+        assert_eq!(entries[1].get("name").and_then(JsonValue::as_str), Some("sub"));
+        assert_eq!(entries[1].get("is_dir"), Some(&JsonValue::Bool(true)));
+
+        // Browsers still get HTML
+        assert!(html.contains("Content-Type: text/html"));
+        assert!(html.contains("Directory Listing"));
+    }
+
+    #[test]
+    fn test_static_path_beyond_max_depth() {
+        let port = 9339;
+        let static_dir = std::env::temp_dir().join(format!("http_server_max_depth_{}", std::process::id()));
+        fs::create_dir_all(static_dir.join("a/b/c/d")).unwrap();
+        fs::write(static_dir.join("a/b/ok.txt"), "shallow").unwrap();
+        fs::write(static_dir.join("a/b/c/d/deep.txt"), "deep").unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        config.static_files.max_path_depth = 3;
+        config.static_files.path_depth_status = 403;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let shallow = send_http_request(port, "GET /a/b/ok.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        let deep = send_http_request(port, "GET /a/b/c/d/deep.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        fs::remove_dir_all(&static_dir).ok();
+
+        assert!(shallow.contains("HTTP/1.1 200 OK"), "unexpected response: {}", shallow);
+        assert!(deep.contains("HTTP/1.1 403 Forbidden"), "unexpected response: {}", deep);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_loop_is_refused() {
+        let port = 9340;
+        let static_dir = std::env::temp_dir().join(format!("http_server_symlink_loop_{}", std::process::id()));
+        fs::create_dir_all(static_dir.join("docs")).unwrap();
+        fs::write(static_dir.join("docs/a.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink("..", static_dir.join("docs/up")).unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let direct = send_http_request(port, "GET /docs/a.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        let looped = send_http_request(port, "GET /docs/up/docs/up/docs/a.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        fs::remove_dir_all(&static_dir).ok();
+
+        assert!(direct.contains("HTTP/1.1 200 OK"), "unexpected response: {}", direct);
+        assert!(looped.contains("HTTP/1.1 404 Not Found"), "unexpected response: {}", looped);
+    }
+
+    #[test]
+    fn test_head_on_static_file_and_directory_listing() {
+        let port = 9344;
+        let static_dir = std::env::temp_dir().join(format!("http_server_head_{}", std::process::id()));
+        fs::create_dir_all(static_dir.join("docs")).unwrap();
+        fs::write(static_dir.join("index.html"), "<h1>Home page</h1>").unwrap();
+        fs::write(static_dir.join("docs/a.txt"), "hello").unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let get_file = send_http_request(port, "GET /index.html HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        let head_file = send_http_request(port, "HEAD /index.html HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        let get_listing = send_http_request(port, "GET /docs HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        let head_listing = send_http_request(port, "HEAD /docs HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        fs::remove_dir_all(&static_dir).ok();
+
+        assert!(head_file.contains("HTTP/1.1 200 OK"), "unexpected response: {}", head_file);
+        assert!(head_file.contains("Content-Type: text/html"));
+        assert!(head_file.contains("Content-Length: 18\r\n"));
+        assert!(head_file.ends_with("\r\n\r\n"), "HEAD response has a body: {}", head_file);
+        assert!(get_file.ends_with("<h1>Home page</h1>"));
+
+        // The listing's Content-Length matches the body a GET receives
+        let listing_body = get_listing.split("\r\n\r\n").nth(1).unwrap();
+        assert!(head_listing.contains("HTTP/1.1 200 OK"), "unexpected response: {}", head_listing);
+        assert!(head_listing.contains(&format!("Content-Length: {}\r\n", listing_body.len())));
+        assert!(head_listing.ends_with("\r\n\r\n"), "HEAD response has a body: {}", head_listing);
+    }
+
+    #[test]
+    fn test_static_root_selected_by_host() {
+        let port = 9348;
+        let base = std::env::temp_dir().join(format!("http_server_vhosts_{}", std::process::id()));
+        for site in ["default", "alpha", "beta"] {
+            fs::create_dir_all(base.join(site)).unwrap();
+            fs::write(base.join(site).join("index.html"), format!("<h1>{} site</h1>", site)).unwrap();
+        }
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = base.join("default").to_string_lossy().into_owned();
+        config.static_files.host_directories.insert("alpha.test".to_string(), base.join("alpha").to_string_lossy().into_owned());
+        config.static_files.host_directories.insert("beta.test".to_string(), base.join("beta").to_string_lossy().into_owned());
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let alpha = send_http_request(port, "GET /index.html HTTP/1.1\r\nHost: alpha.test\r\nConnection: close\r\n\r\n");
+        // Host matching ignores case and the port
+        let beta = send_http_request(port, &format!("GET /index.html HTTP/1.1\r\nHost: BETA.test:{}\r\nConnection: close\r\n\r\n", port));
+        let other = send_http_request(port, "GET /index.html HTTP/1.1\r\nHost: unknown.test\r\nConnection: close\r\n\r\n");
+        fs::remove_dir_all(&base).ok();
+
+        assert!(alpha.ends_with("<h1>alpha site</h1>"), "unexpected response: {}", alpha);
+        assert!(beta.ends_with("<h1>beta site</h1>"), "unexpected response: {}", beta);
+        assert!(other.ends_with("<h1>default site</h1>"), "unexpected response: {}", other);
+    }
+
+    fn large_page_handler(_request: &HttpRequest) -> HttpResponse {
+        HttpResponse::new(200, "OK")
+            .with_content_type("text/html; charset=utf-8")
+            .with_body(&"<p>compress me</p>".repeat(100))
+    }
+
+    fn large_png_handler(_request: &HttpRequest) -> HttpResponse {
+        HttpResponse::new(200, "OK")
+            .with_content_type("image/png")
+            .with_body_bytes(&[0x89; 2000])
+    }
+
+    fn large_chunked_handler(_request: &HttpRequest) -> HttpResponse {
+        HttpResponse::new(200, "OK")
+            .with_content_type("text/plain")
+            .with_chunked_encoding()
+            .with_body(&"chunk ".repeat(500))
+    }
+
+    // The raw response split into its head and body bytes
+    fn fetch_raw(port: u16, path: &str, accept_encoding: &str) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", path, accept_encoding).unwrap();
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+        let header_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        (String::from_utf8_lossy(&response[..header_end]).into_owned(), response[header_end + 4..].to_vec())
+    }
+
+    #[test]
+    fn test_gzip_compression_of_text_responses() {
+        let port = 9351;
+        let mut config = ServerConfig::default();
+        config.compression.enabled = true;
+        config.compression.min_size_bytes = 256;
+        let _server_handle = start_test_server_with_setup(port, config, |server| {
+            server.add_route("GET", "/page", large_page_handler);
+            server.add_route("GET", "/logo.png", large_png_handler);
+            server.add_route("GET", "/stream", large_chunked_handler);
+        });
+        wait_for_server(port);
+
+        let (head, body) = fetch_raw(port, "/page", "Accept-Encoding: gzip, deflate\r\n");
+        assert!(head.contains("Content-Encoding: gzip"), "unexpected response: {}", head);
+        assert!(head.contains("Vary: Accept-Encoding"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        let mut decoded = String::new();
+        GzDecoder::new(body.as_slice()).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "<p>compress me</p>".repeat(100));
+
+        // Clients that did not ask for gzip get the identity body
+        let (head, body) = fetch_raw(port, "/page", "");
+        assert!(!head.contains("Content-Encoding"), "unexpected response: {}", head);
+        assert_eq!(body.len(), 1800);
+        // ...and are told the response varies, so a shared cache keeps the two apart
+        assert!(head.contains("Vary: Accept-Encoding"), "unexpected response: {}", head);
+
+        // HEAD goes through the same negotiation, so its headers match the gzipped GET
+        let (get_head, get_body) = fetch_raw(port, "/page", "Accept-Encoding: gzip\r\n");
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(b"HEAD /page HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n").unwrap();
+        let mut head = String::new();
+        let _ = stream.read_to_string(&mut head);
+        assert!(head.ends_with("\r\n\r\n"), "HEAD response must not have a body: {:?}", head);
+        for header in ["Content-Encoding: gzip", "Vary: Accept-Encoding", &format!("Content-Length: {}", get_body.len())] {
+            assert!(get_head.contains(header) && head.contains(header), "{} missing: {} / {}", header, get_head, head);
+        }
+
+        // Small bodies, already-compressed types and chunked responses are not compressed
+        let (head, _) = fetch_raw(port, "/hello", "Accept-Encoding: gzip\r\n");
+        assert!(!head.contains("Content-Encoding"), "unexpected response: {}", head);
+        let (head, body) = fetch_raw(port, "/logo.png", "Accept-Encoding: gzip\r\n");
+        assert!(!head.contains("Content-Encoding"), "unexpected response: {}", head);
+        assert_eq!(body, vec![0x89; 2000]);
+        let (head, body) = fetch_raw(port, "/stream", "Accept-Encoding: gzip\r\n");
+        assert!(head.contains("Transfer-Encoding: chunked"));
+        assert!(!head.contains("Content-Encoding"), "unexpected response: {}", head);
+        let response = format!("{}\r\n\r\n{}", head, String::from_utf8_lossy(&body));
+        assert_eq!(decode_chunked_body(&response), "chunk ".repeat(500));
+    }
+
+    // Chunk payloads of a chunked body, in order, up to the last-chunk marker
+    fn split_chunks(mut body: &[u8]) -> Vec<Vec<u8>> {
+        let mut chunks = Vec::new();
+        loop {
+            let line_end = body.windows(2).position(|w| w == b"\r\n").unwrap();
+            let size = usize::from_str_radix(std::str::from_utf8(&body[..line_end]).unwrap(), 16).unwrap();
+            if size == 0 {
+                return chunks;
+            }
+            chunks.push(body[line_end + 2..line_end + 2 + size].to_vec());
+            body = &body[line_end + 2 + size + 2..];
+        }
+    }
+
+    fn streamed_log_handler(_request: &HttpRequest) -> HttpResponse {
+        let lines: String = (0..6000).map(|i| format!("event {} processed\n", i)).collect();
+        HttpResponse::new(200, "OK")
+            .with_content_type("text/plain")
+            .with_chunked_encoding()
+            .with_body(&lines)
+    }
+
+    #[test]
+    fn test_chunked_writer_gzips_each_flush() {
+        let mut writer = ChunkedWriter::gzip(Vec::new());
+        writer.write_all(b"first part, ").unwrap();
+        writer.flush().unwrap();
+        let after_first = writer.get_ref().len();
+        writer.write_all(b"second part").unwrap();
+        writer.flush().unwrap();
+        let out = writer.finish(&[("X-Checksum".to_string(), "42".to_string())]).unwrap();
+
+        // The first flush already put compressed bytes on the wire
+        assert!(after_first > 0);
+        assert!(out.ends_with(b"0\r\nX-Checksum: 42\r\n\r\n"));
+        let compressed: Vec<u8> = split_chunks(&out).concat();
+        let mut decoded = String::new();
+        GzDecoder::new(compressed.as_slice()).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "first part, second part");
+
+        // Without gzip every write is one chunk
+        let mut writer = ChunkedWriter::new(Vec::new());
+        writer.write_all(b"abc").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(b"0123456789").unwrap();
+        assert_eq!(writer.finish(&[]).unwrap(), b"3\r\nabc\r\nA\r\n0123456789\r\n0\r\n\r\n");
+    }
+
+    #[test]
+    fn test_gzip_compression_of_chunked_responses() {
+        let port = 9382;
+        let mut config = ServerConfig::default();
+        config.compression.enabled = true;
+        config.compression.chunked_responses = true;
+        let _server_handle = start_test_server_with_setup(port, config, |server| {
+            server.add_route("GET", "/events", streamed_log_handler);
+        });
+        wait_for_server(port);
+
+        let (head, body) = fetch_raw(port, "/events", "Accept-Encoding: gzip\r\n");
+        assert!(head.contains("Transfer-Encoding: chunked"), "unexpected response: {}", head);
+        assert!(head.contains("Content-Encoding: gzip"), "unexpected response: {}", head);
+        assert!(head.contains("Vary: Accept-Encoding"));
+        assert!(!head.contains("Content-Length"), "unexpected response: {}", head);
+
+        // The body was compressed segment by segment into several chunks, and the chunks
+        // together gunzip to the original stream
+        let chunks = split_chunks(&body);
+        assert!(chunks.len() > 1, "expected several chunks, got {}", chunks.len());
+        let mut decoded = String::new();
+        GzDecoder::new(chunks.concat().as_slice()).read_to_string(&mut decoded).unwrap();
+        let expected: String = (0..6000).map(|i| format!("event {} processed\n", i)).collect();
+        assert_eq!(decoded, expected);
+
+        // Clients that did not ask for gzip get plain chunks
+        let (head, body) = fetch_raw(port, "/events", "");
+        assert!(!head.contains("Content-Encoding"), "unexpected response: {}", head);
+        assert_eq!(split_chunks(&body).concat(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_static_file_multiple_byte_ranges() {
+        let port = 9354;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+        let file = fs::read_to_string("static/about.html").unwrap();
+        let total = file.len();
+
+        let response = send_http_request(port, "GET /static/about.html HTTP/1.1\r\nHost: localhost\r\nRange: bytes=0-9, 20-29\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 206 Partial Content"), "unexpected response: {}", response);
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(!head.contains("Content-Range"), "unexpected response: {}", response);
+        let boundary = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Type: multipart/byteranges; boundary="))
+            .expect("multipart/byteranges Content-Type")
+            .to_string();
+
+        let expected = format!(
+            "\r\n--{b}\r\nContent-Type: text/html\r\nContent-Range: bytes 0-9/{t}\r\n\r\n{}\
+             \r\n--{b}\r\nContent-Type: text/html\r\nContent-Range: bytes 20-29/{t}\r\n\r\n{}\
+             \r\n--{b}--\r\n",
+            &file[0..10], &file[20..30], b = boundary, t = total
+        );
+        assert_eq!(body, expected);
+        assert!(head.contains(&format!("Content-Length: {}", expected.len())));
+
+        // A single range is still sent without multipart framing
+        let single = send_http_request(port, "GET /static/about.html HTTP/1.1\r\nHost: localhost\r\nRange: bytes=0-9\r\n\r\n");
+        assert!(single.contains(&format!("Content-Range: bytes 0-9/{}", total)), "unexpected response: {}", single);
+        assert!(single.ends_with(&file[0..10]));
+    }
+
+    #[test]
+    fn test_content_length_matches_body_while_file_is_rewritten() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let port = 9355;
+        let static_dir = std::env::temp_dir().join(format!("http_server_rewrite_{}", std::process::id()));
+        fs::create_dir_all(&static_dir).unwrap();
+        let file = static_dir.join("report.txt");
+        fs::write(&file, "x".repeat(50_000)).unwrap();
+
+        let mut config = ServerConfig::default();
+        config.static_files.directory = static_dir.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        // Keep truncating and rewriting the file with different sizes while it is served
+        let done = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let (done, file) = (Arc::clone(&done), file.clone());
+            std::thread::spawn(move || {
+                let mut size = 1_000;
+                while !done.load(Ordering::SeqCst) {
+                    let _ = fs::write(&file, "y".repeat(size));
+                    let _ = fs::metadata(&file);
+                    size = if size >= 20_000 { 1_000 } else { size * 3 }; // stays below the chunking threshold
+                }
+            })
+        };
+
+        for _ in 0..30 {
+            let (head, body) = fetch_raw(port, "/report.txt", "");
+            if !head.contains("200 OK") {
+                continue; // the file can briefly be unreadable mid-rewrite
+            }
+            let content_length: usize = head.lines()
+                .find_map(|line| line.strip_prefix("Content-Length: "))
+                .and_then(|value| value.trim().parse().ok())
+                .expect("Content-Length header");
+            assert_eq!(content_length, body.len(), "unexpected response head: {}", head);
+        }
+        done.store(true, Ordering::SeqCst);
+        writer.join().unwrap();
+        fs::remove_dir_all(&static_dir).ok();
+    }
+
+    #[test]
+    fn test_static_file_single_byte_ranges() {
+        let port = 9360;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+        let file = fs::read_to_string("static/about.html").unwrap();
+        let total = file.len();
+        let get = |range: &str| send_http_request(port, &format!(
+            "GET /static/about.html HTTP/1.1\r\nHost: localhost\r\n{}\r\n", range
+        ));
+
+        // Full responses advertise range support
+        let full = get("");
+        assert!(full.contains("HTTP/1.1 200 OK"));
+        assert!(full.contains("Accept-Ranges: bytes\r\n"));
+
+        let open_ended = get("Range: bytes=700-\r\n");
+        assert!(open_ended.contains("HTTP/1.1 206 Partial Content"), "unexpected response: {}", open_ended);
+        assert!(open_ended.contains(&format!("Content-Range: bytes 700-{}/{}\r\n", total - 1, total)));
+        assert!(open_ended.contains(&format!("Content-Length: {}\r\n", total - 700)));
+        assert!(open_ended.ends_with(&file[700..]));
+
+        let suffix = get("Range: bytes=-500\r\n");
+        assert!(suffix.contains(&format!("Content-Range: bytes {}-{}/{}\r\n", total - 500, total - 1, total)), "unexpected response: {}", suffix);
+        assert!(suffix.contains("Content-Length: 500\r\n"));
+        assert!(suffix.ends_with(&file[total - 500..]));
+
+        let beyond = get(&format!("Range: bytes={}-{}\r\n", total, total + 10));
+        assert!(beyond.contains("HTTP/1.1 416 Range Not Satisfiable"), "unexpected response: {}", beyond);
+        assert!(beyond.contains(&format!("Content-Range: bytes */{}\r\n", total)));
+    }
 }
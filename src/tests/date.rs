@@ -0,0 +1,40 @@
+#[cfg(test)]
+mod tests {
+    use api::{http_date, parse_http_date};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_http_date_known_values() {
+        let cases = [
+            (0, "Thu, 01 Jan 1970 00:00:00 GMT"),
+            (784_111_777, "Sun, 06 Nov 1994 08:49:37 GMT"), // the RFC 7231 example
+            (951_782_400, "Tue, 29 Feb 2000 00:00:00 GMT"), // leap day in a century leap year
+            (1_445_412_480, "Wed, 21 Oct 2015 07:28:00 GMT"),
+            (4_107_542_399, "Sun, 28 Feb 2100 23:59:59 GMT"), // 2100 is not a leap year
+        ];
+        for (secs, expected) in cases {
+            let time = UNIX_EPOCH + Duration::from_secs(secs);
+            assert_eq!(http_date(time), expected);
+            assert_eq!(parse_http_date(expected), Some(time), "round trip of {}", expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_http_date_rejects_other_forms() {
+        // RFC 850 and asctime dates are obsolete and not accepted
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+        assert_eq!(parse_http_date("Sun, 32 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_http_date(""), None);
+    }
+
+    #[test]
+    fn test_parse_http_date_out_of_range_year() {
+        // Years whose timestamp does not fit are rejected rather than overflowing
+        assert_eq!(parse_http_date("Sun, 06 Nov 300000000000 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 9223372036854775807 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Jan -9223372036854775808 08:49:37 GMT"), None);
+    }
+}
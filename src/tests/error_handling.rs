@@ -2,6 +2,8 @@ use super::helpers::*;
 use std::net::TcpStream;
 use std::io::{Read, Write};
 use std::time::Duration;
+use std::thread;
+use api::{HttpRequest, HttpResponse, ServerConfig, ServerError};
 
 #[cfg(test)]
 mod tests {
@@ -18,6 +20,7 @@ mod tests {
 
         assert!(response.contains("HTTP/1.1 400 Bad Request"));
         assert!(response.contains("400 - Bad Request"));
+        assert!(response.contains("X-Parse-Error: invalid_request_line\r\n"), "unexpected response: {}", response);
     }
 
     #[test]
@@ -143,8 +146,8 @@ mod tests {
         let request = "INVALID_METHOD /hello HTTP/1.1\r\nHost: localhost\r\n\r\n";
         let response = send_http_request(port, request);
 
-        // Should return 404 since the method/path combo doesn't exist
-        assert!(response.contains("HTTP/1.1 404 Not Found"));
+        // The path exists, so an unknown method is 405 rather than 404
+        assert!(response.contains("HTTP/1.1 405 Method Not Allowed"));
     }
 
     #[test]
@@ -159,4 +162,321 @@ mod tests {
         assert!(response.contains("HTTP/1.1 403 Forbidden"));
         assert!(response.contains("Directory traversal is not allowed"));
     }
+
+    #[test]
+    fn test_client_reset_during_keep_alive_is_normal_close() {
+        let port = 9304;
+        let mut config = ServerConfig::default();
+        config.threading.worker_threads = 1; // a stuck worker would block the follow-up request
+        config.logging.log_connections = true;
+        let (_server_handle, log) = start_test_server_with_log_capture(port, config);
+        wait_for_server(port);
+
+        // Leave the response unread so closing the socket sends a RST while the
+        // worker waits for the next keep-alive request
+        {
+            let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+            stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n").unwrap();
+            thread::sleep(Duration::from_millis(200));
+        }
+        thread::sleep(Duration::from_millis(200));
+
+        // The single worker was freed and serves the next client
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+
+        let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+        assert!(log.contains("closed connection"), "unexpected log: {}", log);
+        assert!(!log.contains("ERROR"), "unexpected log: {}", log);
+    }
+
+    #[test]
+    fn test_logging_level_and_toggles() {
+        let port = 9368;
+        let mut config = ServerConfig::default();
+        config.logging.level = "error".to_string();
+        config.logging.log_connections = true; // info lines, dropped at level "error"
+        config.logging.log_responses = true;
+        let (_server_handle, log) = start_test_server_with_log_capture(port, config);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        thread::sleep(Duration::from_millis(100));
+
+        let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+        assert!(!log.contains("INFO:"), "unexpected log: {}", log);
+        assert!(log.contains("GET /hello 200"), "request lines follow log_requests: {}", log);
+        // Request lines end with the time taken, e.g. "GET - /hello 200 0.3ms"
+        let request_line = log.lines().find(|line| line.contains("GET - /hello 200")).unwrap();
+        let millis = request_line.rsplit(' ').next().unwrap().strip_suffix("ms").unwrap();
+        assert!(millis.parse::<f64>().is_ok(), "unexpected request line: {}", request_line);
+        assert!(log.contains("RESPONSE GET /hello 200: Hello, World!"), "unexpected log: {}", log);
+
+        // Request lines can be turned off, and response bodies are off by default
+        let quiet_port = 9369;
+        let mut config = ServerConfig::default();
+        config.logging.log_requests = false;
+        let (_quiet_handle, quiet_log) = start_test_server_with_log_capture(quiet_port, config);
+        wait_for_server(quiet_port);
+        send_http_request(quiet_port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        thread::sleep(Duration::from_millis(100));
+        let quiet_log = String::from_utf8(quiet_log.lock().unwrap().clone()).unwrap();
+        assert!(!quiet_log.contains("/hello"), "unexpected log: {}", quiet_log);
+    }
+
+    #[test]
+    fn test_logging_to_file() {
+        let port = 9372;
+        let path = std::env::temp_dir().join(format!("http_server_log_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        std::fs::write(&path, "earlier line\n").unwrap();
+        let mut config = ServerConfig::default();
+        config.logging.file = path.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        thread::sleep(Duration::from_millis(100));
+
+        // Appended to what was there, and flushed without waiting for shutdown
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(log.starts_with("earlier line\n"), "unexpected log: {}", log);
+        assert!(log.contains("GET - /hello 200"), "unexpected log: {}", log);
+
+        // A path that cannot be opened falls back to stderr instead of failing
+        let logger = api::Logger::with_file("/nonexistent-dir/server.log");
+        logger.log_info("still logging");
+    }
+
+    fn send_oversized(port: u16, data: &[u8]) -> String {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        // The server may stop reading partway through; only the response matters
+        let _ = stream.write_all(data);
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    #[test]
+    fn test_overlong_lines_are_rejected() {
+        let port = 9322;
+        let mut config = ServerConfig::default();
+        config.connection.max_line_length = 1024;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        // A request line far past the limit with no CRLF at all
+        let mut request_line = b"GET /".to_vec();
+        request_line.extend(std::iter::repeat_n(b'a', 200_000));
+        let response = send_oversized(port, &request_line);
+        assert!(response.starts_with("HTTP/1.1 414 URI Too Long"), "unexpected response: {}", response);
+
+        let mut header = b"GET /hello HTTP/1.1\r\nX-Big: ".to_vec();
+        header.extend(std::iter::repeat_n(b'b', 200_000));
+        let response = send_oversized(port, &header);
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large"), "unexpected response: {}", response);
+
+        // Lines within the limit are unaffected, including one of exactly the limit before its CRLF
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        let exact = format!("X-Pad: {}", "c".repeat(1024 - "X-Pad: ".len()));
+        let response = send_http_request(port, &format!("GET /hello HTTP/1.1\r\nHost: localhost\r\n{}\r\n\r\n", exact));
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        let response = send_oversized(port, format!("GET /hello HTTP/1.1\r\nHost: localhost\r\n{}c\r\n\r\n", exact).as_bytes());
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large"), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_oversized_requests_are_rejected() {
+        let port = 9380;
+        let mut config = ServerConfig::default();
+        config.connection.max_request_body_bytes = 1024;
+        config.connection.max_header_bytes = 4096;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        // A huge declared length is refused from the head alone, before any body is sent
+        let response = send_oversized(port, b"POST /api/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2147483648\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large"), "unexpected response: {}", response);
+        assert!(response.contains("X-Parse-Error: payload_too_large"));
+
+        // A chunked body is cut off once it decodes past the limit
+        let mut chunked = b"POST /api/echo HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n300\r\n".to_vec();
+        chunked.extend(std::iter::repeat_n(b'c', 0x300));
+        chunked.extend_from_slice(b"\r\n300\r\n");
+        let response = send_oversized(port, &chunked);
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large"), "unexpected response: {}", response);
+
+        // Many short header lines, each within the line limit, add up past the head limit
+        let mut head = b"GET /hello HTTP/1.1\r\nHost: localhost\r\n".to_vec();
+        for i in 0..200 {
+            head.extend_from_slice(format!("X-Filler-{}: {}\r\n", i, "f".repeat(40)).as_bytes());
+        }
+        head.extend_from_slice(b"\r\n");
+        let response = send_oversized(port, &head);
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large"), "unexpected response: {}", response);
+        assert!(response.contains("X-Parse-Error: header_section_too_large"));
+
+        // Trailer fields after a chunked body share the same limit
+        let mut trailers = b"POST /api/echo HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n".to_vec();
+        for i in 0..200 {
+            trailers.extend_from_slice(format!("X-Trailer-{}: {}\r\n", i, "t".repeat(40)).as_bytes());
+        }
+        trailers.extend_from_slice(b"\r\n");
+        let response = send_oversized(port, &trailers);
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large"), "unexpected response: {}", response);
+        assert!(response.contains("X-Parse-Error: header_section_too_large"));
+
+        // Bodies within the limit are unaffected
+        let response = send_http_request(port, "POST /api/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello");
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_parse_errors_use_precise_statuses() {
+        let port = 9330;
+        let mut config = ServerConfig::default();
+        config.server.strict_parsing = true;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /hello HTTP/2.0\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 505 HTTP Version Not Supported"), "unexpected response: {}", response);
+
+        let response = send_http_request(port, "BREW /pot HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 501 Not Implemented"), "unexpected response: {}", response);
+
+        // Request bytes quoted in the error are HTML-escaped
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\n<b> x: y\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "unexpected response: {}", response);
+        assert!(response.contains("&lt;b&gt;") && !response.contains("<b>"));
+    }
+
+    #[test]
+    fn test_invalid_content_length_is_rejected() {
+        let port = 9389;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        for content_length in ["Content-Length: abc", "Content-Length: -5", "Content-Length: +5", "Content-Length: 5 5", "Content-Length: 5\r\nContent-Length: 6"] {
+            let request = format!("POST /api/echo HTTP/1.1\r\nHost: localhost\r\n{}\r\n\r\nhello", content_length);
+            let response = send_oversized(port, request.as_bytes());
+            assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "{:?} gave: {}", content_length, response);
+            assert!(response.contains("X-Parse-Error: invalid_content_length\r\n"), "unexpected response: {}", response);
+        }
+
+        // Repeating the same length, as a duplicate header or a list, is accepted
+        for content_length in ["Content-Length: 5\r\nContent-Length: 5", "Content-Length: 5, 5"] {
+            let request = format!("POST /api/echo HTTP/1.1\r\nHost: localhost\r\n{}\r\n\r\nhello", content_length);
+            let response = send_http_request(port, &request);
+            assert!(response.contains("HTTP/1.1 200 OK"), "{:?} gave: {}", content_length, response);
+        }
+    }
+
+    #[test]
+    fn test_transfer_encoding_must_end_with_chunked() {
+        let port = 9392;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        for transfer_encoding in ["Transfer-Encoding: gzip", "Transfer-Encoding: chunked, gzip", "Transfer-Encoding: chunked\r\nTransfer-Encoding: gzip"] {
+            let request = format!("POST /api/echo HTTP/1.1\r\nHost: localhost\r\n{}\r\n\r\n5\r\nhello\r\n0\r\n\r\n", transfer_encoding);
+            let response = send_oversized(port, request.as_bytes());
+            assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "{:?} gave: {}", transfer_encoding, response);
+            assert!(response.contains("X-Parse-Error: invalid_transfer_encoding\r\n"), "unexpected response: {}", response);
+        }
+
+        let response = send_http_request(port, "POST /api/echo HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_server_errors_map_to_client_responses() {
+        let status = |error: ServerError| HttpResponse::from_error(&error).map(|response| response.status_code);
+
+        assert_eq!(status(ServerError::TimeoutError), Some(408));
+        assert_eq!(status(std::io::Error::from(std::io::ErrorKind::TimedOut).into()), Some(408));
+        assert_eq!(status(std::io::Error::from(std::io::ErrorKind::InvalidData).into()), Some(400));
+        assert_eq!(status(std::io::Error::from(std::io::ErrorKind::PermissionDenied).into()), Some(500));
+        // A vanished client or a failed connection is closed without a response
+        assert_eq!(status(std::io::Error::from(std::io::ErrorKind::ConnectionReset).into()), None);
+        assert_eq!(status(ServerError::ConnectionError("refused".to_string())), None);
+
+        let response = HttpResponse::from_error(&ServerError::TimeoutError).unwrap();
+        assert_eq!(response.headers.get("Connection"), Some(&"close".to_string()));
+    }
+
+    #[test]
+    fn test_stalled_request_gets_408() {
+        let port = 9331;
+        let mut config = ServerConfig::default();
+        config.server.read_timeout_seconds = 1;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        // Start a request but never finish its head
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: local").unwrap();
+
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout"), "unexpected response: {}", response);
+        assert!(response.contains("Connection: close"));
+    }
+
+    #[test]
+    fn test_panicking_handler_does_not_kill_workers() {
+        let port = 9345;
+        let mut config = ServerConfig::default();
+        config.threading.worker_threads = 2;
+        config.threading.max_concurrent_connections = 2; // a leaked slot would turn into 503s
+        let _server_handle = start_test_server_with_setup(port, config, |server| {
+            server.add_route("GET", "/panic", |_request: &HttpRequest| -> HttpResponse {
+                panic!("handler bug");
+            });
+        });
+        wait_for_server(port);
+
+        // More panics than there are workers; each connection is simply dropped
+        for _ in 0..4 {
+            let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            stream.write_all(b"GET /panic HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+            let mut response = Vec::new();
+            let _ = stream.read_to_end(&mut response);
+            assert!(response.is_empty(), "unexpected response: {}", String::from_utf8_lossy(&response));
+            // The client sees EOF while the worker is still unwinding; let it release the slot
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        // The workers survived and the connection slots were released
+        for _ in 0..4 {
+            let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+            assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        }
+    }
+
+    #[test]
+    fn test_connection_logging_disabled_keeps_access_log() {
+        let port = 9346;
+        let mut config = ServerConfig::default();
+        config.logging.log_connections = false;
+        let (_server_handle, log) = start_test_server_with_log_capture(port, config);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        thread::sleep(Duration::from_millis(100));
+
+        let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+        assert!(log.contains("GET - /hello 200"), "missing access log: {}", log);
+        for noisy in ["New connection from", "Received request from", "Closing connection to", "closed connection"] {
+            assert!(!log.contains(noisy), "unexpected {:?} in log: {}", noisy, log);
+        }
+    }
 }
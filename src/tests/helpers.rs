@@ -1,8 +1,9 @@
 use std::net::TcpStream;
 use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use api::HttpServer;
+use api::{HttpServer, Logger, ServerConfig};
 
 /// Start a test server on the specified port
 pub fn start_test_server(port: u16) -> thread::JoinHandle<()> {
@@ -16,6 +17,62 @@ pub fn start_test_server(port: u16) -> thread::JoinHandle<()> {
     })
 }
 
+/// Start a test server on the specified port using a custom configuration
+pub fn start_test_server_with_config(port: u16, config: ServerConfig) -> thread::JoinHandle<()> {
+    start_test_server_with_setup(port, config, |_| {})
+}
+
+/// Start a test server with a custom configuration and extra setup (e.g. additional routes)
+pub fn start_test_server_with_setup(
+    port: u16,
+    mut config: ServerConfig,
+    setup: fn(&mut HttpServer),
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = port;
+        let mut server = HttpServer::from_config(config).unwrap();
+        setup(&mut server);
+        server.start().unwrap();
+    })
+}
+
+/// Start a test server whose log output is captured in the returned buffer
+pub fn start_test_server_with_log_capture(
+    port: u16,
+    mut config: ServerConfig,
+) -> (thread::JoinHandle<()>, Arc<Mutex<Vec<u8>>>) {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let output = Arc::clone(&log);
+    let handle = thread::spawn(move || {
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = port;
+        let logger = Logger::with_output(output).with_config(&config.logging);
+        let mut server = HttpServer::from_config(config).unwrap();
+        server.set_logger(logger);
+        server.start().unwrap();
+    });
+    (handle, log)
+}
+
+/// Decode a chunked response body (everything after the header block)
+pub fn decode_chunked_body(response: &str) -> String {
+    let body_start = response.find("\r\n\r\n").map(|pos| pos + 4).unwrap_or(response.len());
+    let mut remaining = &response[body_start..];
+    let mut body = String::new();
+
+    while let Some(line_end) = remaining.find("\r\n") {
+        let size = usize::from_str_radix(remaining[..line_end].trim(), 16).unwrap_or(0);
+        if size == 0 {
+            break;
+        }
+        let chunk_start = line_end + 2;
+        body.push_str(&remaining[chunk_start..chunk_start + size]);
+        remaining = &remaining[chunk_start + size + 2..];
+    }
+    body
+}
+
 /// Send an HTTP request to the test server and return the response
 pub fn send_http_request(port: u16, request: &str) -> String {
     let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
@@ -32,9 +89,38 @@ pub fn send_http_request(port: u16, request: &str) -> String {
     
     stream.write_all(request_with_close.as_bytes()).unwrap();
     
-    let mut response = String::new();
-    let _ = stream.read_to_string(&mut response); // Ignore errors from connection close
-    response
+    // Read raw bytes so binary bodies don't discard the whole response
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response); // Ignore errors from connection close
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+/// Read a single Content-Length framed response from a keep-alive connection
+pub fn read_http_response(stream: &mut impl Read) -> String {
+    let mut data = Vec::new();
+    let mut buffer = [0; 4096];
+
+    loop {
+        if let Some(header_end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+            let content_length = head
+                .lines()
+                .find(|line| line.to_lowercase().starts_with("content-length:"))
+                .and_then(|line| line.split(':').nth(1))
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if data.len() >= header_end + 4 + content_length {
+                break;
+            }
+        }
+
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => data.extend_from_slice(&buffer[..n]),
+        }
+    }
+
+    String::from_utf8_lossy(&data).to_string()
 }
 
 /// Wait for the server to start listening on the specified port
@@ -1,4 +1,9 @@
 use super::helpers::*;
+use api::{ServerConfig, parse_http_date};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
 
 #[cfg(test)]
 mod tests {
@@ -19,6 +24,12 @@ mod tests {
         let response = send_http_request(port, request);
         assert!(response.contains("HTTP/1.1 200 OK"));
 
+        // Every response carries a Date header in IMF-fixdate form
+        let date = response.lines()
+            .find_map(|line| line.strip_prefix("Date: "))
+            .expect("Date header");
+        assert!(parse_http_date(date).is_some(), "unexpected Date: {}", date);
+
         // Test with different methods
         let methods = ["GET", "POST", "PUT", "DELETE", "HEAD", "OPTIONS"];
         for method in &methods {
@@ -57,7 +68,7 @@ mod tests {
 
         // Test invalid methods
         let invalid_requests = vec![
-            ("INVALID_METHOD /hello HTTP/1.1\r\nHost: localhost\r\n\r\n", "should return 404 for invalid method"),
+            ("INVALID_METHOD /hello HTTP/1.1\r\nHost: localhost\r\n\r\n", "should return 405 for invalid method"),
             ("GET\r\nHost: localhost\r\n\r\n", "should return 400 for missing parts"), 
             ("GET /hello\r\nHost: localhost\r\n\r\n", "should return 400 for missing HTTP version"),
             ("GET /hello HTTP/2.0\r\nHost: localhost\r\n\r\n", "should handle unsupported version"),
@@ -67,10 +78,12 @@ mod tests {
             let response = send_http_request(port, request);
             
             // Should return appropriate error response for malformed requests
-            // Our server is tolerant - it may return 404 for invalid methods or 400 for malformed syntax
+            // Our server is tolerant - it may return 404/405 for invalid methods or 400 for malformed syntax
             assert!(response.contains("HTTP/1.1 400 Bad Request") || 
                     response.contains("HTTP/1.1 404 Not Found") ||
+                    response.contains("HTTP/1.1 405 Method Not Allowed") ||
                     response.contains("HTTP/1.1 501 Not Implemented") ||
+                    response.contains("HTTP/1.1 505 HTTP Version Not Supported") ||
                     response.contains("HTTP/1.1 200 OK"), // Some malformed requests might still work due to tolerant parsing
                     "Failed for: {} - {}", request.trim(), description);
         }
@@ -91,6 +104,44 @@ mod tests {
         assert!(response.contains("\r\n"));
     }
 
+    #[test]
+    fn test_headers_ended_by_eof() {
+        let port = 9370;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+        let strict_port = 9371;
+        let mut config = ServerConfig::default();
+        config.server.strict_parsing = true;
+        let _strict_handle = start_test_server_with_config(strict_port, config);
+        wait_for_server(strict_port);
+
+        // Send `request`, close the sending side and read whatever comes back
+        let send_then_close = |port: u16, request: &str| {
+            let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).unwrap();
+            String::from_utf8_lossy(&response).into_owned()
+        };
+
+        // No blank line, a bare LF, or not even a final line terminator: EOF ends the headers
+        for request in [
+            "GET /hello HTTP/1.1\r\nHost: localhost\r\n",
+            "GET /hello HTTP/1.1\nHost: localhost\n",
+            "GET /hello HTTP/1.1\r\nHost: localhost",
+        ] {
+            let response = send_then_close(port, request);
+            assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response to {:?}: {}", request, response);
+            assert!(response.contains("Hello, World!"));
+        }
+
+        // Strict parsing requires the blank line
+        let response = send_then_close(strict_port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n");
+        assert!(response.contains("HTTP/1.1 400 Bad Request"), "unexpected response: {}", response);
+    }
+
     // =====================================================
     // RFC 7231: Semantics and Content Compliance
     // =====================================================
@@ -108,7 +159,7 @@ mod tests {
             ("PUT", "/nonexistent", "404 Not Found"),
             ("DELETE", "/nonexistent", "404 Not Found"),
             ("HEAD", "/hello", "200 OK"), // Now supported
-            ("OPTIONS", "/hello", "404 Not Found"), // Our server doesn't implement OPTIONS
+            ("OPTIONS", "/hello", "204 No Content"), // Answered for any path with registered routes
         ];
 
         for (method, path, expected_status) in test_cases {
@@ -217,15 +268,30 @@ mod tests {
         let _server_handle = start_test_server(port);
         wait_for_server(port);
 
-        // Test If-Modified-Since header (basic implementation)
+        // A date before the file was last changed gets the full response
         let request = "GET /static/index.html HTTP/1.1\r\n\
                       Host: localhost\r\n\
                       If-Modified-Since: Wed, 21 Oct 2015 07:28:00 GMT\r\n\r\n";
         let response = send_http_request(port, request);
-
-        // Our server doesn't implement conditional requests yet, so it should return 200
-        // This test documents the current behavior and can be updated when implemented
         assert!(response.contains("HTTP/1.1 200 OK"));
+        let last_modified = response.lines()
+            .find_map(|line| line.strip_prefix("Last-Modified: "))
+            .expect("Last-Modified header")
+            .to_string();
+        assert!(last_modified.ends_with(" GMT"), "unexpected Last-Modified: {}", last_modified);
+
+        // The Last-Modified value itself, or any later date, means the copy is current
+        for since in [last_modified.as_str(), "Fri, 01 Jan 2100 00:00:00 GMT"] {
+            let request = format!("GET /static/index.html HTTP/1.1\r\nHost: localhost\r\nIf-Modified-Since: {}\r\n\r\n", since);
+            let response = send_http_request(port, &request);
+            assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"), "unexpected response: {}", response);
+            assert!(response.ends_with("\r\n\r\n"), "304 must not have a body: {}", response);
+            assert!(!response.contains("Content-Length:"));
+        }
+
+        // An unparseable date is ignored
+        let request = "GET /static/index.html HTTP/1.1\r\nHost: localhost\r\nIf-Modified-Since: yesterday\r\n\r\n";
+        assert!(send_http_request(port, request).contains("HTTP/1.1 200 OK"));
     }
 
     #[test]
@@ -234,14 +300,31 @@ mod tests {
         let _server_handle = start_test_server(port);
         wait_for_server(port);
 
-        // Test ETag support (not yet implemented)
         let request = "GET /static/index.html HTTP/1.1\r\nHost: localhost\r\n\r\n";
         let response = send_http_request(port, request);
-
-        // Current implementation doesn't include ETag headers
-        // This test documents current behavior
         assert!(response.contains("HTTP/1.1 200 OK"));
-        // assert!(response.contains("ETag:")); // Not implemented yet
+        let etag = response.lines()
+            .find_map(|line| line.strip_prefix("ETag: "))
+            .expect("ETag header")
+            .to_string();
+        assert!(etag.starts_with("W/\""), "expected a weak ETag, got {}", etag);
+
+        // A matching If-None-Match (weak comparison, in a list, or "*") gets 304
+        for if_none_match in [etag.clone(), etag.trim_start_matches("W/").to_string(), format!("\"other\", {}", etag), "*".to_string()] {
+            let request = format!("GET /static/index.html HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: {}\r\n\r\n", if_none_match);
+            let response = send_http_request(port, &request);
+            assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"), "unexpected response: {}", response);
+            assert!(response.contains(&format!("ETag: {}\r\n", etag)));
+            assert!(response.ends_with("\r\n\r\n"));
+        }
+
+        // If-None-Match takes precedence over If-Modified-Since
+        let request = "GET /static/index.html HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: \"other\"\r\nIf-Modified-Since: Fri, 01 Jan 2100 00:00:00 GMT\r\n\r\n";
+        assert!(send_http_request(port, request).contains("HTTP/1.1 200 OK"));
+
+        // HEAD is conditional too
+        let request = format!("HEAD /static/index.html HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: {}\r\n\r\n", etag);
+        assert!(send_http_request(port, &request).starts_with("HTTP/1.1 304 Not Modified\r\n"));
     }
 
     // =====================================================
@@ -421,11 +504,16 @@ mod tests {
             "GET /hello?name=Test%21 HTTP/1.1\r\nHost: localhost\r\n\r\n",
         ];
 
-        for request in requests {
+        let expected = ["Hello, Hello World!", "Hello, Test!!"];
+        for (request, greeting) in requests.into_iter().zip(expected) {
             let response = send_http_request(port, request);
             assert!(response.contains("HTTP/1.1 200 OK"));
-            // Our current implementation doesn't decode URLs, but it should handle them gracefully
+            assert!(response.ends_with(greeting), "unexpected response: {}", response);
         }
+
+        // '+' is a space in query strings; malformed escapes stay literal
+        let response = send_http_request(port, "GET /hello?name=a+b%ZZ%2 HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.ends_with("Hello, a b%ZZ%2!"), "unexpected response: {}", response);
     }
 
     #[test]
@@ -600,4 +688,169 @@ mod tests {
             handle.join().unwrap();
         }
     }
+
+    #[test]
+    fn test_request_fragmented_one_byte_per_segment() {
+        let port = 9308;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        // Every byte goes out in its own segment, so header lines (and their CRLFs)
+        // are split across many reads on the server side
+        let body = "fragmented body";
+        let request = format!(
+            "POST /api/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_nodelay(true).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        for byte in request.as_bytes() {
+            stream.write_all(&[*byte]).unwrap();
+            thread::sleep(Duration::from_millis(2));
+        }
+
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.contains(&format!(r#""body":"{}""#, body)));
+    }
+
+    #[test]
+    fn test_options_asterisk_form() {
+        let port = 9309;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "OPTIONS * HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 204 No Content"), "unexpected response: {}", response);
+        assert!(response.contains("Allow: GET, HEAD, OPTIONS, POST\r\n"));
+
+        // The asterisk-form is meaningless for other methods
+        let response = send_http_request(port, "GET * HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 400 Bad Request"));
+    }
+
+    #[test]
+    fn test_options_and_cors_preflight() {
+        let port = 9356;
+        let mut config = ServerConfig::default();
+        config.cors.allowed_origins = vec!["https://app.example.com".to_string()];
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let preflight = |path: &str| send_http_request(port, &format!(
+            "OPTIONS {} HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example.com\r\n\
+             Access-Control-Request-Method: GET\r\nAccess-Control-Request-Headers: Authorization\r\n\r\n",
+            path
+        ));
+        let response = preflight("/hello");
+        assert!(response.contains("HTTP/1.1 204 No Content"), "unexpected response: {}", response);
+        assert!(response.contains("Allow: GET, HEAD, OPTIONS\r\n"));
+        assert!(response.contains("Access-Control-Allow-Origin: https://app.example.com\r\n"));
+        assert!(response.contains("Access-Control-Allow-Methods: GET, POST, PUT, DELETE, OPTIONS\r\n"));
+        assert!(response.contains("Access-Control-Allow-Headers: Content-Type, Authorization\r\n"));
+
+        // Preflights carry no credentials, so protected paths answer them too
+        let response = preflight("/admin");
+        assert!(response.contains("HTTP/1.1 204 No Content"), "unexpected response: {}", response);
+
+        // Unknown paths still 404
+        let response = preflight("/nonexistent");
+        assert!(response.contains("HTTP/1.1 404 Not Found"), "unexpected response: {}", response);
+
+        // Actual requests get the origin header but not the preflight-only ones
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example.com\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(response.contains("Access-Control-Allow-Origin: https://app.example.com\r\n"));
+        assert!(response.contains("Vary: Origin\r\n"));
+        assert!(!response.contains("Access-Control-Allow-Methods"));
+
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nOrigin: https://evil.example\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(!response.contains("Access-Control-Allow-Origin"), "unexpected response: {}", response);
+        assert!(response.contains("Vary: Origin\r\n"));
+    }
+
+    #[test]
+    fn test_options_on_protected_path_without_auth() {
+        let port = 9373;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        // OPTIONS only describes the resource, so it needs no credentials
+        let response = send_http_request(port, "OPTIONS /admin HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 204 No Content"), "unexpected response: {}", response);
+        assert!(response.contains("Allow: GET, HEAD, OPTIONS\r\n"));
+        assert!(!response.contains("Admin Panel"));
+
+        // Everything else still needs a token
+        let response = send_http_request(port, "GET /admin HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 401 Unauthorized"), "unexpected response: {}", response);
+
+        let strict_port = 9374;
+        let mut config = ServerConfig::default();
+        config.authentication.options_without_auth = false;
+        let _strict_handle = start_test_server_with_config(strict_port, config);
+        wait_for_server(strict_port);
+        let response = send_http_request(strict_port, "OPTIONS /admin HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 401 Unauthorized"), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_chunked_request_body_is_decoded() {
+        let port = 9379;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        // Chunk extensions and trailer fields are accepted and dropped; a Content-Length
+        // alongside Transfer-Encoding is ignored
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(b"POST /api/echo HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n\
+            4\r\nWiki\r\n6;name=value\r\npedia \r\nE\r\nin \r\n\r\nchunks.\r\n0\r\nX-Checksum: 1234\r\n\r\n").unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.ends_with(r#""body":"Wikipedia in \r\n\r\nchunks."}"#), "unexpected response: {}", response);
+
+        // The connection stays usable: the whole chunked body was consumed
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.contains("Hello, World!"), "unexpected response: {}", response);
+
+        // Malformed chunk sizes are a 400
+        let response = send_http_request(port, "POST /api/echo HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nabc\r\n0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "unexpected response: {}", response);
+        assert!(response.contains("X-Parse-Error: invalid_chunked_body\r\n"), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_expect_100_continue() {
+        let port = 9381;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        // The interim 100 arrives before any body is sent, then the final response follows it
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(b"POST /api/echo HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").unwrap();
+        let interim = read_http_response(&mut stream);
+        assert_eq!(interim, "HTTP/1.1 100 Continue\r\n\r\n");
+        stream.write_all(b"hello").unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        assert!(response.ends_with(r#""body":"hello"}"#), "unexpected response: {}", response);
+
+        // A request rejected from its head gets the final status instead of 100, and the
+        // connection closes since the client may never send the body
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(b"POST /admin HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized"), "unexpected response: {}", response);
+        assert!(!response.contains("100 Continue"));
+        assert!(response.contains("Connection: close"), "unexpected response: {}", response);
+    }
 }
@@ -0,0 +1,33 @@
+#[cfg(test)]
+mod tests {
+    use api::JsonValue;
+
+    #[test]
+    fn test_json_parse_round_trip() {
+        let input = r#"{"name":"café 😀","list":[1,-2.5,true,null],"nested":{"quote":"a\"b\\c"}}"#;
+        let value = JsonValue::parse(input).unwrap();
+
+        assert_eq!(value.get("name").and_then(JsonValue::as_str), Some("café 😀"));
+        assert_eq!(
+            value.get("list"),
+            Some(&JsonValue::Array(vec![
+                JsonValue::Number(1.0),
+                JsonValue::Number(-2.5),
+                JsonValue::Bool(true),
+                JsonValue::Null,
+            ]))
+        );
+        assert_eq!(value.get("nested").and_then(|n| n.get("quote")).and_then(JsonValue::as_str), Some("a\"b\\c"));
+
+        // Serializing and parsing again yields the same value
+        assert_eq!(JsonValue::parse(&value.to_string()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_json_parse_rejects_malformed_input() {
+        for input in ["", "{", r#"{"a":}"#, r#"{"a":1,}"#, "[1 2]", r#""unterminated"#, "tru", "{} extra", r#""\ud800""#] {
+            assert!(JsonValue::parse(input).is_err(), "accepted malformed JSON: {:?}", input);
+        }
+        assert!(JsonValue::parse(&"[".repeat(1000)).is_err());
+    }
+}
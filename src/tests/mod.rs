@@ -7,3 +7,11 @@ pub mod advanced_features;
 pub mod performance;
 pub mod auth;
 pub mod http_compliance;
+pub mod request_parsing;
+pub mod config;
+pub mod json;
+pub mod stats;
+pub mod tls;
+pub mod date;
+pub mod multipart;
+pub mod redirect;
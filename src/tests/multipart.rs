@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod tests {
+    use api::{parse_multipart, HttpRequest, ParseMode};
+
+    const BOUNDARY: &str = "----WebKitFormBoundary7MA4YWxkTrZu0gW";
+
+    fn form_body() -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(b"preamble to ignore\r\n");
+        body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+        body.extend_from_slice(b"Content-Disposition: form-data; name=\"title\"\r\n\r\n");
+        body.extend_from_slice(b"Hello; world\r\n");
+        body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+        body.extend_from_slice(b"Content-Disposition: form-data; name=\"upload\"; filename=\"a;b \\\"c\\\".bin\"\r\n");
+        body.extend_from_slice(b"Content-Type: application/octet-stream\r\n\r\n");
+        // Binary content with CRLFs and a near-miss of the boundary
+        body.extend_from_slice(&[0x00, 0xff, b'\r', b'\n', b'-', b'-', b'-', b'-', 0x80]);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+        body.extend_from_slice(b"epilogue to ignore");
+        body
+    }
+
+    #[test]
+    fn test_parse_multipart_fields_and_files() {
+        let parts = parse_multipart(&form_body(), BOUNDARY);
+        assert_eq!(parts.len(), 2);
+
+        assert_eq!(parts[0].name, "title");
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(parts[0].content_str(), Some("Hello; world"));
+        assert_eq!(parts[0].content_type(), None);
+
+        assert_eq!(parts[1].name, "upload");
+        assert_eq!(parts[1].filename.as_deref(), Some("a;b \"c\".bin"));
+        assert_eq!(parts[1].content_type(), Some("application/octet-stream"));
+        assert_eq!(parts[1].content, vec![0x00, 0xff, b'\r', b'\n', b'-', b'-', b'-', b'-', 0x80]);
+        assert!(parts[1].headers.contains_key("content-disposition"));
+    }
+
+    #[test]
+    fn test_parse_multipart_edge_cases() {
+        // Empty content, an empty filename, and no headers at all
+        let body = b"--xyz\r\nContent-Disposition: form-data; name=empty\r\n\r\n\r\n\
+            --xyz\r\nContent-Disposition: form-data; name=\"file\"; filename=\"\"\r\n\r\n\r\n\
+            --xyz\r\n\r\nbare\r\n--xyz--";
+        let parts = parse_multipart(body, "xyz");
+        assert_eq!(parts.len(), 3);
+        assert_eq!((parts[0].name.as_str(), parts[0].content.as_slice()), ("empty", &b""[..]));
+        assert_eq!(parts[1].filename.as_deref(), Some(""));
+        assert!(parts[2].headers.is_empty());
+        assert_eq!(parts[2].content_str(), Some("bare"));
+
+        // A part cut off before its closing boundary is dropped
+        let truncated = b"--xyz\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--xyz\r\nContent-Disposition: form-data; name=\"b\"\r\n\r\npart";
+        let parts = parse_multipart(truncated, "xyz");
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].content_str(), Some("1"));
+
+        // No boundary in the body, or no boundary given
+        assert!(parse_multipart(b"just text", "xyz").is_empty());
+        assert!(parse_multipart(body, "").is_empty());
+    }
+
+    #[test]
+    fn test_multipart_boundary_from_content_type() {
+        let request = |content_type: &str| HttpRequest::parse_bytes(
+            format!("POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Type: {}\r\n\r\n", content_type).as_bytes(),
+            ParseMode::Tolerant,
+        ).unwrap();
+
+        assert_eq!(request(&format!("multipart/form-data; boundary={}", BOUNDARY)).multipart_boundary().as_deref(), Some(BOUNDARY));
+        assert_eq!(request("Multipart/Form-Data; charset=utf-8; boundary=\"Quoted Boundary\"").multipart_boundary().as_deref(), Some("Quoted Boundary"));
+        assert_eq!(request("multipart/form-data").multipart_boundary(), None);
+        assert_eq!(request("text/plain; boundary=abc").multipart_boundary(), None);
+
+        let mut upload = request(&format!("multipart/form-data; boundary={}", BOUNDARY));
+        upload.set_body(form_body());
+        let parts = parse_multipart(upload.raw_body(), &upload.multipart_boundary().unwrap());
+        assert_eq!(parts.len(), 2);
+    }
+}
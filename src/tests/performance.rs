@@ -4,6 +4,8 @@ use std::io::{Read, Write};
 use std::time::{Duration, Instant};
 use std::thread;
 use std::sync::{Arc, Barrier};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use api::{HttpRequest, HttpResponse, ServerConfig, ThreadPool};
 
 #[cfg(test)]
 mod tests {
@@ -272,4 +274,178 @@ mod tests {
 
         println!("Performance baseline: Average request time: {:?}", average_time);
     }
+
+    #[test]
+    fn test_idle_keep_alive_connections_capped() {
+        let port = 9301;
+        let mut config = ServerConfig::default();
+        config.connection.max_idle_connections = 2;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        // Open more idle keep-alive connections than the pool may hold
+        let mut connections = Vec::new();
+        for i in 0..3 {
+            let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let request = format!("GET /hello?conn={} HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n", i);
+            stream.write_all(request.as_bytes()).unwrap();
+            assert!(read_http_response(&mut stream).contains("HTTP/1.1 200 OK"));
+            connections.push(stream);
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        // The oldest idle connection was closed by the server
+        let mut buffer = [0; 64];
+        assert_eq!(connections[0].read(&mut buffer).unwrap_or(0), 0);
+
+        // Stats report the capped idle count
+        let stats = send_http_request(port, "GET /api/stats HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(stats.contains(r#""idle":2"#), "unexpected stats: {}", stats);
+
+        // The newest connection is still usable
+        connections[2].write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        assert!(read_http_response(&mut connections[2]).contains("Hello, World!"));
+    }
+
+    #[test]
+    fn test_idle_keep_alive_connections_reaped_after_timeout() {
+        let port = 9341;
+        let mut config = ServerConfig::default();
+        config.connection.idle_timeout_seconds = 1;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        assert!(read_http_response(&mut stream).contains("HTTP/1.1 200 OK"));
+
+        // Well before the 30s read timeout, the reaper closes the idle connection
+        let idle_started = Instant::now();
+        let mut buffer = [0; 64];
+        assert_eq!(stream.read(&mut buffer).unwrap_or(0), 0);
+        let idle_for = idle_started.elapsed();
+        assert!(idle_for >= Duration::from_millis(900), "closed too early: {:?}", idle_for);
+        assert!(idle_for < Duration::from_secs(5), "closed too late: {:?}", idle_for);
+    }
+
+    #[test]
+    fn test_thread_pool_shutdown_drains_queued_jobs() {
+        let pool = ThreadPool::new(1, 10);
+        let finished = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let finished = Arc::clone(&finished);
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(50));
+                finished.fetch_add(1, Ordering::SeqCst);
+            }).unwrap();
+        }
+
+        // With one worker, two jobs are still queued; all of them complete before shutdown returns
+        pool.shutdown();
+        assert_eq!(finished.load(Ordering::SeqCst), 3);
+        assert!(pool.execute(|| {}).is_err());
+        pool.shutdown(); // a second call is a no-op
+    }
+
+    #[test]
+    fn test_small_and_large_responses_written_intact() {
+        let port = 9324;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        // A tiny response takes the direct-write path
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("Hello, World!"), "unexpected response: {}", response);
+
+        // A response well above the buffer size goes through the write buffer
+        let body = "z".repeat(40_000);
+        let request = format!(
+            "POST /api/echo/raw HTTP/1.1\r\nHost: localhost\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(), body
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Content-Length: 40000"));
+        assert!(response.ends_with(&body));
+
+        // The same connection still serves a tiny response afterwards
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let response = read_http_response(&mut stream);
+        assert!(response.ends_with("Hello, World!"));
+    }
+
+    // Throughput baseline for spotting regressions; run with `cargo test throughput -- --nocapture`
+    // to see the numbers. Only correctness is asserted so the test stays stable in CI.
+    #[test]
+    fn test_request_throughput_benchmark() {
+        let port = 9325;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        const REQUESTS: u32 = 500;
+        let request = b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+        // Warm up so thread start-up does not skew the first measurement
+        send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+
+        // Keep-alive: one connection, many sequential requests
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let start_time = Instant::now();
+        for _ in 0..REQUESTS {
+            stream.write_all(request).unwrap();
+            let response = read_http_response(&mut stream);
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+        }
+        let keep_alive = start_time.elapsed();
+
+        // A fresh connection per request
+        let start_time = Instant::now();
+        for _ in 0..REQUESTS / 5 {
+            let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+        }
+        let per_connection = start_time.elapsed();
+
+        println!(
+            "Throughput: {:.0} req/s keep-alive ({} requests in {:?}), {:.0} req/s new connections ({} requests in {:?})",
+            REQUESTS as f64 / keep_alive.as_secs_f64(), REQUESTS, keep_alive,
+            (REQUESTS / 5) as f64 / per_connection.as_secs_f64(), REQUESTS / 5, per_connection
+        );
+    }
+
+    #[test]
+    fn test_saturated_server_queues_instead_of_rejecting() {
+        let port = 9376;
+        let mut config = ServerConfig::default();
+        config.threading.worker_threads = 2;
+        config.threading.max_concurrent_connections = 2;
+        let _server_handle = start_test_server_with_setup(port, config, |server| {
+            server.add_route("GET", "/slow", |_request: &HttpRequest| -> HttpResponse {
+                thread::sleep(Duration::from_millis(200));
+                HttpResponse::new(200, "OK").with_body("done")
+            });
+        });
+        wait_for_server(port);
+
+        // Four times as many clients as slots; the extra ones wait in the accept queue
+        let clients: Vec<_> = (0..8)
+            .map(|_| thread::spawn(move || send_http_request(port, "GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")))
+            .collect();
+        for client in clients {
+            let response = client.join().unwrap();
+            assert!(response.contains("HTTP/1.1 200 OK"), "unexpected response: {}", response);
+        }
+
+        let stats = send_http_request(port, "GET /api/stats HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(stats.contains(r#""rejected":0"#), "unexpected stats: {}", stats);
+    }
 }
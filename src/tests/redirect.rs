@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use api::{absolute_url, HttpRequest, HttpResponse, HttpServer, ParseMode, RedirectError, RedirectPolicy, ServerConfig};
+
+    fn request_for_host(host: &str) -> HttpRequest {
+        HttpRequest::parse_bytes(format!("GET /login HTTP/1.1\r\nHost: {}\r\n\r\n", host).as_bytes(), ParseMode::Tolerant).unwrap()
+    }
+
+    #[test]
+    fn test_same_host_redirect_allowed() {
+        let policy = RedirectPolicy::new();
+        let request = request_for_host("Example.com:8080");
+
+        let response = HttpResponse::checked_redirect(302, "https://example.com/account?tab=1", &request, &policy).unwrap();
+        assert_eq!(response.status_code, 302);
+        assert_eq!(response.headers.get("Location").map(String::as_str), Some("https://example.com/account?tab=1"));
+
+        // Relative and protocol-relative targets on the same host
+        assert!(policy.check("/dashboard", Some("example.com")).is_ok());
+        assert!(policy.check("next?page=2", Some("example.com")).is_ok());
+        assert!(policy.check("//EXAMPLE.com:9000/x", Some("example.com")).is_ok());
+        assert!(policy.check("http://[::1]:8080/", Some("[::1]:8080")).is_ok());
+    }
+
+    #[test]
+    fn test_external_redirect_rejected() {
+        let policy = RedirectPolicy::new();
+        let request = request_for_host("example.com");
+
+        let result = HttpResponse::checked_redirect(302, "https://evil.example.net/phish", &request, &policy);
+        assert_eq!(result.unwrap_err(), RedirectError::DisallowedHost("evil.example.net".to_string()));
+        assert!(policy.check("//evil.example.net/", Some("example.com")).is_err());
+        assert!(policy.check("https://example.com.evil.net/", Some("example.com")).is_err());
+        // Without a Host header only relative targets pass
+        assert!(policy.check("https://example.com/", None).is_err());
+        assert!(policy.check("/home", None).is_ok());
+
+        // Targets a browser might resolve elsewhere are invalid, not just disallowed
+        for target in ["javascript:alert(1)", "https://example.com@evil.net/", "https:evil.net", "/\\evil.net", "https://exa mple.com/", "http:///path"] {
+            assert!(matches!(policy.check(target, Some("example.com")), Err(RedirectError::InvalidTarget(_))), "{} was not rejected", target);
+        }
+
+        // The allowlist opens up specific other hosts
+        let policy = RedirectPolicy::new().with_allowed_hosts(&["Login.Example.org".to_string()]);
+        assert!(policy.check("https://login.example.org:8443/sso", Some("example.com")).is_ok());
+        assert!(policy.check("https://other.example.org/", Some("example.com")).is_err());
+
+        // The status is checked as well as the target
+        let result = HttpResponse::checked_redirect(304, "/dashboard", &request, &policy);
+        assert_eq!(result.unwrap_err(), RedirectError::InvalidStatus(304));
+    }
+
+    #[test]
+    fn test_absolute_url_builder() {
+        assert_eq!(absolute_url("HTTPS", "example.com:8443", "/a?b=c").unwrap(), "https://example.com:8443/a?b=c");
+        assert!(absolute_url("https", "example.com", "relative").is_err());
+        assert!(absolute_url("https", "example.com", "//evil.net/").is_err());
+        assert!(absolute_url("ftp", "example.com", "/").is_err());
+        assert!(absolute_url("https", "user@example.com", "/").is_err());
+        assert!(absolute_url("https", "", "/").is_err());
+    }
+
+    #[test]
+    fn test_redirect_validates_absolute_targets() {
+        assert!(HttpResponse::redirect(302, "https://elsewhere.example.net/path").is_ok());
+        assert!(HttpResponse::redirect(302, "/relative?x=1").is_ok());
+        for target in ["javascript:alert(1)", "https://example.com@evil.net/", "http:///path"] {
+            assert!(matches!(HttpResponse::redirect(302, target), Err(RedirectError::InvalidTarget(_))), "{} was not rejected", target);
+        }
+    }
+
+    #[test]
+    fn test_configured_root_redirect_is_trusted() {
+        let mut config = ServerConfig::default();
+        config.server.port = 0;
+        config.static_files.enabled = false;
+        config.server.default_routes = false;
+        config.server.root_redirect = "https://docs.example.org/start".to_string();
+        let server = HttpServer::from_config(config.clone()).unwrap();
+
+        // The configured external target is followed whatever Host the request names
+        for host in ["docs.example.org", "localhost"] {
+            let request = format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", host);
+            let response = String::from_utf8(server.handle_raw(request.as_bytes())).unwrap();
+            assert!(response.starts_with("HTTP/1.1 302 Found\r\n"), "unexpected response: {}", response);
+            assert!(response.contains("Location: https://docs.example.org/start\r\n"));
+        }
+
+        // A malformed target is still refused
+        config.server.root_redirect = "javascript:alert(1)".to_string();
+        let server = HttpServer::from_config(config).unwrap();
+        let response = String::from_utf8(server.handle_raw(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")).unwrap();
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"), "unexpected response: {}", response);
+        assert!(!response.contains("Location:"));
+    }
+}
@@ -0,0 +1,190 @@
+#[cfg(test)]
+mod tests {
+    use api::{HttpRequest, ParseMode, RequestParseError};
+
+    #[test]
+    fn test_parse_bytes_crlf_request() {
+        let raw = b"POST /api/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 11\r\n\r\nline1\r\nline2";
+        let request = HttpRequest::parse_bytes(raw, ParseMode::Strict).unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/echo");
+        assert_eq!(request.version, "HTTP/1.1");
+        assert_eq!(request.headers.get("host"), Some(&"localhost".to_string()));
+        // The body is taken verbatim, CRLFs included
+        assert_eq!(request.body, b"line1\r\nline2");
+        assert_eq!(request.body_str(), Some("line1\r\nline2"));
+    }
+
+    #[test]
+    fn test_parse_bytes_lone_lf_per_mode() {
+        let raw = b"GET /hello HTTP/1.1\nHost: localhost\n\n";
+
+        let request = HttpRequest::parse_bytes(raw, ParseMode::Tolerant).unwrap();
+        assert_eq!(request.path, "/hello");
+        assert_eq!(request.headers.get("host"), Some(&"localhost".to_string()));
+
+        assert_eq!(HttpRequest::parse_bytes(raw, ParseMode::Strict).unwrap_err(), RequestParseError::BareLineFeed { line: 1 });
+    }
+
+    #[test]
+    fn test_parse_bytes_non_utf8_header_value_per_mode() {
+        let raw = b"GET /hello HTTP/1.1\r\nHost: localhost\r\nX-Name: caf\xe9\r\n\r\n";
+
+        // Tolerant mode keeps the header, replacing the invalid byte
+        let request = HttpRequest::parse_bytes(raw, ParseMode::Tolerant).unwrap();
+        assert_eq!(request.headers.get("x-name"), Some(&"caf\u{FFFD}".to_string()));
+
+        // Strict mode rejects it outright
+        assert_eq!(
+            HttpRequest::parse_bytes(raw, ParseMode::Strict).unwrap_err(),
+            RequestParseError::InvalidHeaderValue("X-Name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_bytes_precise_errors() {
+        assert_eq!(HttpRequest::parse_bytes(b"", ParseMode::Tolerant).unwrap_err(), RequestParseError::EmptyRequest);
+        assert_eq!(
+            HttpRequest::parse_bytes(b"GET /hello\r\n\r\n", ParseMode::Tolerant).unwrap_err(),
+            RequestParseError::InvalidRequestLine
+        );
+        assert_eq!(
+            HttpRequest::parse_bytes(b"GET /hello HTTP/1.1\r\nHost: x\r\nBroken-Header\r\n\r\n", ParseMode::Strict).unwrap_err(),
+            RequestParseError::MalformedHeader { line: 3 }
+        );
+        assert_eq!(
+            HttpRequest::parse_bytes(b"GET /hello FTP/1.0\r\n\r\n", ParseMode::Strict).unwrap_err(),
+            RequestParseError::InvalidVersion("FTP/1.0".to_string())
+        );
+        // Tolerant mode skips header lines without a colon
+        let request = HttpRequest::parse_bytes(b"GET /hello HTTP/1.1\r\nBroken-Header\r\n\r\n", ParseMode::Tolerant).unwrap();
+        assert!(request.headers.is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors_map_to_statuses() {
+        let status = |raw: &[u8], mode| HttpRequest::parse_bytes(raw, mode).unwrap_err().status().0;
+
+        assert_eq!(status(b"", ParseMode::Tolerant), 400);
+        assert_eq!(status(b"GET\r\n\r\n", ParseMode::Tolerant), 400);
+        assert_eq!(status(b"G(T /hello HTTP/1.1\r\n\r\n", ParseMode::Tolerant), 400);
+        assert_eq!(status(b"GET /hello HTTP/1.1\r\nBad Name: x\r\n\r\n", ParseMode::Strict), 400);
+        assert_eq!(status(b"GET /hello HTTP/1.1\r\nHost: x", ParseMode::Strict), 400);
+        assert_eq!(status(b"GET /hello HTTP/1.1\r\nHost: x\r\n", ParseMode::Strict), 400);
+        assert_eq!(status(b"BREW /pot HTTP/1.1\r\n\r\n", ParseMode::Strict), 501);
+        assert_eq!(status(b"GET /hello HTTP/2.0\r\n\r\n", ParseMode::Tolerant), 505);
+        assert_eq!(RequestParseError::RequestLineTooLong.status(), (414, "URI Too Long"));
+        assert_eq!(RequestParseError::HeaderTooLarge.status(), (431, "Request Header Fields Too Large"));
+
+        // A header section cut short by EOF is only accepted in tolerant mode
+        assert!(HttpRequest::parse_bytes(b"GET /hello HTTP/1.1\r\nHost: x\r\n", ParseMode::Tolerant).is_ok());
+
+        // Unknown methods are only rejected in strict mode; routing decides otherwise
+        assert!(HttpRequest::parse_bytes(b"BREW /pot HTTP/1.1\r\n\r\n", ParseMode::Tolerant).is_ok());
+
+        // Errors carry the offending input
+        assert_eq!(
+            HttpRequest::parse_bytes(b"GET /hello HTTP/3.0\r\n\r\n", ParseMode::Tolerant).unwrap_err().to_string(),
+            "HTTP version HTTP/3.0 is not supported"
+        );
+    }
+
+    #[test]
+    fn test_parse_bytes_binary_body_is_preserved() {
+        let raw = b"POST /upload HTTP/1.1\r\nContent-Length: 8\r\n\r\n\x89PNG\x00\xff\r\n";
+        let request = HttpRequest::parse_bytes(raw, ParseMode::Strict).unwrap();
+
+        assert_eq!(request.body, b"\x89PNG\x00\xff\r\n");
+        assert_eq!(request.body_str(), None);
+    }
+
+    #[test]
+    fn test_parse_bytes_transcodes_declared_latin1_body() {
+        let raw = b"POST /form HTTP/1.1\r\nContent-Type: text/plain; charset=\"latin1\"\r\nContent-Length: 5\r\n\r\nna\xefve";
+        let request = HttpRequest::parse_bytes(raw, ParseMode::Strict).unwrap();
+
+        assert_eq!(request.charset().as_deref(), Some("latin1"));
+        assert_eq!(request.body_str(), Some("naïve"));
+        assert_eq!(request.raw_body(), b"na\xefve");
+
+        // UTF-8 and unsupported charsets are left alone
+        for content_type in ["text/plain; charset=utf-8", "text/plain; charset=shift_jis", "application/octet-stream"] {
+            let raw = format!("POST /form HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: 3\r\n\r\n", content_type);
+            let mut request = HttpRequest::parse_bytes(raw.as_bytes(), ParseMode::Strict).unwrap();
+            request.set_body(b"\xef\xbb\x01".to_vec());
+            assert_eq!(request.body, b"\xef\xbb\x01");
+            assert!(request.original_body.is_none());
+        }
+    }
+
+    fn request_with_cookie(cookie: &str) -> HttpRequest {
+        let raw = format!("GET / HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\n\r\n", cookie);
+        HttpRequest::parse_bytes(raw.as_bytes(), ParseMode::Strict).unwrap()
+    }
+
+    #[test]
+    fn test_cookies_single() {
+        let cookies = request_with_cookie("session=abc123").cookies();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies.get("session").map(String::as_str), Some("abc123"));
+
+        // No Cookie header at all
+        let raw = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert!(HttpRequest::parse_bytes(raw, ParseMode::Strict).unwrap().cookies().is_empty());
+    }
+
+    #[test]
+    fn test_cookies_multiple_quoted_and_duplicate() {
+        let cookies = request_with_cookie(r#"theme=dark; lang="en-GB"; session=first; session=second; token="#).cookies();
+        assert_eq!(cookies.len(), 4);
+        assert_eq!(cookies.get("theme").map(String::as_str), Some("dark"));
+        assert_eq!(cookies.get("lang").map(String::as_str), Some("en-GB"));
+        assert_eq!(cookies.get("session").map(String::as_str), Some("first"));
+        assert_eq!(cookies.get("token").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn test_cookies_stray_whitespace_and_empty_segments() {
+        let cookies = request_with_cookie(";  a = 1 ;;b=2;   ; novalue ;c=  3  ;").cookies();
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies.get("a").map(String::as_str), Some("1"));
+        assert_eq!(cookies.get("b").map(String::as_str), Some("2"));
+        assert_eq!(cookies.get("c").map(String::as_str), Some("3"));
+    }
+
+    fn request_with_body(content_type: Option<&str>, body: &str) -> HttpRequest {
+        let content_type = content_type.map(|value| format!("Content-Type: {}\r\n", value)).unwrap_or_default();
+        let raw = format!("POST /form HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: {}\r\n\r\n{}", content_type, body.len(), body);
+        HttpRequest::parse_bytes(raw.as_bytes(), ParseMode::Strict).unwrap()
+    }
+
+    #[test]
+    fn test_form_params_multiple_fields() {
+        let form = request_with_body(Some("application/x-www-form-urlencoded"), "name=alice&age=30&empty=&flag").form_params();
+        assert_eq!(form.len(), 4);
+        assert_eq!(form.get("name").map(String::as_str), Some("alice"));
+        assert_eq!(form.get("age").map(String::as_str), Some("30"));
+        assert_eq!(form.get("empty").map(String::as_str), Some(""));
+        assert_eq!(form.get("flag").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn test_form_params_decodes_escapes_and_plus() {
+        let request = request_with_body(
+            Some("Application/X-WWW-Form-Urlencoded; charset=UTF-8"),
+            "full+name=Jos%C3%A9+Garc%C3%ADa&q=a%2Bb%3Dc%26d&caf%C3%A9=1",
+        );
+        let form = request.form_params();
+        assert_eq!(form.get("full name").map(String::as_str), Some("José García"));
+        assert_eq!(form.get("q").map(String::as_str), Some("a+b=c&d"));
+        assert_eq!(form.get("café").map(String::as_str), Some("1"));
+    }
+
+    #[test]
+    fn test_form_params_requires_form_content_type() {
+        assert!(request_with_body(None, "name=alice").form_params().is_empty());
+        assert!(request_with_body(Some("text/plain"), "name=alice").form_params().is_empty());
+        assert!(request_with_body(Some("application/json"), r#"{"name":"alice"}"#).form_params().is_empty());
+    }
+}
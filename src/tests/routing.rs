@@ -1,4 +1,7 @@
 use super::helpers::*;
+use api::{percent_decode, HttpRequest, HttpResponse, ParseMode, Route, Router, ServerConfig};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[cfg(test)]
 mod tests {
@@ -123,4 +126,250 @@ mod tests {
         // Should be serving the home page, not static index.html
         assert!(response.contains("Welcome to Rust HTTP Server!"));
     }
+
+    #[test]
+    fn test_default_root_message_without_routes() {
+        let port = 9306;
+        let mut config = ServerConfig::default();
+        config.static_files.enabled = false;
+        config.server.default_routes = false;
+        config.server.root_message = "Server is running".to_string();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("Server is running"));
+
+        // Other paths still 404, and the default routes are gone
+        let response = send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 404 Not Found"));
+    }
+
+    #[test]
+    fn test_default_root_redirect_without_routes() {
+        let port = 9307;
+        let mut config = ServerConfig::default();
+        config.static_files.enabled = false;
+        config.server.default_routes = false;
+        config.server.root_redirect = "/docs".to_string();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 302 Found"));
+        assert!(response.contains("Location: /docs"));
+    }
+
+    #[test]
+    fn test_form_urlencoded_parsing() {
+        assert_eq!(percent_decode("a%20b+c%2Fd"), "a b c/d");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+
+        let params = Router::parse_query_params("/search?na%6De=J%C3%B6rg+K&q=%ZZ");
+        assert_eq!(params.get("name"), Some(&"Jörg K".to_string()));
+        assert_eq!(params.get("q"), Some(&"%ZZ".to_string()));
+
+        let form = Router::parse_form_urlencoded("username=a%3Db&password=x+y&empty=&flag");
+        assert_eq!(form.get("username").map(String::as_str), Some("a=b"));
+        assert_eq!(form.get("password").map(String::as_str), Some("x y"));
+        assert_eq!(form.get("empty").map(String::as_str), Some(""));
+        assert_eq!(form.get("flag").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn test_status_reports_configured_identity() {
+        let port = 9334;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+        let response = send_http_request(port, "GET /api/status HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        // Without configuration the real crate version is reported
+        assert!(response.contains(&format!(r#""version":"{}""#, env!("CARGO_PKG_VERSION"))), "unexpected response: {}", response);
+
+        let port = 9335;
+        let mut config = ServerConfig::default();
+        config.server.server_name = "acme-gateway".to_string();
+        config.server.server_version = "7.2.0".to_string();
+        config.threading.worker_threads = 3;
+        config.threading.max_concurrent_connections = 42;
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let response = send_http_request(port, "GET /api/status HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.ends_with(r#"{"status":"ok","server":"acme-gateway","version":"7.2.0"}"#), "unexpected response: {}", response);
+        let response = send_http_request(port, "GET /api/stats HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains(r#""server":"acme-gateway","version":"7.2.0""#), "unexpected response: {}", response);
+        // Pool sizes and timeouts come from the configuration
+        assert!(response.contains(r#""thread_pool_size":3,"max_connections":42,"buffer_size":8192,"read_timeout_seconds":30,"#), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_route_pattern_matching() {
+        let route = Route { method: "GET".to_string(), path: "/posts/:slug/comments".to_string(), handler: Arc::new(|_: &HttpRequest| HttpResponse::new(200, "OK")) };
+        assert!(route.is_pattern());
+        assert_eq!(route.match_params("/posts/hello/comments"), Some(vec![("slug".to_string(), "hello".to_string())]));
+        assert_eq!(route.match_params("/posts/hello"), None);
+        assert_eq!(route.match_params("/posts//comments"), None);
+        assert_eq!(route.match_params("/posts/hello/likes"), None);
+    }
+
+    #[test]
+    fn test_closure_handlers_share_captured_state() {
+        let port = 9385;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            let hits = Arc::new(AtomicUsize::new(0));
+            let greeting = String::from("visit");
+            server.add_route_fn("GET", "/counter", move |_request: &HttpRequest| {
+                let count = hits.fetch_add(1, Ordering::SeqCst) + 1;
+                HttpResponse::new(200, "OK").with_body(&format!("{} {}", greeting, count))
+            });
+        });
+        wait_for_server(port);
+
+        // Each connection runs on a cloned router; the captured counter is shared by all
+        let first = send_http_request(port, "GET /counter HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let second = send_http_request(port, "GET /counter HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(first.ends_with("visit 1"), "unexpected response: {}", first);
+        assert!(second.ends_with("visit 2"), "unexpected response: {}", second);
+
+        // Plain fn handlers still register through add_route
+        let mut router = Router::new();
+        router.add_route("GET", "/plain", |_request| HttpResponse::new(200, "OK").with_body("plain"));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        router.add_route_fn("GET", "/counted", move |_request| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            HttpResponse::new(204, "No Content")
+        });
+        let cloned = router.clone();
+        let request = |path: &str| HttpRequest::parse_bytes(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes(), ParseMode::Tolerant).unwrap();
+        assert_eq!(router.route(&request("/plain")).body, b"plain");
+        assert_eq!(router.route(&request("/counted")).status_code, 204);
+        assert_eq!(cloned.route(&request("/counted")).status_code, 204);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_wildcard_route_matching() {
+        let route = Route { method: "GET".to_string(), path: "/files/*".to_string(), handler: Arc::new(|_: &HttpRequest| HttpResponse::new(200, "OK")) };
+        assert!(route.is_wildcard() && route.is_pattern());
+        assert_eq!(route.match_params("/files/a/b/c"), Some(vec![("*".to_string(), "a/b/c".to_string())]));
+        assert_eq!(route.match_params("/files/"), Some(vec![("*".to_string(), String::new())]));
+        assert_eq!(route.match_params("/files"), None);
+        assert_eq!(route.match_params("/filesystem/a"), None);
+
+        // Parameters before the wildcard are captured as usual
+        let route = Route { method: "GET".to_string(), path: "/repos/:owner/*".to_string(), handler: Arc::new(|_: &HttpRequest| HttpResponse::new(200, "OK")) };
+        assert_eq!(route.match_params("/repos/alice/src/main.rs"), Some(vec![
+            ("owner".to_string(), "alice".to_string()),
+            ("*".to_string(), "src/main.rs".to_string()),
+        ]));
+    }
+
+    fn describe_match(label: &'static str) -> impl Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static {
+        move |request: &HttpRequest| {
+            let mut params: Vec<String> = request.params.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            params.sort();
+            HttpResponse::new(200, "OK").with_body(&format!("{} [{}]", label, params.join(" ")))
+        }
+    }
+
+    #[test]
+    fn test_wildcard_routes_and_precedence() {
+        let port = 9387;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            // Registered before the more specific routes on purpose
+            server.add_route_fn("GET", "/files/*", describe_match("files"));
+            server.add_route_fn("GET", "/files/special/*", describe_match("special"));
+            server.add_route_fn("GET", "/files/:name", describe_match("named"));
+            server.add_route_fn("GET", "/files/readme", describe_match("readme"));
+        });
+        wait_for_server(port);
+        let get = |path: &str| send_http_request(port, &format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path));
+
+        // Nested paths reach the wildcard handler with the decoded tail
+        let response = get("/files/a/b/c");
+        assert!(response.ends_with("files [*=a/b/c]"), "unexpected response: {}", response);
+        let response = get("/files/docs/read%20me.txt?download=1");
+        assert!(response.ends_with("files [*=docs/read me.txt]"), "unexpected response: {}", response);
+
+        // Exact beats parameterized beats wildcard, and a longer wildcard prefix wins
+        let response = get("/files/readme");
+        assert!(response.ends_with("readme []"), "unexpected response: {}", response);
+        let response = get("/files/notes");
+        assert!(response.ends_with("named [name=notes]"), "unexpected response: {}", response);
+        let response = get("/files/special/x/y");
+        assert!(response.ends_with("special [*=x/y]"), "unexpected response: {}", response);
+
+        // The prefix itself is not under the wildcard
+        let response = get("/files");
+        assert!(response.contains("HTTP/1.1 404 Not Found"), "unexpected response: {}", response);
+    }
+
+    fn health_handler(request: &HttpRequest) -> HttpResponse {
+        HttpResponse::new(200, "OK").with_body(&format!("healthy via {} {}", request.method, request.path))
+    }
+
+    fn user_handler(request: &HttpRequest) -> HttpResponse {
+        HttpResponse::new(200, "OK").with_body(&format!("user {}", request.params.get("id").map(String::as_str).unwrap_or("?")))
+    }
+
+    #[test]
+    fn test_route_aliases_dispatch_to_the_same_handler() {
+        let port = 9386;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            server.add_route("GET", "/api/health", health_handler);
+            server.add_route("POST", "/api/health", health_handler);
+            server.add_route("GET", "/users/:id", user_handler);
+            assert_eq!(server.add_alias("/api/health", "/healthz"), 2);
+            assert_eq!(server.add_alias("/users/:id", "/u/:id"), 1);
+            assert_eq!(server.add_alias("/missing", "/also-missing"), 0);
+        });
+        wait_for_server(port);
+
+        let original = send_http_request(port, "GET /api/health HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let alias = send_http_request(port, "GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(original.ends_with("healthy via GET /api/health"), "unexpected response: {}", original);
+        assert!(alias.ends_with("healthy via GET /healthz"), "unexpected response: {}", alias);
+
+        // Every method of the original path is aliased
+        let response = send_http_request(port, "POST /healthz HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n");
+        assert!(response.ends_with("healthy via POST /healthz"), "unexpected response: {}", response);
+        let response = send_http_request(port, "DELETE /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 405 Method Not Allowed"), "unexpected response: {}", response);
+
+        let response = send_http_request(port, "GET /u/42 HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.ends_with("user 42"), "unexpected response: {}", response);
+        let response = send_http_request(port, "GET /also-missing HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.contains("HTTP/1.1 404 Not Found"), "unexpected response: {}", response);
+    }
+
+    #[test]
+    fn test_path_parameters_reach_handlers() {
+        let port = 9338;
+        let _server_handle = start_test_server_with_setup(port, ServerConfig::default(), |server| {
+            server.add_route("GET", "/users/:id", |request: &HttpRequest| {
+                HttpResponse::new(200, "OK").with_body(&format!("user {}", request.params["id"]))
+            });
+            server.add_route("GET", "/users/me", |_request: &HttpRequest| {
+                HttpResponse::new(200, "OK").with_body("current user")
+            });
+            server.add_route("GET", "/posts/:slug/comments", |request: &HttpRequest| {
+                HttpResponse::new(200, "OK").with_body(&format!("comments on {}", request.params["slug"]))
+            });
+        });
+        wait_for_server(port);
+
+        let get = |path: &str| send_http_request(port, &format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path));
+
+        assert!(get("/users/42").ends_with("user 42"));
+        assert!(get("/users/42?verbose=1").ends_with("user 42"));
+        // Exact routes win over patterns, whatever the registration order
+        assert!(get("/users/me").ends_with("current user"));
+        // Captured segments are percent-decoded
+        assert!(get("/posts/hello%20world/comments").ends_with("comments on hello world"));
+        assert!(get("/users/42/extra").contains("404 Not Found"));
+    }
 }
@@ -0,0 +1,188 @@
+use super::helpers::*;
+use api::{ServerStats, StatsSnapshot};
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_counting_and_interval_deltas() {
+        let stats = Arc::new(ServerStats::new());
+        stats.record_request(10, 20);
+        let (first, _) = stats.next_interval();
+        assert_eq!(first.requests_total, 1);
+
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let stats = Arc::clone(&stats);
+                thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        stats.record_request(100, 1_000);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let (second, delta) = stats.next_interval();
+        assert_eq!(second.requests_total, 80_001);
+        assert_eq!(delta.requests, 80_000);
+        assert_eq!(delta.bytes_received, 8_000_000);
+        assert_eq!(delta.bytes_sent, 80_000_000);
+
+        // A new interval starts after each call
+        let (_, delta) = stats.next_interval();
+        assert_eq!(delta.requests, 0);
+    }
+
+    #[test]
+    fn test_snapshot_delta_across_counter_wraparound() {
+        let start = Instant::now();
+        let earlier = StatsSnapshot {
+            requests_total: u64::MAX - 4,
+            bytes_received: u64::MAX,
+            bytes_sent: 100,
+            taken_at: start,
+        };
+        let later = StatsSnapshot {
+            requests_total: 5, // wrapped past u64::MAX
+            bytes_received: 9,
+            bytes_sent: 150,
+            taken_at: start + Duration::from_secs(2),
+        };
+
+        let delta = later.delta_since(&earlier);
+        assert_eq!(delta.requests, 10);
+        assert_eq!(delta.bytes_received, 10);
+        assert_eq!(delta.bytes_sent, 50);
+        assert_eq!(delta.elapsed, Duration::from_secs(2));
+        assert_eq!(delta.rate(delta.requests), 5.0);
+    }
+
+    #[test]
+    fn test_metrics_endpoint_reports_counters() {
+        let port = 9327;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        for _ in 0..3 {
+            send_http_request(port, "GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        }
+        let response = send_http_request(port, "GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+
+        assert!(response.contains("HTTP/1.1 200 OK"));
+        assert!(response.contains("# TYPE http_requests_total counter"));
+        // The metrics request itself is counted once its response has been written
+        assert!(response.contains("\nhttp_requests_total 3\n"), "unexpected response: {}", response);
+        assert!(response.contains("http_requests_per_second "));
+        assert!(!response.contains("\nhttp_sent_bytes_total 0\n"));
+    }
+
+    #[test]
+    fn test_requests_per_connection_reflects_keep_alive() {
+        let stats = ServerStats::new();
+        assert_eq!(stats.requests_per_connection(), 0.0);
+        stats.record_connection();
+        stats.record_connection();
+        for _ in 0..5 {
+            stats.record_request(0, 0);
+        }
+        assert_eq!(stats.requests_per_connection(), 2.5);
+
+        let port = 9333;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port); // its probe is the first accepted connection
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        for _ in 0..4 {
+            stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            assert!(read_http_response(&mut stream).contains("200 OK"));
+        }
+        stream.write_all(b"GET /api/stats HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let response = read_http_response(&mut stream);
+
+        assert!(response.contains(r#""accepted":2"#), "unexpected response: {}", response);
+        assert!(response.contains(r#""requests_served":4"#));
+        assert!(response.contains(r#""requests_per_connection":2}"#));
+    }
+
+    #[test]
+    fn test_status_classes_and_active_connections() {
+        let stats = Arc::new(ServerStats::new());
+        for status in [101, 200, 204, 301, 404, 404, 503, 42, 600] {
+            stats.record_status(status);
+        }
+        assert_eq!(stats.responses_by_class(), [1, 2, 1, 2, 1]);
+
+        let first = stats.open_connection();
+        let second = stats.open_connection();
+        assert_eq!(stats.active_connections(), 2);
+        assert_eq!(stats.connections_total(), 2);
+        drop(first);
+        assert_eq!(stats.active_connections(), 1);
+        drop(second);
+        assert_eq!(stats.active_connections(), 0);
+    }
+
+    #[test]
+    fn test_stats_endpoint_reports_live_counters() {
+        let port = 9366;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        for path in ["/hello", "/hello", "/missing"] {
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            read_http_response(&mut stream);
+        }
+        stream.write_all(b"GET /api/stats HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let response = read_http_response(&mut stream);
+
+        // The stats request itself is answered before it is counted
+        assert!(response.contains(r#""requests_served":3"#), "unexpected response: {}", response);
+        assert!(response.contains(r#""2xx":2"#));
+        assert!(response.contains(r#""4xx":1"#));
+        assert!(response.contains(r#""5xx":0"#));
+        // This connection is open while it asks
+        assert!(response.contains(r#""active":1"#));
+        assert!(response.contains(r#""uptime_seconds":"#));
+    }
+
+    #[test]
+    fn test_chunked_request_counts_decoded_body_bytes() {
+        let port = 9390;
+        let _server_handle = start_test_server(port);
+        wait_for_server(port);
+
+        let metrics = b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let head = b"POST /api/echo HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let received = |response: &str| -> u64 {
+            let line = response.lines().find(|line| line.starts_with("http_received_bytes_total ")).unwrap();
+            line["http_received_bytes_total ".len()..].trim().parse().unwrap()
+        };
+
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(metrics).unwrap();
+        let before = received(&read_http_response(&mut stream));
+
+        let mut request = head.to_vec();
+        request.extend_from_slice(b"5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n");
+        stream.write_all(&request).unwrap();
+        assert!(read_http_response(&mut stream).contains("200 OK"));
+        stream.write_all(metrics).unwrap();
+        let after = received(&read_http_response(&mut stream));
+
+        // The earlier metrics request, then the chunked request's head and its 11 decoded body bytes
+        assert_eq!(after - before, (metrics.len() + head.len() + 11) as u64);
+    }
+}
@@ -0,0 +1,150 @@
+use super::helpers::*;
+use api::{HttpServer, JsonValue, ServerConfig, ServerError};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Write a fresh self-signed certificate for "localhost" and return (cert_file, key_file, DER cert).
+// Calling it again with the same name overwrites the files with a new certificate.
+fn write_self_signed_cert(name: &str) -> (PathBuf, PathBuf, Vec<u8>) {
+    let dir = std::env::temp_dir().join(format!("http_server_tls_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert_file = dir.join("cert.pem");
+    let key_file = dir.join("key.pem");
+    fs::write(&cert_file, certified.cert.pem()).unwrap();
+    fs::write(&key_file, certified.key_pair.serialize_pem()).unwrap();
+    (cert_file, key_file, certified.cert.der().to_vec())
+}
+
+// Open a TLS connection that only trusts `cert_der`; fails the handshake for any other certificate
+fn connect_trusting(port: u16, cert_der: &[u8]) -> std::io::Result<StreamOwned<ClientConnection, TcpStream>> {
+    let connection = ClientConnection::new(client_trusting(cert_der.to_vec()), ServerName::try_from("localhost").unwrap()).unwrap();
+    let socket = TcpStream::connect(format!("127.0.0.1:{}", port))?;
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut stream = StreamOwned::new(connection, socket);
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+    Ok(stream)
+}
+
+fn client_trusting(cert_der: Vec<u8>) -> Arc<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    roots.add(cert_der.into()).unwrap();
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_https_request_against_self_signed_cert() {
+        let port = 9347;
+        let (cert_file, key_file, cert_der) = write_self_signed_cert("handshake");
+        let mut config = ServerConfig::default();
+        config.tls.cert_file = cert_file.to_string_lossy().into_owned();
+        config.tls.key_file = key_file.to_string_lossy().into_owned();
+        let _server_handle = start_test_server_with_config(port, config);
+        wait_for_server(port);
+
+        let mut stream = connect_trusting(port, &cert_der).unwrap();
+
+        // Two requests on one keep-alive TLS session
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let first = read_http_response(&mut stream);
+        stream.write_all(b"GET /hello?name=TLS HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut second = Vec::new();
+        let _ = stream.read_to_end(&mut second);
+        let second = String::from_utf8_lossy(&second);
+        fs::remove_dir_all(cert_file.parent().unwrap()).ok();
+
+        assert!(first.starts_with("HTTP/1.1 200 OK"), "unexpected response: {}", first);
+        assert!(first.ends_with("Hello, World!"));
+        assert!(second.starts_with("HTTP/1.1 200 OK"), "unexpected response: {}", second);
+        assert!(second.ends_with("Hello, TLS!"));
+
+        // A plaintext request on the TLS port gets no HTTP answer
+        let mut plain = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        plain.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        plain.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut reply = Vec::new();
+        let _ = plain.read_to_end(&mut reply);
+        assert!(!String::from_utf8_lossy(&reply).contains("HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn test_missing_certificate_is_a_startup_error() {
+        let mut config = ServerConfig::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = 0;
+        config.tls.cert_file = "/nonexistent/cert.pem".to_string();
+        config.tls.key_file = "/nonexistent/key.pem".to_string();
+        assert!(matches!(HttpServer::from_config(config), Err(ServerError::TlsError(_))));
+    }
+
+    #[test]
+    fn test_reload_certificates_applies_to_new_connections() {
+        let port = 9349;
+        let (cert_file, key_file, old_cert) = write_self_signed_cert("reload");
+        let mut config = ServerConfig::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = port;
+        config.tls.cert_file = cert_file.to_string_lossy().into_owned();
+        config.tls.key_file = key_file.to_string_lossy().into_owned();
+        let server = Arc::new(HttpServer::from_config(config).unwrap());
+        let running = Arc::clone(&server);
+        thread::spawn(move || running.start());
+        wait_for_server(port);
+
+        let mut before_rotation = connect_trusting(port, &old_cert).unwrap();
+
+        // Rotate the files on disk; nothing changes until a reload
+        let (_, _, new_cert) = write_self_signed_cert("reload");
+        assert!(connect_trusting(port, &new_cert).is_err());
+        server.reload_certificates().unwrap();
+
+        // New connections get the new certificate...
+        assert!(connect_trusting(port, &old_cert).is_err());
+        let mut after_rotation = connect_trusting(port, &new_cert).unwrap();
+        after_rotation.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        assert!(read_http_response(&mut after_rotation).starts_with("HTTP/1.1 200 OK"));
+
+        // ...while a connection accepted before the reload keeps working
+        before_rotation.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        assert!(read_http_response(&mut before_rotation).starts_with("HTTP/1.1 200 OK"));
+
+        // The admin endpoint reloads too, for an authenticated caller
+        let login_body = r#"{"username": "admin", "password": "password123"}"#;
+        after_rotation.write_all(format!(
+            "POST /api/login HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            login_body.len(), login_body
+        ).as_bytes()).unwrap();
+        let login = read_http_response(&mut after_rotation);
+        let token = JsonValue::parse(login.split("\r\n\r\n").nth(1).unwrap()).unwrap()
+            .get("token").and_then(JsonValue::as_str).unwrap().to_string();
+        after_rotation.write_all(b"POST /admin/tls/reload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n").unwrap();
+        let unauthorized = read_http_response(&mut after_rotation);
+        after_rotation.write_all(format!(
+            "POST /admin/tls/reload HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\nContent-Length: 0\r\n\r\n", token
+        ).as_bytes()).unwrap();
+        let reloaded = read_http_response(&mut after_rotation);
+        fs::remove_dir_all(cert_file.parent().unwrap()).ok();
+
+        assert!(unauthorized.starts_with("HTTP/1.1 401 Unauthorized"), "unexpected response: {}", unauthorized);
+        assert!(reloaded.starts_with("HTTP/1.1 200 OK"), "unexpected response: {}", reloaded);
+        assert!(reloaded.ends_with(r#"{"status":"reloaded"}"#));
+    }
+}